@@ -1,17 +1,21 @@
 use std::convert::TryFrom;
 use std::fmt::Debug;
+use std::path::Path;
 use std::sync::Arc;
 
 use http::{Method, Request, Response, Uri};
 
 use crate::body::Body;
 use crate::config::{AgentScope, Config, ConfigBuilder, HttpCrateScope, RequestLevelConfig};
+use crate::download;
 use crate::http;
 use crate::middleware::MiddlewareNext;
 use crate::pool::ConnectionPool;
 use crate::resolver::{DefaultResolver, Resolver};
+use crate::run;
 use crate::send_body::AsSendBody;
 use crate::transport::{Connector, DefaultConnector};
+use crate::upgrade::Upgraded;
 use crate::{Error, RequestBuilder, SendBody};
 use crate::{WithBody, WithoutBody};
 
@@ -80,6 +84,9 @@ use crate::{WithBody, WithoutBody};
 /// And if we want separate pools, we can create multiple agents via one of the constructors
 /// (such as [`Agent::new_with_config()`]).
 ///
+/// The opposite, different configurations sharing the same connection pool (and cookie jar),
+/// is achieved with [`Agent::derive()`].
+///
 /// Note that both [`Config::clone()`] and [`Agent::clone()`] are  "cheap" meaning they should not
 /// incur any heap allocation.
 #[derive(Debug, Clone)]
@@ -134,6 +141,59 @@ impl Agent {
         }
     }
 
+    /// Creates a new agent with another config that shares state with this agent.
+    ///
+    /// The new agent uses the same connection pool, connector, resolver and cookie jar
+    /// as `self`. This is useful when different parts of a program need different
+    /// configuration (timeouts, headers, middleware) while talking to the same hosts,
+    /// without opening extra sockets or splitting cookie sessions.
+    ///
+    /// The pool settings ([`max_idle_connections`][crate::config::ConfigBuilder::max_idle_connections],
+    /// [`max_idle_connections_per_host`][crate::config::ConfigBuilder::max_idle_connections_per_host])
+    /// of the new config are ignored since the pool is owned by the original agent.
+    ///
+    /// Pooled connections are only reused for requests with the same scheme, host, proxy
+    /// and TLS config, regardless of which agent opened them.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use ureq::Agent;
+    ///
+    /// let agent = Agent::new_with_defaults();
+    ///
+    /// let impatient = agent.derive(
+    ///     Agent::config_builder()
+    ///         .timeout_global(Some(Duration::from_secs(1)))
+    ///         .build(),
+    /// );
+    ///
+    /// // Connections made by impatient are returned to the pool of agent.
+    /// impatient.get("http://httpbin.org/get").call()?;
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    pub fn derive(&self, config: Config) -> Agent {
+        Agent {
+            config: Arc::new(config),
+            pool: self.pool.clone(),
+            resolver: self.resolver.clone(),
+
+            #[cfg(feature = "cookies")]
+            jar: self.jar.clone(),
+        }
+    }
+
+    /// Creates a new agent with another config that shares the connection pool, but not
+    /// the cookie jar, with this agent.
+    ///
+    /// See [`Agent::derive()`]. The new agent starts with an empty cookie jar.
+    #[cfg(feature = "cookies")]
+    pub fn derive_with_new_cookie_jar(&self, config: Config) -> Agent {
+        Agent {
+            jar: Arc::new(crate::cookies::SharedCookieJar::new()),
+            ..self.derive(config)
+        }
+    }
+
     /// Access the shared cookie jar.
     ///
     /// Used to persist and manipulate the cookies. The jar is shared between
@@ -205,6 +265,173 @@ impl Agent {
         next.handle(request)
     }
 
+    /// Download to a file, resuming a previous partial download.
+    ///
+    /// The data is first written to `<path>.part`, which is renamed to `path` once
+    /// complete. If the download fails, calling this again continues from the
+    /// length of the part file, using a `Range` request. The `ETag` (or `Last-Modified`)
+    /// of the first response is sent as `If-Range`, to make sure the parts are of the same
+    /// content. It's kept in `<path>.part.meta` between calls.
+    ///
+    /// Falls back on downloading everything, if the server doesn't honor the range.
+    ///
+    /// Returns the size of the file.
+    ///
+    /// ```no_run
+    /// let agent = ureq::Agent::new_with_defaults();
+    ///
+    /// // Retry until complete.
+    /// let size = loop {
+    ///     match agent.download("https://example.com/big.iso", "big.iso") {
+    ///         Ok(v) => break v,
+    ///         Err(e) => eprintln!("download failed, resuming: {}", e),
+    ///     }
+    /// };
+    /// ```
+    pub fn download<T>(&self, uri: T, path: impl AsRef<Path>) -> Result<u64, Error>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        let uri = Uri::try_from(uri).map_err(|e| Error::Http(e.into()))?;
+        download::download(self, &uri, path.as_ref())
+    }
+
+    /// Download to a file in `segments` parts, fetched concurrently.
+    ///
+    /// A `HEAD` request first checks the server supports ranges (`Accept-Ranges: bytes`)
+    /// and tells the size (`Content-Length`). The file is then preallocated, and each
+    /// segment is fetched with a `Range` request on a worker thread, over a separate
+    /// pooled connection. A segment that fails is retried from where it stopped.
+    ///
+    /// Like [`Agent::download()`], data is written to `<path>.part`, which is renamed to
+    /// `path` once the total size is verified. A failed download is not resumable, and
+    /// the part file is removed.
+    ///
+    /// Falls back on [`Agent::download()`] if the server doesn't support ranges.
+    ///
+    /// Returns the size of the file.
+    ///
+    /// ```no_run
+    /// let agent = ureq::Agent::new_with_defaults();
+    ///
+    /// let size = agent.download_segmented("https://example.com/big.iso", "big.iso", 4)?;
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    pub fn download_segmented<T>(
+        &self,
+        uri: T,
+        path: impl AsRef<Path>,
+        segments: usize,
+    ) -> Result<u64, Error>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        let uri = Uri::try_from(uri).map_err(|e| Error::Http(e.into()))?;
+        download::download_segmented(self, &uri, path.as_ref(), segments)
+    }
+
+    /// Open a tunnel to `host:port` through the configured proxy.
+    ///
+    /// For an HTTP proxy, this sends a `CONNECT` request and hands over the connection
+    /// once the proxy answers `200`. SOCKS proxies (feature **socks-proxy**) work as
+    /// well. The returned stream is raw TCP to the target, with nothing added by ureq.
+    ///
+    /// Fails if no proxy is configured.
+    ///
+    /// ```no_run
+    /// use std::io::{Read, Write};
+    /// use ureq::{Agent, Proxy};
+    ///
+    /// let agent: Agent = Agent::config_builder()
+    ///     .proxy(Some(Proxy::new("http://corporate.proxy:3128")?))
+    ///     .build()
+    ///     .into();
+    ///
+    /// let mut stream = agent.tunnel("db.internal", 5432)?;
+    /// stream.write_all(b"hello")?;
+    /// # Ok::<_, Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn tunnel(&self, host: &str, port: u16) -> Result<Upgraded, Error> {
+        if self.config.proxy.is_none() {
+            return Err(Error::ConnectProxyFailed("no proxy configured".to_string()));
+        }
+
+        // IPv6 addresses must be in brackets.
+        let authority = if host.contains(':') && !host.starts_with('[') {
+            format!("[{}]:{}", host, port)
+        } else {
+            format!("{}:{}", host, port)
+        };
+
+        let uri: Uri = format!("http://{}/", authority)
+            .parse()
+            .map_err(|_| Error::BadUri(authority))?;
+
+        let connection = run::tunnel(self, &uri)?;
+
+        Ok(Upgraded::new(connection))
+    }
+
+    /// Open a [`WebSocket`](crate::WebSocket) using this agent.
+    ///
+    /// The uri can use the `ws://` and `wss://` schemes, as well as `http://` and
+    /// `https://`. The handshake goes via the agent, which means proxy, TLS config,
+    /// cookies and middleware apply.
+    ///
+    /// ```no_run
+    /// use ureq::Message;
+    ///
+    /// let agent = ureq::Agent::new_with_defaults();
+    ///
+    /// let mut ws = agent.websocket("wss://echo.websocket.org/")
+    ///     .header("authorization", "Bearer secret")
+    ///     .connect()?;
+    ///
+    /// ws.send(Message::Text("hello".into()))?;
+    /// let reply = ws.read()?;
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    #[cfg(feature = "websocket")]
+    #[must_use]
+    pub fn websocket<T>(&self, uri: T) -> crate::WebSocketBuilder
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        crate::WebSocketBuilder::new(self.clone(), uri)
+    }
+
+    /// Open a reconnecting [`EventSource`](crate::EventSource) using this agent.
+    ///
+    /// Requests a `text/event-stream` and reconnects when it ends, sending the
+    /// `Last-Event-ID` header and waiting for the reconnection time set by the server.
+    /// To read a single response as events, see
+    /// [`Body::into_event_stream()`](crate::Body::into_event_stream).
+    ///
+    /// ```no_run
+    /// let agent = ureq::Agent::new_with_defaults();
+    ///
+    /// let events = agent.event_source("http://my-server.test/events")
+    ///     .header("authorization", "Bearer secret")
+    ///     .connect()?;
+    ///
+    /// for event in events {
+    ///     let event = event?;
+    ///     println!("{}: {}", event.event, event.data);
+    /// }
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    #[must_use]
+    pub fn event_source<T>(&self, uri: T) -> crate::EventSourceBuilder
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        crate::EventSourceBuilder::new(self.clone(), uri)
+    }
+
     /// Get the config for this agent.
     pub fn config(&self) -> &Config {
         &self.config
@@ -271,9 +498,9 @@ impl From<Config> for Agent {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "_test"))]
 impl Agent {
-    pub fn pool_count(&self) -> usize {
+    pub(crate) fn pool_count(&self) -> usize {
         self.pool.pool_count()
     }
 }
@@ -288,4 +515,49 @@ mod test {
         let a = Agent::new_with_defaults();
         assert_no_alloc(|| a.clone());
     }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn derived_agent_shares_pool() {
+        use crate::test::init_test_log;
+        use crate::transport::set_handler;
+
+        init_test_log();
+        set_handler("/derived", 200, &[("content-length", "2")], b"ok");
+
+        let agent = Agent::new_with_defaults();
+        let derived = agent.derive(Config::builder().https_only(true).build());
+
+        let mut res = derived.get("https://example.test/derived").call().unwrap();
+        res.body_mut().read_to_string().unwrap();
+
+        assert_eq!(agent.pool_count(), 1);
+        assert_eq!(derived.pool_count(), 1);
+
+        // The derived config applies to the derived agent only.
+        let err = derived
+            .get("http://example.test/derived")
+            .call()
+            .unwrap_err();
+        assert!(matches!(err, Error::RequireHttpsOnly(_)));
+    }
+
+    #[test]
+    #[cfg(feature = "cookies")]
+    fn derived_agent_shares_cookie_jar() {
+        use crate::Cookie;
+
+        let agent = Agent::new_with_defaults();
+        let derived = agent.derive(Config::default());
+        let separate = agent.derive_with_new_cookie_jar(Config::default());
+
+        let uri = Uri::from_static("http://cookie.test/");
+        let mut jar = agent.cookie_jar_lock();
+        jar.insert(Cookie::parse("a=1", &uri).unwrap(), &uri)
+            .unwrap();
+        jar.release();
+
+        assert_eq!(derived.cookie_jar_lock().iter().count(), 1);
+        assert_eq!(separate.cookie_jar_lock().iter().count(), 0);
+    }
 }
@@ -3,7 +3,8 @@ use std::sync::Arc;
 
 use ureq_proto::BodyMode;
 
-use super::{Body, BodyDataSource, ContentEncoding, ResponseInfo};
+use super::{Body, BodyDataSource, ContentDecoding, ResponseInfo};
+use super::{MAX_BODY_SIZE, MAX_DECOMPRESSED_SIZE};
 
 /// Builder for creating a response body.
 ///
@@ -42,10 +43,13 @@ impl BodyBuilder {
     pub(crate) fn new() -> Self {
         BodyBuilder {
             info: ResponseInfo {
-                content_encoding: ContentEncoding::None,
+                content_decoding: ContentDecoding::Decode(vec![]),
                 mime_type: None,
                 charset: None,
+                boundary: None,
                 body_mode: BodyMode::NoBody,
+                max_body_size: MAX_BODY_SIZE,
+                max_decompressed_size: MAX_DECOMPRESSED_SIZE,
             },
             limit: None,
         }
@@ -84,6 +88,23 @@ impl BodyBuilder {
         self
     }
 
+    /// Set the multipart boundary of the body.
+    ///
+    /// **This does not set any HTTP headers. Affects Body decoding.**
+    ///
+    /// ```
+    /// use ureq::Body;
+    ///
+    /// let body = Body::builder()
+    ///     .mime_type("multipart/mixed")
+    ///     .boundary("3d6b6a416f9b5")
+    ///     .data("--3d6b6a416f9b5\r\n\r\nHello\r\n--3d6b6a416f9b5--");
+    /// ```
+    pub fn boundary(mut self, boundary: impl Into<String>) -> Self {
+        self.info.boundary = Some(boundary.into());
+        self
+    }
+
     /// Limit how much data is to be released from the body.
     ///
     /// **This does not set any HTTP headers. Affects Body decoding.**
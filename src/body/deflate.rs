@@ -0,0 +1,112 @@
+use std::io::{self, Cursor, Read};
+
+use flate2::read::{DeflateDecoder as RawDecoder, ZlibDecoder};
+
+use crate::Error;
+
+type Prefixed<R> = io::Chain<Cursor<Vec<u8>>, R>;
+
+/// Decoder for `Content-Encoding: deflate`.
+///
+/// RFC 9110 says deflate is a zlib stream, but some servers send raw deflate
+/// data. We look at the first two bytes to pick the format.
+pub(crate) enum DeflateDecoder<R> {
+    Detect(Option<R>),
+    Zlib(Box<ZlibDecoder<Prefixed<R>>>),
+    Raw(Box<RawDecoder<Prefixed<R>>>),
+}
+
+impl<R: io::Read> DeflateDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        DeflateDecoder::Detect(Some(reader))
+    }
+
+    fn detect(&mut self) -> io::Result<()> {
+        let DeflateDecoder::Detect(reader) = self else {
+            return Ok(());
+        };
+        let mut reader = reader.take().expect("deflate reader");
+
+        let mut prefix = Vec::with_capacity(2);
+        while prefix.len() < 2 {
+            let mut byte = [0];
+            if reader.read(&mut byte)? == 0 {
+                break;
+            }
+            prefix.push(byte[0]);
+        }
+
+        let is_zlib = is_zlib_header(&prefix);
+        let reader = Cursor::new(prefix).chain(reader);
+
+        *self = if is_zlib {
+            DeflateDecoder::Zlib(Box::new(ZlibDecoder::new(reader)))
+        } else {
+            debug!("Deflate body without zlib header");
+            DeflateDecoder::Raw(Box::new(RawDecoder::new(reader)))
+        };
+
+        Ok(())
+    }
+}
+
+fn is_zlib_header(prefix: &[u8]) -> bool {
+    let [cmf, flg] = prefix else {
+        return false;
+    };
+    // Compression method 8 (deflate) and a header checksum divisible by 31.
+    cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0
+}
+
+impl<R: io::Read> io::Read for DeflateDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.detect()
+            .map_err(|e| Error::Decompress("deflate", e).into_io())?;
+
+        match self {
+            DeflateDecoder::Detect(_) => unreachable!("deflate format detected"),
+            DeflateDecoder::Zlib(v) => v.read(buf),
+            DeflateDecoder::Raw(v) => v.read(buf),
+        }
+        .map_err(|e| Error::Decompress("deflate", e).into_io())
+    }
+}
+
+#[cfg(all(test, feature = "_test"))]
+mod test {
+    use std::io::Write;
+
+    use flate2::write::{DeflateEncoder, ZlibEncoder};
+    use flate2::Compression;
+
+    use crate::test::init_test_log;
+    use crate::transport::set_handler;
+
+    #[test]
+    fn deflate_zlib() {
+        init_test_log();
+
+        let mut enc = ZlibEncoder::new(vec![], Compression::default());
+        enc.write_all(b"hello zlib").unwrap();
+        let body = enc.finish().unwrap();
+
+        set_handler("/zlib", 200, &[("content-encoding", "deflate")], &body);
+
+        let mut res = crate::get("https://my.test/zlib").call().unwrap();
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "hello zlib");
+    }
+
+    #[test]
+    fn deflate_raw() {
+        init_test_log();
+
+        let mut enc = DeflateEncoder::new(vec![], Compression::default());
+        enc.write_all(b"hello raw").unwrap();
+        let body = enc.finish().unwrap();
+
+        set_handler("/raw", 200, &[("content-encoding", "deflate")], &body);
+
+        let mut res = crate::get("https://my.test/raw").call().unwrap();
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "hello raw");
+    }
+}
@@ -6,6 +6,7 @@ pub(crate) struct LimitReader<R> {
     reader: R,
     limit: u64,
     left: u64,
+    error: fn(u64) -> Error,
 }
 
 impl<R> LimitReader<R> {
@@ -14,6 +15,15 @@ impl<R> LimitReader<R> {
             reader,
             limit,
             left: limit,
+            error: Error::BodyExceedsLimit,
+        }
+    }
+
+    /// Limit the output of a content decoder.
+    pub fn new_decompressed(reader: R, limit: u64) -> Self {
+        LimitReader {
+            error: Error::DecompressedBodyExceedsLimit,
+            ..LimitReader::new(reader, limit)
         }
     }
 }
@@ -21,7 +31,7 @@ impl<R> LimitReader<R> {
 impl<R: io::Read> io::Read for LimitReader<R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         if self.left == 0 {
-            return Err(Error::BodyExceedsLimit(self.limit).into_io());
+            return Err((self.error)(self.limit).into_io());
         }
 
         // The max buffer size is usize, which may be 32 bit.
@@ -39,9 +49,10 @@ impl<R: io::Read> io::Read for LimitReader<R> {
 mod test {
     use std::io;
 
+    use crate::config::Config;
     use crate::test::init_test_log;
     use crate::transport::set_handler;
-    use crate::Error;
+    use crate::{Agent, Error};
 
     #[test]
     fn short_read() {
@@ -67,4 +78,62 @@ mod test {
         println!("{:?}", err);
         assert!(matches!(err, Error::BodyExceedsLimit(3)));
     }
+
+    #[test]
+    fn max_body_size() {
+        init_test_log();
+        set_handler("/get", 200, &[("content-length", "5")], b"hello");
+
+        let agent: Agent = Config::builder().max_body_size(3).build().into();
+        let mut res = agent.get("https://my.test/get").call().unwrap();
+        let err = res.body_mut().read_to_vec().unwrap_err();
+        assert!(matches!(err, Error::BodyExceedsLimit(3)));
+
+        let mut res = agent
+            .get("https://my.test/get")
+            .config()
+            .max_body_size(10)
+            .build()
+            .call()
+            .unwrap();
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "hello");
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn max_decompressed_size() {
+        use std::io::{Read, Write};
+
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+
+        init_test_log();
+
+        let mut enc = GzEncoder::new(vec![], Compression::best());
+        enc.write_all(&[0; 100_000]).unwrap();
+        let gz = enc.finish().unwrap();
+
+        set_handler("/bomb", 200, &[("content-encoding", "gzip")], &gz);
+
+        let agent: Agent = Config::builder().max_decompressed_size(1000).build().into();
+
+        let mut res = agent.get("https://my.test/bomb").call().unwrap();
+        let err = res.body_mut().read_to_vec().unwrap_err();
+        assert!(matches!(err, Error::DecompressedBodyExceedsLimit(1000)));
+
+        // Readers are only limited when configured.
+        let mut res = agent.get("https://my.test/bomb").call().unwrap();
+        let mut body = vec![];
+        res.body_mut().as_reader().read_to_end(&mut body).unwrap();
+        assert_eq!(body.len(), 100_000);
+
+        let mut res = agent.get("https://my.test/bomb").call().unwrap();
+        let body = res
+            .body_mut()
+            .with_config()
+            .decompressed_limit(200_000)
+            .read_to_vec()
+            .unwrap();
+        assert_eq!(body.len(), 100_000);
+    }
 }
@@ -1,12 +1,18 @@
 use std::fmt;
 use std::io;
+use std::mem;
 use std::sync::Arc;
 
 pub use build::BodyBuilder;
+pub use multipart::{BodyPart, BodyParts};
+use once_cell::sync::OnceCell;
 use ureq_proto::BodyMode;
 
+use crate::config::{Config, ContentDecoderHandler};
 use crate::http;
 use crate::run::BodyHandler;
+use crate::sse::EventStream;
+use crate::upgrade::Upgraded;
 use crate::Error;
 
 use self::limit::LimitReader;
@@ -15,6 +21,7 @@ use self::lossy::LossyUtf8Reader;
 mod build;
 mod limit;
 mod lossy;
+mod multipart;
 
 #[cfg(feature = "charset")]
 mod charset;
@@ -22,11 +29,20 @@ mod charset;
 #[cfg(feature = "gzip")]
 mod gzip;
 
+#[cfg(feature = "gzip")]
+mod deflate;
+
 #[cfg(feature = "brotli")]
 mod brotli;
 
+#[cfg(feature = "zstd")]
+mod zstd;
+
 /// Default max body size for read_to_string() and read_to_vec().
-const MAX_BODY_SIZE: u64 = 10 * 1024 * 1024;
+pub(crate) const MAX_BODY_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Default max size of a body after decompression.
+pub(crate) const MAX_DECOMPRESSED_SIZE: u64 = 100 * 1024 * 1024;
 
 /// A response body returned as [`http::Response<Body>`].
 ///
@@ -57,14 +73,22 @@ pub struct Body {
 enum BodyDataSource {
     Handler(BodyHandler),
     Reader(Box<dyn io::Read + Send + Sync>),
+    /// The body with custom content decoders applied, and the trailers once read.
+    Decoded(
+        Box<dyn io::Read + Send + Sync>,
+        Arc<OnceCell<http::HeaderMap>>,
+    ),
 }
 
 #[derive(Clone)]
 pub(crate) struct ResponseInfo {
-    content_encoding: ContentEncoding,
+    content_decoding: ContentDecoding,
     mime_type: Option<String>,
     charset: Option<String>,
+    boundary: Option<String>,
     body_mode: BodyMode,
+    max_body_size: u64,
+    max_decompressed_size: u64,
 }
 
 impl Body {
@@ -125,6 +149,171 @@ impl Body {
         self.info.charset.as_deref()
     }
 
+    /// The content encodings decoded when reading the body.
+    ///
+    /// The encodings are listed in the order they are decoded, which is the reverse
+    /// of the `Content-Encoding` header. For the below header, we would get
+    /// `["br", "gzip"]`:
+    ///
+    /// ```text
+    ///     Content-Encoding: gzip, br
+    /// ```
+    ///
+    /// An encoding that isn't supported (or whose feature flag is not enabled) is
+    /// passed through undecoded, in which case this is empty. If such an encoding
+    /// is stacked with others, reading the body fails with
+    /// [`Error::UnsupportedContentEncoding`]. More encodings can be supported with
+    /// [`ConfigBuilder::content_decoder()`](crate::config::ConfigBuilder::content_decoder).
+    ///
+    /// This is also empty when decoding is turned off with
+    /// [`ConfigBuilder::decode_content()`](crate::config::ConfigBuilder::decode_content).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// let res = ureq::get("https://httpbin.org/gzip")
+    ///     .call()?;
+    ///
+    /// let decoded: Vec<_> = res.body().content_decoding().collect();
+    /// assert_eq!(decoded, ["gzip"]);
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    pub fn content_decoding(&self) -> impl Iterator<Item = &str> + '_ {
+        let encodings = match &self.info.content_decoding {
+            ContentDecoding::Decode(v) => &v[..],
+            ContentDecoding::Unsupported(_) => &[],
+        };
+        encodings.iter().map(ContentEncoding::as_str)
+    }
+
+    /// The trailers sent after a chunked body.
+    ///
+    /// A response using `Transfer-Encoding: chunked` can send header fields after the
+    /// body, such as `grpc-status`. These are available once the body has been read to
+    /// the end, at which point a chunked body without trailers gives an empty map.
+    ///
+    /// Gives `None` before the body is read to the end, or if the body is not chunked.
+    /// The size of the trailers is limited by
+    /// [`max_response_header_size`](crate::config::ConfigBuilder::max_response_header_size).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// let mut res = ureq::get("http://httpbin.org/stream/2")
+    ///     .call()?;
+    ///
+    /// let body = res.body_mut().read_to_string()?;
+    ///
+    /// if let Some(trailers) = res.body().trailers() {
+    ///     println!("grpc-status: {:?}", trailers.get("grpc-status"));
+    /// }
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    pub fn trailers(&self) -> Option<&http::HeaderMap> {
+        match &self.source {
+            BodyDataSource::Handler(v) => v.trailers(),
+            BodyDataSource::Reader(_) => None,
+            BodyDataSource::Decoded(_, v) => v.get(),
+        }
+    }
+
+    /// The parts of a `multipart/*` body, such as `multipart/byteranges` or
+    /// `multipart/mixed`.
+    ///
+    /// The boundary is taken from the `content-type` header. Each part has headers and
+    /// is read as a stream. Moving to the next part skips whatever is left of the
+    /// previous one.
+    ///
+    /// Parts are read without limit. Fails with [`Error::Multipart`] if the body is not
+    /// multipart.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::io::Read;
+    ///
+    /// let mut res = ureq::get("http://httpbin.org/range/100")
+    ///     .header("range", "bytes=0-9,50-59")
+    ///     .call()?;
+    ///
+    /// for part in res.body_mut().as_multipart()? {
+    ///     let mut part = part?;
+    ///     println!("{:?}", part.headers().get("content-range"));
+    ///
+    ///     let mut data = vec![];
+    ///     part.read_to_end(&mut data)?;
+    /// }
+    /// # Ok::<_, Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn as_multipart(&mut self) -> Result<BodyParts<'_>, Error> {
+        let boundary = self.info.multipart_boundary()?.to_string();
+        Ok(BodyParts::new(self.as_reader(), &boundary))
+    }
+
+    /// Consume self and read the parts of a `multipart/*` body.
+    ///
+    /// Like [`Body::as_multipart()`], but the parts have lifetime `'static`.
+    pub fn into_multipart(self) -> Result<BodyParts<'static>, Error> {
+        let boundary = self.info.multipart_boundary()?.to_string();
+        Ok(BodyParts::new(self.into_reader(), &boundary))
+    }
+
+    /// Consume self and read the body as `text/event-stream`, also known as
+    /// Server-Sent Events.
+    ///
+    /// The events are parsed as the body is read, which means each event is available
+    /// as soon as the server sends it. Data split over several `data` lines is joined
+    /// with line breaks. Each event is limited by
+    /// [`max_body_size`](crate::config::ConfigBuilder::max_body_size).
+    ///
+    /// The stream ends with the body. To reconnect, use [`Agent::event_source()`](crate::Agent::event_source).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// let res = ureq::get("http://my-server.test/events")
+    ///     .header("accept", "text/event-stream")
+    ///     .call()?;
+    ///
+    /// for event in res.into_body().into_event_stream() {
+    ///     let event = event?;
+    ///     println!("{}: {}", event.event, event.data);
+    /// }
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    pub fn into_event_stream(self) -> EventStream {
+        let limit = self.info.max_body_size;
+        EventStream::new(self.into_reader(), limit)
+    }
+
+    /// Take over the connection of a `101 Switching Protocols` response.
+    ///
+    /// The request must ask for the upgrade itself, by setting the `connection` and
+    /// `upgrade` headers. The returned [`Upgraded`] reads and writes directly on the
+    /// connection, which is never returned to the connection pool.
+    ///
+    /// Fails with [`Error::NotUpgraded`] for any other response.
+    ///
+    /// ```no_run
+    /// use std::io::Write;
+    ///
+    /// let res = ureq::get("http://my-server.test/chat")
+    ///     .header("connection", "upgrade")
+    ///     .header("upgrade", "my-protocol")
+    ///     .call()?;
+    ///
+    /// let mut stream = res.into_body().into_upgraded()?;
+    /// stream.write_all(b"hello")?;
+    /// # Ok::<_, Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn into_upgraded(self) -> Result<Upgraded, Error> {
+        match self.source {
+            BodyDataSource::Handler(mut v) => v.take_upgraded().map(Upgraded::new),
+            BodyDataSource::Reader(_) | BodyDataSource::Decoded(..) => None,
+        }
+        .ok_or(Error::NotUpgraded)
+    }
+
     /// Handle this body as a shared `impl Read` of the body.
     ///
     /// This is the regular API which goes via [`http::Response::body_mut()`] to get a
@@ -178,7 +367,10 @@ impl Body {
 
     /// Read the response as a string.
     ///
-    /// * Response is limited to 10MB
+    /// * Response is limited by [`max_body_size`](crate::config::ConfigBuilder::max_body_size),
+    ///   which defaults to 10MB.
+    /// * Decompressed data is limited by
+    ///   [`max_decompressed_size`](crate::config::ConfigBuilder::max_decompressed_size).
     /// * Replaces incorrect utf-8 chars to `?`
     ///
     /// To change these defaults use [`Body::with_config()`].
@@ -192,15 +384,21 @@ impl Body {
     /// # Ok::<_, ureq::Error>(())
     /// ```
     pub fn read_to_string(&mut self) -> Result<String, Error> {
+        let limit = self.info.max_body_size;
+        let decompressed_limit = self.info.max_decompressed_size;
         self.with_config()
-            .limit(MAX_BODY_SIZE)
+            .limit(limit)
+            .decompressed_limit(decompressed_limit)
             .lossy_utf8(true)
             .read_to_string()
     }
 
     /// Read the response to a vec.
     ///
-    /// * Response is limited to 10MB.
+    /// * Response is limited by [`max_body_size`](crate::config::ConfigBuilder::max_body_size),
+    ///   which defaults to 10MB.
+    /// * Decompressed data is limited by
+    ///   [`max_decompressed_size`](crate::config::ConfigBuilder::max_decompressed_size).
     ///
     /// To change this default use [`Body::with_config()`].
     /// ```
@@ -212,15 +410,20 @@ impl Body {
     /// # Ok::<_, ureq::Error>(())
     /// ```
     pub fn read_to_vec(&mut self) -> Result<Vec<u8>, Error> {
+        let limit = self.info.max_body_size;
+        let decompressed_limit = self.info.max_decompressed_size;
         self.with_config()
-            //
-            .limit(MAX_BODY_SIZE)
+            .limit(limit)
+            .decompressed_limit(decompressed_limit)
             .read_to_vec()
     }
 
     /// Read the response from JSON.
     ///
-    /// * Response is limited to 10MB.
+    /// * Response is limited by [`max_body_size`](crate::config::ConfigBuilder::max_body_size),
+    ///   which defaults to 10MB.
+    /// * Decompressed data is limited by
+    ///   [`max_decompressed_size`](crate::config::ConfigBuilder::max_decompressed_size).
     ///
     /// To change this default use [`Body::as_reader()`] and deserialize JSON manually.
     ///
@@ -250,7 +453,13 @@ impl Body {
     /// ```
     #[cfg(feature = "json")]
     pub fn read_json<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, Error> {
-        let reader = self.with_config().limit(MAX_BODY_SIZE).reader();
+        let limit = self.info.max_body_size;
+        let decompressed_limit = self.info.max_decompressed_size;
+        let reader = self
+            .with_config()
+            .limit(limit)
+            .decompressed_limit(decompressed_limit)
+            .reader();
         let value: T = serde_json::from_reader(reader)?;
         Ok(value)
     }
@@ -273,8 +482,8 @@ impl Body {
     /// # Ok::<_, ureq::Error>(())
     /// ```
     pub fn with_config(&mut self) -> BodyWithConfig {
-        let handler = (&mut self.source).into();
-        BodyWithConfig::new(handler, self.info.clone())
+        let source = BodySource::Shared(&mut self.source);
+        BodyWithConfig::new(source, self.info.clone())
     }
 
     /// Consume self and read the body with configuration.
@@ -297,8 +506,8 @@ impl Body {
     /// # Ok::<_, ureq::Error>(())
     /// ```
     pub fn into_with_config(self) -> BodyWithConfig<'static> {
-        let handler = self.source.into();
-        BodyWithConfig::new(handler, self.info.clone())
+        let source = BodySource::Owned(self.source);
+        BodyWithConfig::new(source, self.info.clone())
     }
 }
 
@@ -310,18 +519,20 @@ impl Body {
 /// * [Body::into_with_config()]
 ///
 pub struct BodyWithConfig<'a> {
-    handler: BodySourceRef<'a>,
+    source: BodySource<'a>,
     info: Arc<ResponseInfo>,
     limit: u64,
+    decompressed_limit: u64,
     lossy_utf8: bool,
 }
 
 impl<'a> BodyWithConfig<'a> {
-    fn new(handler: BodySourceRef<'a>, info: Arc<ResponseInfo>) -> Self {
+    fn new(source: BodySource<'a>, info: Arc<ResponseInfo>) -> Self {
         BodyWithConfig {
-            handler,
+            source,
             info,
             limit: u64::MAX,
+            decompressed_limit: u64::MAX,
             lossy_utf8: false,
         }
     }
@@ -337,6 +548,17 @@ impl<'a> BodyWithConfig<'a> {
         self
     }
 
+    /// Limit the response body after decompression.
+    ///
+    /// Controls how many bytes a gzip or brotli decoder may produce before throwing
+    /// an error. This is used to ensure a small compressed body can't exhaust RAM.
+    ///
+    /// The default limit is `u64::MAX` (unlimited).
+    pub fn decompressed_limit(mut self, value: u64) -> Self {
+        self.decompressed_limit = value;
+        self
+    }
+
     /// Replace invalid utf-8 chars.
     ///
     /// `true` means that broken utf-8 characters are replaced by a question mark `?`
@@ -350,10 +572,25 @@ impl<'a> BodyWithConfig<'a> {
     }
 
     fn do_build(self) -> BodyReader<'a> {
+        let (limit, decompressed_limit) = (self.limit, self.decompressed_limit);
+
+        let source = match self.source {
+            BodySource::Shared(v) => {
+                v.decode_custom(&self.info, limit, decompressed_limit);
+                v.into()
+            }
+            BodySource::Owned(mut v) => {
+                v.decode_custom(&self.info, limit, decompressed_limit);
+                v.into()
+            }
+        };
+
         BodyReader::new(
-            LimitReader::new(self.handler, self.limit),
+            source,
+            self.limit,
             &self.info,
             self.info.body_mode,
+            self.decompressed_limit,
             self.lossy_utf8,
         )
     }
@@ -418,36 +655,69 @@ impl<'a> BodyWithConfig<'a> {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// A content encoding we can decode.
+#[derive(Debug, Clone)]
 enum ContentEncoding {
-    None,
+    #[cfg(feature = "gzip")]
     Gzip,
+    #[cfg(feature = "gzip")]
+    Deflate,
+    #[cfg(feature = "brotli")]
     Brotli,
-    Unknown,
+    #[cfg(feature = "zstd")]
+    Zstd,
+    /// Decoder registered with [`ConfigBuilder::content_decoder()`](crate::config::ConfigBuilder::content_decoder).
+    Custom(String, ContentDecoderHandler),
+}
+
+/// The decoding steps for the `Content-Encoding` of a response.
+#[derive(Debug, Clone)]
+enum ContentDecoding {
+    /// Decoders in the order they are applied, which is the reverse of the header.
+    Decode(Vec<ContentEncoding>),
+    /// Stacked encodings where one layer can't be decoded.
+    Unsupported(String),
 }
 
 impl ResponseInfo {
-    pub fn new(headers: &http::HeaderMap, body_mode: BodyMode) -> Self {
-        let content_encoding = headers
-            .get("content-encoding")
-            .and_then(|v| v.to_str().ok())
-            .map(ContentEncoding::from)
-            .unwrap_or(ContentEncoding::None);
+    pub fn new(headers: &http::HeaderMap, body_mode: BodyMode, config: &Config) -> Self {
+        let content_decoding = ContentDecoding::new(headers, config);
 
-        let (mime_type, charset) = headers
+        let (mime_type, charset, boundary) = headers
             .get("content-type")
             .and_then(|v| v.to_str().ok())
             .map(split_content_type)
-            .unwrap_or((None, None));
+            .unwrap_or((None, None, None));
 
         ResponseInfo {
-            content_encoding,
+            content_decoding,
             mime_type,
             charset,
+            boundary,
             body_mode,
+            max_body_size: config.max_body_size,
+            max_decompressed_size: config.max_decompressed_size,
         }
     }
 
+    /// The boundary, if the mime type is `multipart/*`.
+    fn multipart_boundary(&self) -> Result<&str, Error> {
+        let is_multipart = self
+            .mime_type
+            .as_deref()
+            .map(|s| s.trim().to_ascii_lowercase().starts_with("multipart/"))
+            .unwrap_or(false);
+
+        if !is_multipart {
+            return Err(Error::Multipart("body is not multipart"));
+        }
+
+        self.boundary
+            .as_deref()
+            .filter(|s| !s.is_empty())
+            .ok_or(Error::Multipart("content-type has no boundary"))
+    }
+
     /// Whether the mime type indicats text.
     fn is_text(&self) -> bool {
         self.mime_type
@@ -457,32 +727,44 @@ impl ResponseInfo {
     }
 }
 
-fn split_content_type(content_type: &str) -> (Option<String>, Option<String>) {
+fn split_content_type(content_type: &str) -> (Option<String>, Option<String>, Option<String>) {
     // Content-Type: text/plain; charset=iso-8859-1
+    // Content-Type: multipart/byteranges; boundary="3d6b6a416f9b5"
     let mut split = content_type.split(';');
 
     let Some(mime_type) = split.next() else {
-        return (None, None);
+        return (None, None, None);
     };
 
     let mut charset = None;
+    let mut boundary = None;
 
-    for maybe_charset in split {
-        let maybe_charset = maybe_charset.trim();
-        if let Some(s) = maybe_charset.strip_prefix("charset=") {
+    for param in split {
+        // Parameter names are case-insensitive.
+        let Some((name, s)) = param.trim().split_once('=') else {
+            continue;
+        };
+        if name.eq_ignore_ascii_case("charset") {
             charset = Some(s.to_string());
+        } else if name.eq_ignore_ascii_case("boundary") {
+            let s = s
+                .strip_prefix('"')
+                .and_then(|s| s.strip_suffix('"'))
+                .unwrap_or(s);
+            boundary = Some(s.to_string());
         }
     }
 
-    (Some(mime_type.to_string()), charset)
+    (Some(mime_type.to_string()), charset, boundary)
 }
 
 /// A reader of the response data.
 ///
 /// 1. If `Transfer-Encoding: chunked`, the returned reader will unchunk it
 ///    and any `Content-Length` header is ignored.
-/// 2. If `Content-Encoding: gzip` (or `br`) and the corresponding feature
-///    flag is enabled (**gzip** and **brotli**), decompresses the body data.
+/// 2. If `Content-Encoding: gzip` (or `deflate`, `br`, `zstd`) and the corresponding
+///    feature flag is enabled (**gzip**, **brotli** and **zstd**), decompresses the
+///    body data.
 /// 3. Given a header like `Content-Type: text/plain; charset=ISO-8859-1`
 ///    and the **charset** feature enabled, will translate the body to utf-8.
 ///    This mechanic need two components a mime-type starting `text/` and
@@ -491,6 +773,7 @@ fn split_content_type(content_type: &str) -> (Option<String>, Option<String>) {
 ///    length regardless of how many bytes the server sends.
 /// 5. If no length header, the reader is until server stream end.
 /// 6. The limit in the body method used to obtain the reader.
+/// 7. The decompressed limit in the body method used to obtain the reader.
 ///
 /// Note: The reader is also limited by the [`Body::as_reader`] and
 /// [`Body::into_reader`] calls. If that limit is set very high, a malicious
@@ -526,33 +809,25 @@ pub struct BodyReader<'a> {
 
 impl<'a> BodyReader<'a> {
     fn new(
-        reader: LimitReader<BodySourceRef<'a>>,
+        source: BodySourceRef<'a>,
+        limit: u64,
         info: &ResponseInfo,
         incoming_body_mode: BodyMode,
+        decompressed_limit: u64,
         lossy_utf8: bool,
     ) -> BodyReader<'a> {
         // This is outgoing body_mode in case we are using the BodyReader as a send body
         // in a proxy situation.
         let mut outgoing_body_mode = incoming_body_mode;
 
-        let reader = match info.content_encoding {
-            ContentEncoding::None | ContentEncoding::Unknown => ContentDecoder::PassThrough(reader),
-            #[cfg(feature = "gzip")]
-            ContentEncoding::Gzip => {
-                debug!("Decoding gzip");
-                outgoing_body_mode = BodyMode::Chunked;
-                ContentDecoder::Gzip(Box::new(gzip::GzipDecoder::new(reader)))
+        let reader = match &info.content_decoding {
+            ContentDecoding::Decode(encodings) => {
+                if !encodings.is_empty() {
+                    outgoing_body_mode = BodyMode::Chunked;
+                }
+                ContentDecoder::new(source, limit, encodings, decompressed_limit)
             }
-            #[cfg(not(feature = "gzip"))]
-            ContentEncoding::Gzip => ContentDecoder::PassThrough(reader),
-            #[cfg(feature = "brotli")]
-            ContentEncoding::Brotli => {
-                debug!("Decoding brotli");
-                outgoing_body_mode = BodyMode::Chunked;
-                ContentDecoder::Brotli(Box::new(brotli::BrotliDecoder::new(reader)))
-            }
-            #[cfg(not(feature = "brotli"))]
-            ContentEncoding::Brotli => ContentDecoder::PassThrough(reader),
+            ContentDecoding::Unsupported(v) => ContentDecoder::Unsupported(v.clone()),
         };
 
         let reader = if info.is_text() {
@@ -650,21 +925,105 @@ impl<R: io::Read> io::Read for CharsetDecoder<R> {
     }
 }
 
+/// Stack of content decoders, where each layer reads from the one before.
 enum ContentDecoder<R: io::Read> {
     #[cfg(feature = "gzip")]
-    Gzip(Box<gzip::GzipDecoder<R>>),
+    Gzip(Box<LimitReader<gzip::GzipDecoder<ContentDecoder<R>>>>),
+    #[cfg(feature = "gzip")]
+    Deflate(Box<LimitReader<deflate::DeflateDecoder<ContentDecoder<R>>>>),
     #[cfg(feature = "brotli")]
-    Brotli(Box<brotli::BrotliDecoder<R>>),
+    Brotli(Box<LimitReader<brotli::BrotliDecoder<ContentDecoder<R>>>>),
+    #[cfg(feature = "zstd")]
+    Zstd(Box<LimitReader<zstd::ZstdDecoder<ContentDecoder<R>>>>),
+    Custom(LimitReader<Box<dyn io::Read + Send + Sync>>),
+    Unsupported(String),
     PassThrough(R),
 }
 
+impl<'a> ContentDecoder<LimitReader<BodySourceRef<'a>>> {
+    fn new(
+        source: BodySourceRef<'a>,
+        limit: u64,
+        encodings: &[ContentEncoding],
+        decompressed_limit: u64,
+    ) -> Self {
+        // The body source has the custom decoders and limits applied already.
+        if has_custom(encodings) {
+            return ContentDecoder::PassThrough(LimitReader::new(source, u64::MAX));
+        }
+
+        let reader = ContentDecoder::PassThrough(LimitReader::new(source, limit));
+        encodings.iter().fold(reader, |reader, encoding| {
+            reader.decode(encoding, decompressed_limit)
+        })
+    }
+}
+
+impl<R: io::Read + Send + Sync + 'static> ContentDecoder<R> {
+    /// Add a decoder layer on top of this one, including custom decoders.
+    fn decode_owned(self, encoding: &ContentEncoding, limit: u64) -> Self {
+        match encoding {
+            ContentEncoding::Custom(token, handler) => {
+                debug!("Decoding {}", token);
+                ContentDecoder::Custom(LimitReader::new_decompressed(
+                    handler.call(Box::new(self)),
+                    limit,
+                ))
+            }
+            // Without compression features, there are only custom encodings.
+            #[allow(unreachable_patterns)]
+            _ => self.decode(encoding, limit),
+        }
+    }
+}
+
+impl<R: io::Read> ContentDecoder<R> {
+    /// Add a decoder layer on top of this one.
+    fn decode(self, encoding: &ContentEncoding, limit: u64) -> Self {
+        debug!("Decoding {}", encoding.as_str());
+
+        #[cfg(not(any(feature = "gzip", feature = "brotli", feature = "zstd")))]
+        let _ = limit;
+
+        match encoding {
+            #[cfg(feature = "gzip")]
+            ContentEncoding::Gzip => ContentDecoder::Gzip(Box::new(LimitReader::new_decompressed(
+                gzip::GzipDecoder::new(self),
+                limit,
+            ))),
+            #[cfg(feature = "gzip")]
+            ContentEncoding::Deflate => ContentDecoder::Deflate(Box::new(
+                LimitReader::new_decompressed(deflate::DeflateDecoder::new(self), limit),
+            )),
+            #[cfg(feature = "brotli")]
+            ContentEncoding::Brotli => ContentDecoder::Brotli(Box::new(
+                LimitReader::new_decompressed(brotli::BrotliDecoder::new(self), limit),
+            )),
+            #[cfg(feature = "zstd")]
+            ContentEncoding::Zstd => ContentDecoder::Zstd(Box::new(LimitReader::new_decompressed(
+                zstd::ZstdDecoder::new(self),
+                limit,
+            ))),
+            ContentEncoding::Custom(..) => unreachable!("custom decoders in the body source"),
+        }
+    }
+}
+
 impl<R: io::Read> io::Read for ContentDecoder<R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         match self {
             #[cfg(feature = "gzip")]
             ContentDecoder::Gzip(v) => v.read(buf),
+            #[cfg(feature = "gzip")]
+            ContentDecoder::Deflate(v) => v.read(buf),
             #[cfg(feature = "brotli")]
             ContentDecoder::Brotli(v) => v.read(buf),
+            #[cfg(feature = "zstd")]
+            ContentDecoder::Zstd(v) => v.read(buf),
+            ContentDecoder::Custom(v) => v.read(buf),
+            ContentDecoder::Unsupported(v) => {
+                Err(Error::UnsupportedContentEncoding(v.clone()).into_io())
+            }
             ContentDecoder::PassThrough(v) => v.read(buf),
         }
     }
@@ -676,24 +1035,158 @@ impl fmt::Debug for Body {
     }
 }
 
-impl From<&str> for ContentEncoding {
-    fn from(s: &str) -> Self {
-        match s {
-            "gzip" => ContentEncoding::Gzip,
-            "br" => ContentEncoding::Brotli,
-            _ => {
-                info!("Unknown content-encoding: {}", s);
-                ContentEncoding::Unknown
+impl ContentEncoding {
+    fn parse(s: &str, config: &Config) -> Option<Self> {
+        let s = s.to_ascii_lowercase();
+
+        // Registered decoders take precedence over the built in ones.
+        if let Some((token, handler)) = config.content_decoders.iter().find(|(t, _)| *t == s) {
+            return Some(ContentEncoding::Custom(token.clone(), handler.clone()));
+        }
+
+        match s.as_str() {
+            #[cfg(feature = "gzip")]
+            "gzip" | "x-gzip" => Some(ContentEncoding::Gzip),
+            #[cfg(feature = "gzip")]
+            "deflate" => Some(ContentEncoding::Deflate),
+            #[cfg(feature = "brotli")]
+            "br" => Some(ContentEncoding::Brotli),
+            #[cfg(feature = "zstd")]
+            "zstd" => Some(ContentEncoding::Zstd),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        match self {
+            #[cfg(feature = "gzip")]
+            ContentEncoding::Gzip => "gzip",
+            #[cfg(feature = "gzip")]
+            ContentEncoding::Deflate => "deflate",
+            #[cfg(feature = "brotli")]
+            ContentEncoding::Brotli => "br",
+            #[cfg(feature = "zstd")]
+            ContentEncoding::Zstd => "zstd",
+            ContentEncoding::Custom(v, _) => v,
+        }
+    }
+}
+
+impl ContentDecoding {
+    fn new(headers: &http::HeaderMap, config: &Config) -> Self {
+        if !config.decode_content {
+            return ContentDecoding::Decode(vec![]);
+        }
+
+        // Content-Encoding: gzip, br
+        // The header can also be repeated.
+        let names: Vec<&str> = headers
+            .get_all("content-encoding")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty() && !v.eq_ignore_ascii_case("identity"))
+            .collect();
+
+        let mut encodings = Vec::with_capacity(names.len());
+        let mut unsupported = None;
+
+        // The last applied encoding is listed last, and must be decoded first.
+        for name in names.iter().rev() {
+            match ContentEncoding::parse(name, config) {
+                Some(v) => encodings.push(v),
+                None => {
+                    unsupported.get_or_insert(*name);
+                }
+            }
+        }
+
+        match unsupported {
+            None => ContentDecoding::Decode(encodings),
+            // A single unknown encoding is passed through for the user to handle.
+            Some(name) if names.len() == 1 => {
+                info!("Unknown content-encoding: {}", name);
+                ContentDecoding::Decode(vec![])
+            }
+            Some(name) => {
+                info!("Unsupported content-encoding in {:?}", names);
+                ContentDecoding::Unsupported(name.to_string())
+            }
+        }
+    }
+}
+
+fn has_custom(encodings: &[ContentEncoding]) -> bool {
+    encodings
+        .iter()
+        .any(|v| matches!(v, ContentEncoding::Custom(..)))
+}
+
+impl BodyDataSource {
+    /// Apply the content decoding to the body itself, if there are custom decoders.
+    ///
+    /// Custom decoders are boxed, which means they must own the reader they decode.
+    /// Once applied, every reader of the body continues from the same decoders.
+    fn decode_custom(&mut self, info: &ResponseInfo, limit: u64, decompressed_limit: u64) {
+        let ContentDecoding::Decode(encodings) = &info.content_decoding else {
+            return;
+        };
+
+        if !has_custom(encodings) || matches!(self, BodyDataSource::Decoded(..)) {
+            return;
+        }
+
+        let trailers = Arc::new(OnceCell::new());
+
+        let source: Box<dyn io::Read + Send + Sync> =
+            match mem::replace(self, BodyDataSource::Reader(Box::new(io::empty()))) {
+                BodyDataSource::Handler(handler) => Box::new(KeepTrailers {
+                    handler,
+                    trailers: trailers.clone(),
+                }),
+                BodyDataSource::Reader(v) | BodyDataSource::Decoded(v, _) => v,
+            };
+
+        let reader = ContentDecoder::PassThrough(LimitReader::new(source, limit));
+        let reader = encodings.iter().fold(reader, |reader, encoding| {
+            reader.decode_owned(encoding, decompressed_limit)
+        });
+
+        *self = BodyDataSource::Decoded(Box::new(reader), trailers);
+    }
+}
+
+/// Reads the response body, keeping the trailers for [`Body::trailers()`].
+struct KeepTrailers {
+    handler: BodyHandler,
+    trailers: Arc<OnceCell<http::HeaderMap>>,
+}
+
+impl io::Read for KeepTrailers {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.handler.read(buf)?;
+        if n == 0 {
+            if let Some(v) = self.handler.trailers() {
+                let _ = self.trailers.set(v.clone());
             }
         }
+        Ok(n)
     }
 }
 
+/// The body source of a [`BodyWithConfig`].
+#[allow(clippy::large_enum_variant)]
+enum BodySource<'a> {
+    Shared(&'a mut BodyDataSource),
+    Owned(BodyDataSource),
+}
+
 impl<'a> From<&'a mut BodyDataSource> for BodySourceRef<'a> {
     fn from(value: &'a mut BodyDataSource) -> Self {
         match value {
             BodyDataSource::Handler(v) => Self::HandlerShared(v),
-            BodyDataSource::Reader(v) => Self::ReaderShared(v),
+            BodyDataSource::Reader(v) | BodyDataSource::Decoded(v, _) => Self::ReaderShared(v),
         }
     }
 }
@@ -701,7 +1194,7 @@ impl From<BodyDataSource> for BodySourceRef<'static> {
     fn from(value: BodyDataSource) -> Self {
         match value {
             BodyDataSource::Handler(v) => Self::HandlerOwned(v),
-            BodyDataSource::Reader(v) => Self::ReaderOwned(v),
+            BodyDataSource::Reader(v) | BodyDataSource::Decoded(v, _) => Self::ReaderOwned(v),
         }
     }
 }
@@ -709,7 +1202,7 @@ impl From<BodyDataSource> for BodySourceRef<'static> {
 pub(crate) enum BodySourceRef<'a> {
     HandlerShared(&'a mut BodyHandler),
     HandlerOwned(BodyHandler),
-    ReaderShared(&'a mut (dyn io::Read + Send + Sync)),
+    ReaderShared(&'a mut (dyn io::Read + Send + Sync + 'static)),
     ReaderOwned(Box<dyn io::Read + Send + Sync>),
 }
 
@@ -726,11 +1219,13 @@ impl<'a> io::Read for BodySourceRef<'a> {
 
 #[cfg(all(test, feature = "_test"))]
 mod test {
+    use std::io;
     use std::iter;
 
+    use crate::config::Config;
     use crate::test::init_test_log;
-    use crate::transport::set_handler;
-    use crate::Error;
+    use crate::transport::{set_handler, set_handler_fn};
+    use crate::{Agent, Error};
 
     #[test]
     fn content_type_without_charset() {
@@ -757,6 +1252,20 @@ mod test {
         assert_eq!(res.body().charset(), Some("iso-8859-4"));
     }
 
+    #[test]
+    fn content_type_parameter_case() {
+        init_test_log();
+        set_handler(
+            "/get",
+            200,
+            &[("content-type", "text/plain; Charset=iso-8859-4")],
+            b"{}",
+        );
+
+        let res = crate::get("https://my.test/get").call().unwrap();
+        assert_eq!(res.body().charset(), Some("iso-8859-4"));
+    }
+
     #[test]
     fn chunked_transfer() {
         init_test_log();
@@ -780,6 +1289,79 @@ mod test {
         assert_eq!(b, "hello world!!!");
     }
 
+    #[test]
+    fn chunked_trailers() {
+        init_test_log();
+
+        let s = "3\r\n\
+            hel\r\n\
+            2\r\n\
+            lo\r\n\
+            0\r\n\
+            grpc-status: 0\r\n\
+            grpc-message:  all good \r\n\
+            \r\n";
+
+        set_handler(
+            "/get",
+            200,
+            &[("transfer-encoding", "chunked"), ("trailer", "grpc-status")],
+            s.as_bytes(),
+        );
+
+        let mut res = crate::get("https://my.test/get").call().unwrap();
+        assert!(res.body().trailers().is_none());
+
+        let b = res.body_mut().read_to_string().unwrap();
+        assert_eq!(b, "hello");
+
+        let trailers = res.body().trailers().unwrap();
+        assert_eq!(trailers.len(), 2);
+        assert_eq!(trailers["grpc-status"], "0");
+        assert_eq!(trailers["grpc-message"], "all good");
+    }
+
+    #[test]
+    fn chunked_without_trailers() {
+        init_test_log();
+
+        let s = "3\r\nhel\r\n0\r\n\r\n";
+
+        set_handler(
+            "/get",
+            200,
+            &[("transfer-encoding", "chunked")],
+            s.as_bytes(),
+        );
+
+        let mut res = crate::get("https://my.test/get").call().unwrap();
+        res.body_mut().read_to_string().unwrap();
+        assert!(res.body().trailers().unwrap().is_empty());
+    }
+
+    #[test]
+    fn chunked_trailers_too_large() {
+        init_test_log();
+
+        let s = format!("3\r\nhel\r\n0\r\nx-big: {}\r\n\r\n", "b".repeat(2000));
+
+        set_handler(
+            "/get",
+            200,
+            &[("transfer-encoding", "chunked")],
+            s.as_bytes(),
+        );
+
+        let mut res = crate::get("https://my.test/get")
+            .config()
+            .max_response_header_size(1000)
+            .build()
+            .call()
+            .unwrap();
+        let err = res.body_mut().read_to_string().unwrap_err();
+        assert!(matches!(err, Error::LargeResponseHeader(_, 1000)));
+    }
+
     #[test]
     fn large_response_header() {
         init_test_log();
@@ -796,4 +1378,214 @@ mod test {
         let err = crate::get("https://my.test/get").call().unwrap_err();
         assert!(matches!(err, Error::LargeResponseHeader(_, _)));
     }
+
+    #[cfg(feature = "gzip")]
+    fn deflate_then_gzip(data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+
+        use flate2::write::{GzEncoder, ZlibEncoder};
+        use flate2::Compression;
+
+        let mut enc = ZlibEncoder::new(vec![], Compression::default());
+        enc.write_all(data).unwrap();
+        let deflated = enc.finish().unwrap();
+
+        let mut enc = GzEncoder::new(vec![], Compression::default());
+        enc.write_all(&deflated).unwrap();
+        enc.finish().unwrap()
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn stacked_content_encoding() {
+        init_test_log();
+        let body = deflate_then_gzip(b"hello stacked");
+
+        set_handler(
+            "/list",
+            200,
+            &[("content-encoding", "deflate, gzip")],
+            &body,
+        );
+        set_handler(
+            "/repeated",
+            200,
+            &[
+                ("content-encoding", "deflate"),
+                ("content-encoding", "gzip"),
+            ],
+            &body,
+        );
+
+        for path in ["/list", "/repeated"] {
+            let mut res = crate::get(format!("https://my.test{}", path))
+                .call()
+                .unwrap();
+            let decoded: Vec<_> = res.body().content_decoding().collect();
+            assert_eq!(decoded, ["gzip", "deflate"]);
+            assert_eq!(res.body_mut().read_to_string().unwrap(), "hello stacked");
+        }
+    }
+
+    #[test]
+    fn unknown_content_encoding() {
+        init_test_log();
+        set_handler("/unknown", 200, &[("content-encoding", "foo")], b"raw");
+
+        let mut res = crate::get("https://my.test/unknown").call().unwrap();
+        assert_eq!(res.body().content_decoding().count(), 0);
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "raw");
+    }
+
+    #[test]
+    fn unsupported_stacked_content_encoding() {
+        init_test_log();
+        set_handler(
+            "/stacked",
+            200,
+            &[("content-encoding", "gzip, foo")],
+            b"raw",
+        );
+
+        let mut res = crate::get("https://my.test/stacked").call().unwrap();
+        assert_eq!(res.body().content_decoding().count(), 0);
+        let err = res.body_mut().read_to_string().unwrap_err();
+        assert!(matches!(err, Error::UnsupportedContentEncoding(v) if v == "foo"));
+    }
+
+    struct XorDecoder<R>(R);
+
+    impl<R: io::Read> io::Read for XorDecoder<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.0.read(buf)?;
+            buf[..n].iter_mut().for_each(|b| *b ^= 0x5a);
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn custom_content_decoder() {
+        init_test_log();
+        let body: Vec<u8> = b"hello custom".iter().map(|b| b ^ 0x5a).collect();
+
+        set_handler_fn("/xor", move |uri, req, w| {
+            let accept = req.headers().get("accept-encoding").unwrap();
+            assert!(accept.to_str().unwrap().ends_with("x-xor"));
+            let status = if uri.path() == "/xor-error" { 500 } else { 200 };
+            write!(
+                w,
+                "HTTP/1.1 {} OK\r\n\
+                content-encoding: X-Xor\r\n\
+                content-length: {}\r\n\
+                \r\n",
+                status,
+                body.len()
+            )?;
+            w.write_all(&body)
+        });
+
+        let agent: Agent = Config::builder()
+            .content_decoder("x-xor", |reader| Box::new(XorDecoder(reader)))
+            .status_error_response(true)
+            .build()
+            .into();
+
+        let mut res = agent.get("https://my.test/xor").call().unwrap();
+        let decoded: Vec<_> = res.body().content_decoding().collect();
+        assert_eq!(decoded, ["x-xor"]);
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "hello custom");
+        assert_eq!(agent.pool_count(), 1);
+
+        // Shared readers continue from the same decoder. Other hosts for new connections.
+        let mut res = agent.get("https://shared.test/xor").call().unwrap();
+        let mut start = [0; 6];
+        io::Read::read_exact(&mut res.body_mut().as_reader(), &mut start).unwrap();
+        assert_eq!(&start, b"hello ");
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "custom");
+
+        let res = agent.get("https://owned.test/xor").call().unwrap();
+        let text = res.into_body().into_with_config().read_to_string().unwrap();
+        assert_eq!(text, "hello custom");
+
+        let err = agent
+            .get("https://error.test/xor-error")
+            .call()
+            .unwrap_err();
+        let Error::StatusResponse(res) = err else {
+            panic!("expected a status response: {}", err);
+        };
+        assert_eq!(res.body_text(), "hello custom");
+
+        let mut chunked = b"c\r\n".to_vec();
+        chunked.extend(b"hello custom".iter().map(|b| b ^ 0x5a));
+        chunked.extend_from_slice(b"\r\n0\r\nx-trailer: yes\r\n\r\n");
+        set_handler(
+            "/chunked-xor",
+            200,
+            &[
+                ("content-encoding", "x-xor"),
+                ("transfer-encoding", "chunked"),
+            ],
+            &chunked,
+        );
+
+        let mut res = agent
+            .get("https://chunked.test/chunked-xor")
+            .call()
+            .unwrap();
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "hello custom");
+        assert_eq!(res.body().trailers().unwrap()["x-trailer"], "yes");
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn custom_content_decoder_replaces_built_in() {
+        use std::io::Write;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        use flate2::read::GzDecoder;
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+
+        init_test_log();
+        let mut enc = GzEncoder::new(vec![], Compression::default());
+        enc.write_all(b"hello gzip").unwrap();
+        let body = enc.finish().unwrap();
+
+        set_handler("/custom-gzip", 200, &[("content-encoding", "gzip")], &body);
+
+        let used = Arc::new(AtomicUsize::new(0));
+        let used2 = used.clone();
+
+        let agent: Agent = Config::builder()
+            .content_decoder("gzip", move |reader| {
+                used2.fetch_add(1, Ordering::SeqCst);
+                Box::new(GzDecoder::new(reader))
+            })
+            .build()
+            .into();
+
+        let mut res = agent.get("https://my.test/custom-gzip").call().unwrap();
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "hello gzip");
+        assert_eq!(used.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn decode_content_off() {
+        init_test_log();
+        let body = deflate_then_gzip(b"hello raw");
+
+        set_handler("/raw", 200, &[("content-encoding", "deflate, gzip")], &body);
+
+        let mut res = crate::get("https://my.test/raw")
+            .config()
+            .decode_content(false)
+            .build()
+            .call()
+            .unwrap();
+        assert_eq!(res.body().content_decoding().count(), 0);
+        assert_eq!(res.body_mut().read_to_vec().unwrap(), body);
+    }
 }
@@ -0,0 +1,314 @@
+use std::fmt;
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+
+use crate::http::HeaderMap;
+use crate::util::{self, find};
+use crate::Error;
+
+use super::BodyReader;
+
+/// Max size of the headers of one part.
+const MAX_PART_HEADER_SIZE: usize = 64 * 1024;
+
+/// Iterator over the parts of a `multipart/*` response body.
+///
+/// Obtained via [`Body::as_multipart()`](crate::Body::as_multipart) or
+/// [`Body::into_multipart()`](crate::Body::into_multipart).
+///
+/// The body is parsed while it is read. Moving to the next part skips whatever is
+/// left of the previous part, after which the previous part reads nothing.
+pub struct BodyParts<'a> {
+    state: Arc<Mutex<PartsState<'a>>>,
+}
+
+/// One part of a `multipart/*` response body.
+///
+/// Reads the data of the part, without the headers.
+pub struct BodyPart<'a> {
+    headers: HeaderMap,
+    index: usize,
+    state: Arc<Mutex<PartsState<'a>>>,
+}
+
+struct PartsState<'a> {
+    reader: BodyReader<'a>,
+    /// `\r\n--boundary`
+    delimiter: Vec<u8>,
+    /// Input read, but not yet used.
+    buf: Vec<u8>,
+    /// Index of the part being read. The preamble is 0.
+    index: usize,
+    in_part: bool,
+    done: bool,
+}
+
+impl<'a> BodyParts<'a> {
+    pub(crate) fn new(reader: BodyReader<'a>, boundary: &str) -> Self {
+        let state = PartsState {
+            reader,
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            // The first delimiter is allowed without the preceding line break.
+            buf: b"\r\n".to_vec(),
+            index: 0,
+            in_part: true,
+            done: false,
+        };
+
+        BodyParts {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+}
+
+impl<'a> Iterator for BodyParts<'a> {
+    type Item = Result<BodyPart<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut state = self.state.lock().unwrap();
+
+        let result = state.next_part();
+        if result.is_err() {
+            state.done = true;
+        }
+
+        let headers = result.transpose()?;
+
+        Some(headers.map(|headers| BodyPart {
+            headers,
+            index: state.index,
+            state: self.state.clone(),
+        }))
+    }
+}
+
+impl<'a> BodyPart<'a> {
+    /// The headers of the part.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+}
+
+impl<'a> Read for BodyPart<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        if state.index != self.index {
+            return Ok(0);
+        }
+        state.read_part(buf)
+    }
+}
+
+impl<'a> PartsState<'a> {
+    /// Read more input into the buffer. Returns `false` at the end of the body.
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut tmp = [0; 8192];
+        let n = self.reader.read(&mut tmp)?;
+        self.buf.extend_from_slice(&tmp[..n]);
+        Ok(n > 0)
+    }
+
+    fn fill_or_eof(&mut self) -> Result<(), Error> {
+        if self.fill()? {
+            Ok(())
+        } else {
+            Err(Error::Multipart("body ended before the closing boundary"))
+        }
+    }
+
+    /// Read the data of the current part, up until the next delimiter.
+    fn read_part(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if !self.in_part || out.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            // Bytes that can't be the start of a delimiter.
+            let available = match find(&self.buf, &self.delimiter) {
+                Some(0) => {
+                    self.in_part = false;
+                    return Ok(0);
+                }
+                Some(pos) => pos,
+                None => self.buf.len().saturating_sub(self.delimiter.len() - 1),
+            };
+
+            if available > 0 {
+                let n = available.min(out.len());
+                out[..n].copy_from_slice(&self.buf[..n]);
+                self.buf.drain(..n);
+                return Ok(n);
+            }
+
+            self.fill_or_eof().map_err(Error::into_io)?;
+        }
+    }
+
+    fn next_part(&mut self) -> Result<Option<HeaderMap>, Error> {
+        if self.done {
+            return Ok(None);
+        }
+
+        // Skip the rest of the current part, or the preamble.
+        let mut skip = [0; 8192];
+        while self.read_part(&mut skip)? > 0 {}
+
+        self.buf.drain(..self.delimiter.len());
+
+        while self.buf.len() < 2 {
+            self.fill_or_eof()?;
+        }
+
+        if self.buf.starts_with(b"--") {
+            // Closing delimiter. Anything after is the epilogue.
+            self.done = true;
+            self.index += 1;
+            return Ok(None);
+        }
+
+        // The delimiter line may end with whitespace.
+        let end = self.find_filled(b"\r\n", 1024)?;
+        if self.buf[..end].iter().any(|c| *c != b' ' && *c != b'\t') {
+            return Err(Error::Multipart("invalid boundary line"));
+        }
+        self.buf.drain(..end + 2);
+
+        let headers = if self.buf.starts_with(b"\r\n") {
+            // No headers.
+            self.buf.drain(..2);
+            HeaderMap::new()
+        } else {
+            let end = self.find_filled(b"\r\n\r\n", MAX_PART_HEADER_SIZE)?;
+            let headers = parse_headers(&self.buf[..end])?;
+            self.buf.drain(..end + 4);
+            headers
+        };
+
+        self.index += 1;
+        self.in_part = true;
+
+        Ok(Some(headers))
+    }
+
+    /// Position of `needle`, reading more input as needed.
+    fn find_filled(&mut self, needle: &[u8], max: usize) -> Result<usize, Error> {
+        loop {
+            if let Some(pos) = find(&self.buf, needle) {
+                return Ok(pos);
+            }
+            if self.buf.len() > max {
+                return Err(Error::Multipart("part headers are too big"));
+            }
+            self.fill_or_eof()?;
+        }
+    }
+}
+
+fn parse_headers(block: &[u8]) -> Result<HeaderMap, Error> {
+    let mut headers = HeaderMap::new();
+
+    for header in util::parse_headers(block) {
+        let (name, value) = header.map_err(|_| Error::Multipart("invalid part header"))?;
+        headers.append(name, value);
+    }
+
+    Ok(headers)
+}
+
+impl<'a> fmt::Debug for BodyParts<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BodyParts").finish()
+    }
+}
+
+impl<'a> fmt::Debug for BodyPart<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BodyPart")
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use crate::Body;
+
+    fn body(data: &str) -> Body {
+        Body::builder()
+            .mime_type("multipart/byteranges")
+            .boundary("XyZ")
+            .data(data)
+    }
+
+    #[test]
+    fn parse_parts() {
+        let mut body = body(
+            "preamble\r\n\
+            --XyZ\r\n\
+            Content-Type: text/plain\r\n\
+            Content-Range: bytes 0-4/20\r\n\
+            \r\n\
+            hello\r\n\
+            --XyZ  \r\n\
+            \r\n\
+            wor--XyZ\r\nld\r\n\
+            --XyZ--\r\n\
+            epilogue",
+        );
+
+        let mut parts = body.as_multipart().unwrap();
+
+        let mut part = parts.next().unwrap().unwrap();
+        assert_eq!(part.headers()["content-type"], "text/plain");
+        assert_eq!(part.headers()["content-range"], "bytes 0-4/20");
+        let mut s = String::new();
+        part.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "hello");
+
+        let mut part = parts.next().unwrap().unwrap();
+        assert!(part.headers().is_empty());
+        let mut s = String::new();
+        part.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "wor--XyZ\r\nld");
+
+        assert!(parts.next().is_none());
+    }
+
+    #[test]
+    fn skip_unread_part() {
+        let mut body = body(
+            "--XyZ\r\n\r\nfirst\r\n\
+            --XyZ\r\n\r\nsecond\r\n\
+            --XyZ--",
+        );
+
+        let mut parts = body.as_multipart().unwrap();
+        let mut first = parts.next().unwrap().unwrap();
+        let mut second = parts.next().unwrap().unwrap();
+
+        let mut s = String::new();
+        second.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "second");
+
+        // The iterator moved on.
+        assert_eq!(first.read(&mut [0; 10]).unwrap(), 0);
+        assert!(parts.next().is_none());
+    }
+
+    #[test]
+    fn missing_closing_boundary() {
+        let mut body = body("--XyZ\r\n\r\ntruncated");
+
+        let mut parts = body.as_multipart().unwrap();
+        let mut part = parts.next().unwrap().unwrap();
+        assert!(part.read_to_end(&mut vec![]).is_err());
+    }
+
+    #[test]
+    fn not_multipart() {
+        let mut body = Body::builder().mime_type("text/plain").data("hello");
+        assert!(body.as_multipart().is_err());
+    }
+}
@@ -0,0 +1,48 @@
+use std::io::{self, BufReader};
+
+use zstd::stream::read::Decoder;
+
+use crate::Error;
+
+pub(crate) struct ZstdDecoder<R: io::Read>(io::Result<Decoder<'static, BufReader<R>>>);
+
+impl<R: io::Read> ZstdDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        ZstdDecoder(Decoder::new(reader))
+    }
+}
+
+impl<R: io::Read> io::Read for ZstdDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let decoder = match &mut self.0 {
+            Ok(v) => v,
+            // Creating the decoder context failed.
+            Err(e) => {
+                return Err(
+                    Error::Decompress("zstd", io::Error::new(e.kind(), e.to_string())).into_io(),
+                )
+            }
+        };
+
+        decoder
+            .read(buf)
+            .map_err(|e| Error::Decompress("zstd", e).into_io())
+    }
+}
+
+#[cfg(all(test, feature = "_test"))]
+mod test {
+    use crate::test::init_test_log;
+    use crate::transport::set_handler;
+
+    #[test]
+    fn zstd_body() {
+        init_test_log();
+
+        let body = zstd::stream::encode_all(&b"hello zstd"[..], 3).unwrap();
+        set_handler("/zstd", 200, &[("content-encoding", "zstd")], &body);
+
+        let mut res = crate::get("https://my.test/zstd").call().unwrap();
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "hello zstd");
+    }
+}
@@ -1,16 +1,19 @@
 //! Agent configuration
 
 use std::fmt;
+use std::io;
 use std::sync::Arc;
 use std::time::Duration;
 
 use http::Uri;
 use ureq_proto::client::flow::RedirectAuthHeaders;
 
+use crate::body::{MAX_BODY_SIZE, MAX_DECOMPRESSED_SIZE};
 use crate::http;
 use crate::middleware::{Middleware, MiddlewareChain};
+use crate::redirect::RedirectPolicy;
 use crate::resolver::IpFamily;
-use crate::{Agent, AsSendBody, Proxy, RequestBuilder};
+use crate::{Agent, AsSendBody, Body, Error, ErrorResponse, Proxy, RedirectAction, RequestBuilder};
 
 #[cfg(feature = "_tls")]
 use crate::tls::TlsConfig;
@@ -91,19 +94,35 @@ use crate::tls::TlsConfig;
 #[derive(Clone)]
 pub struct Config {
     pub(crate) http_status_as_error: bool,
+    pub(crate) status_error_policy: Option<StatusErrorPolicy>,
+    pub(crate) status_error_response: bool,
     pub(crate) https_only: bool,
     pub(crate) ip_family: IpFamily,
     #[cfg(feature = "_tls")]
     pub(crate) tls_config: TlsConfig,
     pub(crate) proxy: Option<Proxy>,
+    #[cfg(feature = "http2")]
+    pub(crate) http2: bool,
+    #[cfg(feature = "http2")]
+    pub(crate) http2_prior_knowledge: bool,
     pub(crate) no_delay: bool,
     pub(crate) max_redirects: u32,
     pub(crate) redirect_auth_headers: RedirectAuthHeaders,
+    pub(crate) redirect_policy: Option<RedirectPolicy>,
+    pub(crate) informational_handler: Option<InformationalHandler>,
+    pub(crate) upload_progress: Option<UploadProgressHandler>,
+    pub(crate) download_progress: Option<DownloadProgressHandler>,
     pub(crate) user_agent: AutoHeaderValue,
     pub(crate) accept: AutoHeaderValue,
     pub(crate) accept_encoding: AutoHeaderValue,
+    pub(crate) content_decoders: Vec<(String, ContentDecoderHandler)>,
+    pub(crate) decode_content: bool,
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    pub(crate) body_compression: Option<BodyCompression>,
     pub(crate) timeouts: Timeouts,
     pub(crate) max_response_header_size: usize,
+    pub(crate) max_body_size: u64,
+    pub(crate) max_decompressed_size: u64,
     pub(crate) input_buffer_size: usize,
     pub(crate) output_buffer_size: usize,
     pub(crate) max_idle_connections: usize,
@@ -140,6 +159,31 @@ impl Config {
 
         Some(proxy.uri())
     }
+
+    /// Whether a response is turned into an error.
+    pub(crate) fn is_status_error(
+        &self,
+        status: http::StatusCode,
+        headers: &http::HeaderMap,
+    ) -> bool {
+        if !self.http_status_as_error {
+            return false;
+        }
+
+        match &self.status_error_policy {
+            Some(policy) => (policy.0)(status, headers),
+            None => status.is_client_error() || status.is_server_error(),
+        }
+    }
+
+    /// The error for a response that [`is_status_error()`](Self::is_status_error).
+    pub(crate) fn status_error(&self, response: http::Response<Body>) -> Error {
+        if self.status_error_response {
+            Error::StatusResponse(Box::new(ErrorResponse::new(response)))
+        } else {
+            Error::StatusCode(response.status().as_u16())
+        }
+    }
 }
 
 /// Builder of [`Config`]
@@ -195,6 +239,64 @@ impl<Scope: private::ConfigScope> ConfigBuilder<Scope> {
         self
     }
 
+    /// Decide which responses are errors, instead of all 4xx and 5xx.
+    ///
+    /// The callback receives the status and headers of the final response, and returns
+    /// whether it should be an [`Error::StatusCode`](crate::Error::StatusCode).
+    /// This only applies while [`http_status_as_error`](Self::http_status_as_error) is
+    /// `true`.
+    ///
+    /// ```
+    /// use ureq::Agent;
+    ///
+    /// let agent: Agent = Agent::config_builder()
+    ///     // A 404 is an answer for existence checks, but with
+    ///     // redirects disabled, a 3xx is unexpected.
+    ///     .max_redirects(0)
+    ///     .status_error_policy(|status, _headers| {
+    ///         status != 404 && status.as_u16() >= 300
+    ///     })
+    ///     .build()
+    ///     .into();
+    /// ```
+    ///
+    /// Defaults to `None`, meaning 4xx and 5xx are errors.
+    pub fn status_error_policy(
+        mut self,
+        v: impl Fn(http::StatusCode, &http::HeaderMap) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.config().status_error_policy = Some(StatusErrorPolicy(Arc::new(v)));
+        self
+    }
+
+    /// Whether status code errors carry the response.
+    ///
+    /// When `true`, responses that are errors according to
+    /// [`http_status_as_error`](Self::http_status_as_error) become
+    /// [`Error::StatusResponse`](crate::Error::StatusResponse), with the headers and
+    /// the start of the body, instead of [`Error::StatusCode`](crate::Error::StatusCode).
+    /// This reads up to 64kB of the body before returning the error.
+    ///
+    /// ```no_run
+    /// use ureq::Error;
+    ///
+    /// let result = ureq::get("http://httpbin.org/status/429")
+    ///     .config()
+    ///     .status_error_response(true)
+    ///     .build()
+    ///     .call();
+    ///
+    /// if let Err(Error::StatusResponse(res)) = result {
+    ///     println!("retry after {:?}", res.headers().get("retry-after"));
+    /// }
+    /// ```
+    ///
+    /// Defaults to `false`.
+    pub fn status_error_response(mut self, v: bool) -> Self {
+        self.config().status_error_response = v;
+        self
+    }
+
     /// Whether to limit requests (including redirects) to https only
     ///
     /// Defaults to `false`.
@@ -231,6 +333,38 @@ impl<Scope: private::ConfigScope> ConfigBuilder<Scope> {
         self
     }
 
+    /// Whether to use HTTP/2 for https, if the server supports it.
+    ///
+    /// HTTP/2 is negotiated with ALPN in the TLS handshake. Requests to the same host
+    /// then share one connection, each as a stream, also when sent concurrently from
+    /// several threads.
+    ///
+    /// Protocol upgrades, such as [`Body::into_upgraded()`](crate::Body::into_upgraded),
+    /// need HTTP/1.1. Requests with an `upgrade` header always use HTTP/1.1.
+    ///
+    /// Requires feature flag **http2**.
+    ///
+    /// Defaults to `false`.
+    #[cfg(feature = "http2")]
+    pub fn http2(mut self, v: bool) -> Self {
+        self.config().http2 = v;
+        self
+    }
+
+    /// Use HTTP/2 for plain http, without negotiating it first.
+    ///
+    /// This is known as h2c with prior knowledge. There is no fallback to HTTP/1.1, the
+    /// server must support it.
+    ///
+    /// Requires feature flag **http2**.
+    ///
+    /// Defaults to `false`.
+    #[cfg(feature = "http2")]
+    pub fn http2_prior_knowledge(mut self, v: bool) -> Self {
+        self.config().http2_prior_knowledge = v;
+        self
+    }
+
     /// Disable Nagle's algorithm
     ///
     /// Set TCP_NODELAY. It's up to the transport whether this flag is honored.
@@ -260,6 +394,123 @@ impl<Scope: private::ConfigScope> ConfigBuilder<Scope> {
         self
     }
 
+    /// Callback deciding whether to follow a redirect.
+    ///
+    /// The callback receives the request that was redirected, the 3xx status, and the
+    /// value of the `Location` header, as sent by the server (it might be relative).
+    ///
+    /// The callback is not invoked once [`max_redirects`](Self::max_redirects) is reached.
+    ///
+    /// ```
+    /// use ureq::{Agent, RedirectAction};
+    ///
+    /// let agent: Agent = Agent::config_builder()
+    ///     // Don't follow redirects to plain http.
+    ///     .redirect_policy(|_req, _status, location| {
+    ///         if location.starts_with("http://") {
+    ///             RedirectAction::Error
+    ///         } else {
+    ///             RedirectAction::Follow
+    ///         }
+    ///     })
+    ///     .build()
+    ///     .into();
+    /// ```
+    ///
+    /// Defaults to following all redirects.
+    pub fn redirect_policy(
+        mut self,
+        v: impl Fn(&http::Request<()>, http::StatusCode, &str) -> RedirectAction + Send + Sync + 'static,
+    ) -> Self {
+        self.config().redirect_policy = Some(RedirectPolicy::new(v));
+        self
+    }
+
+    /// Callback for informational (1xx) responses.
+    ///
+    /// A server can send any number of informational responses before the final response,
+    /// such as `103 Early Hints` with `Link` headers for preloading, or `102 Processing`
+    /// to keep a long running request alive. ureq skips these, and passes each one to
+    /// this callback.
+    ///
+    /// `101 Switching Protocols` is a final response and not passed here.
+    ///
+    /// ```
+    /// use ureq::Agent;
+    /// use ureq::http::header::LINK;
+    ///
+    /// let agent: Agent = Agent::config_builder()
+    ///     .informational_handler(|status, headers| {
+    ///         if status == 103 {
+    ///             for link in headers.get_all(LINK) {
+    ///                 println!("Preload: {:?}", link);
+    ///             }
+    ///         }
+    ///     })
+    ///     .build()
+    ///     .into();
+    /// ```
+    ///
+    /// Defaults to `None`.
+    pub fn informational_handler(
+        mut self,
+        v: impl Fn(http::StatusCode, &http::HeaderMap) + Send + Sync + 'static,
+    ) -> Self {
+        self.config().informational_handler = Some(InformationalHandler(Arc::new(v)));
+        self
+    }
+
+    /// Callback for the progress of sending the request body.
+    ///
+    /// The callback receives the number of body bytes sent so far, and the total when
+    /// the body has a known size. It's called as the body is sent, from the thread
+    /// making the request.
+    ///
+    /// ```
+    /// use ureq::Agent;
+    ///
+    /// let agent: Agent = Agent::config_builder()
+    ///     .upload_progress(|sent, total| match total {
+    ///         Some(total) => println!("Sent {} of {}", sent, total),
+    ///         None => println!("Sent {}", sent),
+    ///     })
+    ///     .build()
+    ///     .into();
+    /// ```
+    ///
+    /// Defaults to `None`.
+    pub fn upload_progress(mut self, v: impl Fn(u64, Option<u64>) + Send + Sync + 'static) -> Self {
+        self.config().upload_progress = Some(UploadProgressHandler(Arc::new(v)));
+        self
+    }
+
+    /// Callback for the progress of receiving the response body.
+    ///
+    /// The callback is called as the body is read, see [`DownloadProgress`]. Bodies of
+    /// redirects that are followed are not reported.
+    ///
+    /// ```
+    /// use ureq::Agent;
+    ///
+    /// let agent: Agent = Agent::config_builder()
+    ///     .download_progress(|progress| {
+    ///         if let Some(total) = progress.content_length() {
+    ///             println!("Received {} of {}", progress.decoded(), total);
+    ///         }
+    ///     })
+    ///     .build()
+    ///     .into();
+    /// ```
+    ///
+    /// Defaults to `None`.
+    pub fn download_progress(
+        mut self,
+        v: impl Fn(&DownloadProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.config().download_progress = Some(DownloadProgressHandler(Arc::new(v)));
+        self
+    }
+
     /// Value to use for the `User-Agent` header.
     ///
     /// This can be overridden by setting a `user-agent` header on the request
@@ -288,9 +539,9 @@ impl<Scope: private::ConfigScope> ConfigBuilder<Scope> {
 
     /// Value to use for the `Accept-Encoding` header.
     ///
-    /// Defaults to `Default`, which will add `gz` and `brotli` depending on
-    /// the feature flags **gzip** and **brotli** respectively. If neither
-    /// feature is enabled, the header is not added.
+    /// Defaults to `Default`, which will add `gzip, deflate`, `br` and `zstd` depending
+    /// on the feature flags **gzip**, **brotli** and **zstd** respectively. If none of
+    /// the features are enabled, the header is not added.
     ///
     /// This agent configured value can be overriden per request by setting the header.
     ///
@@ -304,6 +555,113 @@ impl<Scope: private::ConfigScope> ConfigBuilder<Scope> {
         self
     }
 
+    /// Register a decoder for a `Content-Encoding`.
+    ///
+    /// The decoder is used for responses with the coding token `token` (case
+    /// insensitive), and wraps a reader of the encoded body in a reader of the decoded
+    /// data. Registering a token that ureq decodes itself, such as `gzip`, replaces the
+    /// built in decoder. Registering the same token again replaces the previous decoder.
+    ///
+    /// Registered tokens are added to the default `Accept-Encoding` header. A token
+    /// that isn't a valid HTTP token, such as one with spaces, is ignored.
+    ///
+    /// ```
+    /// use std::io::Read;
+    /// use ureq::Agent;
+    ///
+    /// // A "compression" that xors every byte.
+    /// struct XorDecoder<R>(R);
+    ///
+    /// impl<R: Read> Read for XorDecoder<R> {
+    ///     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    ///         let n = self.0.read(buf)?;
+    ///         buf[..n].iter_mut().for_each(|b| *b ^= 0x5a);
+    ///         Ok(n)
+    ///     }
+    /// }
+    ///
+    /// let agent: Agent = Agent::config_builder()
+    ///     .content_decoder("x-xor", |reader| Box::new(XorDecoder(reader)))
+    ///     .build()
+    ///     .into();
+    ///
+    /// let text = agent.get("http://httpbin.org/get")
+    ///     .call()?
+    ///     .body_mut()
+    ///     .read_to_string()?;
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    pub fn content_decoder(
+        mut self,
+        token: impl Into<String>,
+        v: impl Fn(Box<dyn io::Read + Send + Sync>) -> Box<dyn io::Read + Send + Sync>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        let token = token.into().to_ascii_lowercase();
+        if !is_token(&token) {
+            info!("Ignore content decoder for invalid token: {:?}", token);
+            return self;
+        }
+        let handler = ContentDecoderHandler(Arc::new(v));
+        let decoders = &mut self.config().content_decoders;
+        decoders.retain(|(t, _)| *t != token);
+        decoders.push((token, handler));
+        self
+    }
+
+    /// Whether to decode the response body according to its `Content-Encoding`.
+    ///
+    /// When `false`, the body is read as the raw bytes sent by the server, which is
+    /// useful for proxying a response, or storing it compressed. The `Accept-Encoding`
+    /// header is sent regardless of this setting.
+    ///
+    /// ```no_run
+    /// let mut res = ureq::get("http://httpbin.org/gzip")
+    ///     .config()
+    ///     .decode_content(false)
+    ///     .build()
+    ///     .call()?;
+    ///
+    /// // gzip data
+    /// let raw = res.body_mut().read_to_vec()?;
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    ///
+    /// Defaults to `true`.
+    pub fn decode_content(mut self, v: bool) -> Self {
+        self.config().decode_content = v;
+        self
+    }
+
+    /// Compress the request body.
+    ///
+    /// The body is compressed while it is sent, which means it is never held in
+    /// memory in full. This sets the `Content-Encoding` header, and sends the body
+    /// with `Transfer-Encoding: chunked` since the compressed size isn't known up front.
+    ///
+    /// The body is sent as is when it is empty, or when the request already has a
+    /// `Content-Encoding` or `Content-Length` header.
+    ///
+    /// ```
+    /// use ureq::config::BodyCompression;
+    ///
+    /// let res = ureq::post("http://httpbin.org/post")
+    ///     .config()
+    ///     .body_compression(Some(BodyCompression::Gzip))
+    ///     .build()
+    ///     .send("A lot of log lines")?;
+    /// # Ok::<_, ureq::Error>(())
+    /// ```
+    ///
+    /// Defaults to `None`.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    pub fn body_compression(mut self, v: Option<BodyCompression>) -> Self {
+        self.config().body_compression = v;
+        self
+    }
+
     /// Max size of the HTTP response header.
     ///
     /// From the status, including all headers up until the body.
@@ -314,6 +672,35 @@ impl<Scope: private::ConfigScope> ConfigBuilder<Scope> {
         self
     }
 
+    /// Max size of a response body read into memory.
+    ///
+    /// Limits [`Body::read_to_string()`](crate::Body::read_to_string),
+    /// [`Body::read_to_vec()`](crate::Body::read_to_vec) and `Body::read_json()`. Bodies
+    /// larger than this fail with [`Error::BodyExceedsLimit`](crate::Error::BodyExceedsLimit).
+    /// Use [`Body::with_config()`](crate::Body::with_config) to read with another limit.
+    ///
+    /// Defaults to 10MB.
+    pub fn max_body_size(mut self, v: u64) -> Self {
+        self.config().max_body_size = v;
+        self
+    }
+
+    /// Max size of a response body after decompression.
+    ///
+    /// Limits [`Body::read_to_string()`](crate::Body::read_to_string),
+    /// [`Body::read_to_vec()`](crate::Body::read_to_vec) and `Body::read_json()` of a body
+    /// that is decoded because of the `Content-Encoding` header (**gzip**, **brotli** and
+    /// **zstd** features). This protects against small compressed bodies expanding to
+    /// exhaust memory. Bodies that decompress to more than this fail with
+    /// [`Error::DecompressedBodyExceedsLimit`](crate::Error::DecompressedBodyExceedsLimit).
+    /// Use [`Body::with_config()`](crate::Body::with_config) to read with another limit.
+    ///
+    /// Defaults to 100MB.
+    pub fn max_decompressed_size(mut self, v: u64) -> Self {
+        self.config().max_decompressed_size = v;
+        self
+    }
+
     /// Default size of the input buffer
     ///
     /// The default connectors use this setting.
@@ -490,7 +877,7 @@ impl Default for AutoHeaderValue {
 }
 
 impl AutoHeaderValue {
-    pub(crate) fn as_str(&self, default: &'static str) -> Option<&str> {
+    pub(crate) fn as_str<'a>(&'a self, default: &'a str) -> Option<&'a str> {
         let x = match self {
             AutoHeaderValue::None => "",
             AutoHeaderValue::Default => default,
@@ -505,6 +892,34 @@ impl AutoHeaderValue {
     }
 }
 
+/// Compression of request bodies.
+///
+/// Used with [`ConfigBuilder::body_compression()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub enum BodyCompression {
+    /// `Content-Encoding: gzip` (**gzip** feature).
+    #[cfg(feature = "gzip")]
+    Gzip,
+
+    /// `Content-Encoding: zstd` (**zstd** feature).
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+impl BodyCompression {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "gzip")]
+            BodyCompression::Gzip => "gzip",
+            #[cfg(feature = "zstd")]
+            BodyCompression::Zstd => "zstd",
+        }
+    }
+}
+
 impl ConfigBuilder<AgentScope> {
     /// Finalize the config
     pub fn build(self) -> Config {
@@ -562,6 +977,135 @@ pub struct Timeouts {
 #[derive(Debug, Clone)]
 pub(crate) struct RequestLevelConfig(pub Config);
 
+type InformationalFn = dyn Fn(http::StatusCode, &http::HeaderMap) + Send + Sync + 'static;
+
+/// Callback for informational responses.
+#[derive(Clone)]
+pub(crate) struct InformationalHandler(Arc<InformationalFn>);
+
+impl InformationalHandler {
+    pub(crate) fn call(&self, status: http::StatusCode, headers: &http::HeaderMap) {
+        (self.0)(status, headers)
+    }
+}
+
+impl fmt::Debug for InformationalHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("InformationalHandler").finish()
+    }
+}
+
+type StatusErrorFn = dyn Fn(http::StatusCode, &http::HeaderMap) -> bool + Send + Sync + 'static;
+
+/// Callback deciding which response statuses are errors.
+#[derive(Clone)]
+pub(crate) struct StatusErrorPolicy(Arc<StatusErrorFn>);
+
+impl fmt::Debug for StatusErrorPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("StatusErrorPolicy").finish()
+    }
+}
+
+type UploadProgressFn = dyn Fn(u64, Option<u64>) + Send + Sync + 'static;
+
+/// Callback for upload progress.
+#[derive(Clone)]
+pub(crate) struct UploadProgressHandler(Arc<UploadProgressFn>);
+
+impl UploadProgressHandler {
+    pub(crate) fn call(&self, sent: u64, total: Option<u64>) {
+        (self.0)(sent, total)
+    }
+}
+
+impl fmt::Debug for UploadProgressHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("UploadProgressHandler").finish()
+    }
+}
+
+/// Whether `s` is a token (RFC 9110), as used for content codings.
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|c| c.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&c))
+}
+
+type ContentDecoderFn = dyn Fn(Box<dyn io::Read + Send + Sync>) -> Box<dyn io::Read + Send + Sync>
+    + Send
+    + Sync
+    + 'static;
+
+/// Decoder for a custom content encoding.
+#[derive(Clone)]
+pub(crate) struct ContentDecoderHandler(Arc<ContentDecoderFn>);
+
+impl ContentDecoderHandler {
+    pub(crate) fn call(
+        &self,
+        reader: Box<dyn io::Read + Send + Sync>,
+    ) -> Box<dyn io::Read + Send + Sync> {
+        (self.0)(reader)
+    }
+}
+
+impl fmt::Debug for ContentDecoderHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ContentDecoderHandler").finish()
+    }
+}
+
+type DownloadProgressFn = dyn Fn(&DownloadProgress) + Send + Sync + 'static;
+
+/// Callback for download progress.
+#[derive(Clone)]
+pub(crate) struct DownloadProgressHandler(Arc<DownloadProgressFn>);
+
+impl DownloadProgressHandler {
+    pub(crate) fn call(&self, progress: &DownloadProgress) {
+        (self.0)(progress)
+    }
+}
+
+impl fmt::Debug for DownloadProgressHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DownloadProgressHandler").finish()
+    }
+}
+
+/// Progress of receiving a response body.
+///
+/// Passed to the callback set in [`ConfigBuilder::download_progress()`].
+#[derive(Debug, Clone, Default)]
+pub struct DownloadProgress {
+    pub(crate) raw: u64,
+    pub(crate) decoded: u64,
+    pub(crate) content_length: Option<u64>,
+}
+
+impl DownloadProgress {
+    /// Bytes read from the connection for the body so far.
+    ///
+    /// This includes the framing of `transfer-encoding: chunked`.
+    pub fn raw(&self) -> u64 {
+        self.raw
+    }
+
+    /// Body bytes received so far, without the chunked framing.
+    ///
+    /// This is what the `Content-Length` counts. It's before any decompression,
+    /// like gzip.
+    pub fn decoded(&self) -> u64 {
+        self.decoded
+    }
+
+    /// The `Content-Length` of the body, if sent.
+    pub fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+}
+
 pub(crate) static DEFAULT_USER_AGENT: &str =
     concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
 
@@ -569,19 +1113,35 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             http_status_as_error: true,
+            status_error_policy: None,
+            status_error_response: false,
             https_only: false,
             ip_family: IpFamily::Any,
             #[cfg(feature = "_tls")]
             tls_config: TlsConfig::default(),
             proxy: Proxy::try_from_env(),
+            #[cfg(feature = "http2")]
+            http2: false,
+            #[cfg(feature = "http2")]
+            http2_prior_knowledge: false,
             no_delay: true,
             max_redirects: 10,
             redirect_auth_headers: RedirectAuthHeaders::Never,
+            redirect_policy: None,
+            informational_handler: None,
+            upload_progress: None,
+            download_progress: None,
             user_agent: AutoHeaderValue::default(),
             accept: AutoHeaderValue::default(),
             accept_encoding: AutoHeaderValue::default(),
+            content_decoders: vec![],
+            decode_content: true,
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            body_compression: None,
             timeouts: Timeouts::default(),
             max_response_header_size: 64 * 1024,
+            max_body_size: MAX_BODY_SIZE,
+            max_decompressed_size: MAX_DECOMPRESSED_SIZE,
             input_buffer_size: 128 * 1024,
             output_buffer_size: 128 * 1024,
             max_idle_connections: 10,
@@ -614,15 +1174,25 @@ impl fmt::Debug for Config {
         let mut dbg = f.debug_struct("Config");
 
         dbg.field("http_status_as_error", &self.http_status_as_error)
+            .field("status_error_policy", &self.status_error_policy)
+            .field("status_error_response", &self.status_error_response)
             .field("https_only", &self.https_only)
             .field("ip_family", &self.ip_family)
             .field("proxy", &self.proxy)
             .field("no_delay", &self.no_delay)
             .field("max_redirects", &self.max_redirects)
             .field("redirect_auth_headers", &self.redirect_auth_headers)
+            .field("redirect_policy", &self.redirect_policy)
+            .field("informational_handler", &self.informational_handler)
+            .field("upload_progress", &self.upload_progress)
+            .field("download_progress", &self.download_progress)
             .field("user_agent", &self.user_agent)
+            .field("content_decoders", &self.content_decoders)
+            .field("decode_content", &self.decode_content)
             .field("timeouts", &self.timeouts)
             .field("max_response_header_size", &self.max_response_header_size)
+            .field("max_body_size", &self.max_body_size)
+            .field("max_decompressed_size", &self.max_decompressed_size)
             .field("input_buffer_size", &self.input_buffer_size)
             .field("output_buffer_size", &self.output_buffer_size)
             .field("max_idle_connections", &self.max_idle_connections)
@@ -638,6 +1208,17 @@ impl fmt::Debug for Config {
             dbg.field("tls_config", &self.tls_config);
         }
 
+        #[cfg(feature = "http2")]
+        {
+            dbg.field("http2", &self.http2)
+                .field("http2_prior_knowledge", &self.http2_prior_knowledge);
+        }
+
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        {
+            dbg.field("body_compression", &self.body_compression);
+        }
+
         dbg.finish()
     }
 }
@@ -668,4 +1249,16 @@ mod test {
         let c = Config::default();
         assert_no_alloc(|| c.clone());
     }
+
+    #[test]
+    fn content_decoder_invalid_token() {
+        let c = Config::builder()
+            .content_decoder("x-xor", |r| r)
+            .content_decoder("x xor", |r| r)
+            .content_decoder("x-xor\r\n", |r| r)
+            .content_decoder("", |r| r)
+            .build();
+        let tokens: Vec<_> = c.content_decoders.iter().map(|(t, _)| t).collect();
+        assert_eq!(tokens, ["x-xor"]);
+    }
 }
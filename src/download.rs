@@ -0,0 +1,660 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use http::header::{ACCEPT_ENCODING, ACCEPT_RANGES, CONTENT_RANGE, ETAG};
+use http::header::{IF_RANGE, LAST_MODIFIED, RANGE};
+use http::{HeaderMap, Response, StatusCode, Uri};
+
+use crate::http;
+use crate::util::HeaderMapExt;
+use crate::{Agent, Body, Error};
+
+/// Download to `path`, resuming from a previous partial download.
+///
+/// The data is written to `<path>.part`, and the validator (ETag or Last-Modified) for
+/// resuming to `<path>.part.meta`. Once complete, the part file is renamed to `path`.
+pub(crate) fn download(agent: &Agent, uri: &Uri, path: &Path) -> Result<u64, Error> {
+    let part = with_suffix(path, ".part");
+    let meta = with_suffix(path, ".part.meta");
+
+    let existing = fs::metadata(&part).map(|m| m.len()).unwrap_or(0);
+    let validator = fs::read_to_string(&meta).ok().filter(|v| !v.is_empty());
+
+    // Without a validator, we can't know the partial file is for the same content.
+    let mut resume = match validator {
+        Some(v) if existing > 0 => Some((existing, v)),
+        _ => None,
+    };
+
+    loop {
+        let mut response = send(agent, uri, resume.as_ref())?;
+        let status = response.status();
+
+        if let Some((from, _)) = &resume {
+            let range = content_range(response.headers());
+
+            match status {
+                StatusCode::PARTIAL_CONTENT => {
+                    if let Some((start, total)) = range.filter(|r| r.0 == *from) {
+                        debug!("Resume download at: {}", start);
+                        let file = OpenOptions::new().append(true).open(&part)?;
+                        return finish(&mut response, file, start, total, &part, &meta, path);
+                    }
+                    debug!("Unexpected Content-Range, restart download");
+                    resume = None;
+                    continue;
+                }
+                StatusCode::RANGE_NOT_SATISFIABLE => {
+                    // The part file already holds everything.
+                    if range.and_then(|r| r.1) == Some(*from) {
+                        return complete(&part, &meta, path, *from);
+                    }
+                    debug!("Range not satisfiable, restart download");
+                    resume = None;
+                    continue;
+                }
+                // The server ignored the Range, or the content changed.
+                _ => debug!("Range not honored, restart download"),
+            }
+        }
+
+        if status != StatusCode::OK {
+            return Err(agent.config().status_error(response));
+        }
+
+        // Remember the validator before receiving any data.
+        match validator_of(response.headers()) {
+            Some(v) => fs::write(&meta, v)?,
+            None => remove_if_exists(&meta)?,
+        }
+
+        let total = response.headers().content_length();
+        let file = File::create(&part)?;
+
+        return finish(&mut response, file, 0, total, &part, &meta, path);
+    }
+}
+
+/// Attempts per segment before giving up.
+const SEGMENT_ATTEMPTS: usize = 3;
+
+/// Download to `path` in `segments` ranges, fetched concurrently.
+///
+/// Falls back on [`download()`] if the server doesn't advertise ranges and a length.
+pub(crate) fn download_segmented(
+    agent: &Agent,
+    uri: &Uri,
+    path: &Path,
+    segments: usize,
+) -> Result<u64, Error> {
+    let probe = agent
+        .head(uri.clone())
+        .header(ACCEPT_ENCODING, "identity")
+        .call()?;
+
+    let headers = probe.headers();
+    let accepts_ranges = headers
+        .get(ACCEPT_RANGES)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(',').any(|u| u.trim().eq_ignore_ascii_case("bytes")))
+        .unwrap_or(false);
+
+    let len = match headers.content_length() {
+        Some(len) if accepts_ranges && segments > 1 && len > 0 => len,
+        _ => {
+            debug!("Server doesn't support segments, download in one go");
+            return download(agent, uri, path);
+        }
+    };
+
+    let validator = validator_of(headers).map(|v| v.to_string());
+
+    let part = with_suffix(path, ".part");
+
+    // The part file is not resumable by download().
+    remove_if_exists(&with_suffix(path, ".part.meta"))?;
+
+    let file = File::create(&part)?;
+    file.set_len(len)?;
+
+    let result =
+        fetch_segments(agent, uri, &part, len, segments, validator.as_deref()).and_then(|_| {
+            file.sync_all()?;
+            Ok(())
+        });
+
+    if let Err(e) = result {
+        drop(file);
+        let _ = fs::remove_file(&part);
+        return Err(e);
+    }
+
+    fs::rename(&part, path)?;
+
+    Ok(len)
+}
+
+fn fetch_segments(
+    agent: &Agent,
+    uri: &Uri,
+    part: &Path,
+    len: u64,
+    segments: usize,
+    validator: Option<&str>,
+) -> Result<(), Error> {
+    // Don't make segments smaller than a byte.
+    let count = (segments as u64).min(len);
+    let size = len / count;
+
+    let ranges: Vec<(u64, u64)> = (0..count)
+        .map(|i| {
+            let start = i * size;
+            let end = if i == count - 1 { len } else { start + size };
+            (start, end)
+        })
+        .collect();
+
+    let written = thread::scope(|s| {
+        let handles: Vec<_> = ranges
+            .iter()
+            .map(|&(start, end)| {
+                s.spawn(move || fetch_segment(agent, uri, part, start, end, validator))
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("segment thread to not panic"))
+            .sum::<Result<u64, Error>>()
+    })?;
+
+    if written != len {
+        let msg = format!("download incomplete: {} of {} bytes", written, len);
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, msg).into());
+    }
+
+    Ok(())
+}
+
+/// Fetch the range `start..end` into the same offset of the part file.
+fn fetch_segment(
+    agent: &Agent,
+    uri: &Uri,
+    part: &Path,
+    start: u64,
+    end: u64,
+    validator: Option<&str>,
+) -> Result<u64, Error> {
+    let mut file = OpenOptions::new().write(true).open(part)?;
+    let mut pos = start;
+    let mut attempt = 1;
+
+    loop {
+        match fetch_range(agent, uri, &mut file, &mut pos, end, validator) {
+            Ok(()) => return Ok(pos - start),
+            Err(Failure::Transfer(e)) if attempt < SEGMENT_ATTEMPTS && e.is_retryable() => {
+                debug!("Segment {}-{} failed ({}), retry at {}", start, end, e, pos);
+                attempt += 1;
+            }
+            Err(Failure::Transfer(e)) => return Err(e),
+            Err(Failure::File(e)) => return Err(e.into()),
+        }
+    }
+}
+
+/// Why fetching a range failed.
+///
+/// Errors writing the part file, such as a full disk, are not fixed by fetching again.
+enum Failure {
+    Transfer(Error),
+    File(io::Error),
+}
+
+impl From<Error> for Failure {
+    fn from(e: Error) -> Self {
+        Failure::Transfer(e)
+    }
+}
+
+fn fetch_range(
+    agent: &Agent,
+    uri: &Uri,
+    file: &mut File,
+    pos: &mut u64,
+    end: u64,
+    validator: Option<&str>,
+) -> Result<(), Failure> {
+    let mut request = agent
+        .get(uri.clone())
+        .header(ACCEPT_ENCODING, "identity")
+        .header(RANGE, format!("bytes={}-{}", *pos, end - 1));
+
+    if let Some(v) = validator {
+        request = request.header(IF_RANGE, v);
+    }
+
+    let mut response = request.call()?;
+
+    // A 200 means the server ignored the range, or the content changed.
+    let range = content_range(response.headers());
+    if response.status() != StatusCode::PARTIAL_CONTENT || range.map(|r| r.0) != Some(*pos) {
+        let msg = format!("range not honored: {}", response.status());
+        return Err(Error::from(io::Error::new(io::ErrorKind::InvalidData, msg)).into());
+    }
+
+    file.seek(SeekFrom::Start(*pos)).map_err(Failure::File)?;
+
+    let mut reader = response.body_mut().as_reader().take(end - *pos);
+    let mut buf = vec![0; 64 * 1024];
+
+    loop {
+        let n = reader.read(&mut buf).map_err(Error::from)?;
+        if n == 0 {
+            break;
+        }
+        file.write_all(&buf[..n]).map_err(Failure::File)?;
+        // Progress is kept for a retry.
+        *pos += n as u64;
+    }
+
+    if *pos != end {
+        return Err(Error::disconnected().into());
+    }
+
+    Ok(())
+}
+
+fn send(agent: &Agent, uri: &Uri, resume: Option<&(u64, String)>) -> Result<Response<Body>, Error> {
+    // Range is about the encoded bytes, which must not be decompressed.
+    let mut request = agent
+        .get(uri.clone())
+        .header(ACCEPT_ENCODING, "identity")
+        // 416 is handled as part of resuming.
+        .config()
+        .http_status_as_error(false)
+        .build();
+
+    if let Some((from, validator)) = resume {
+        request = request
+            .header(RANGE, format!("bytes={}-", from))
+            .header(IF_RANGE, validator.as_str());
+    }
+
+    request.call()
+}
+
+fn finish(
+    response: &mut Response<Body>,
+    mut file: File,
+    offset: u64,
+    total: Option<u64>,
+    part: &Path,
+    meta: &Path,
+    path: &Path,
+) -> Result<u64, Error> {
+    // A failure here leaves the part file to resume from.
+    let written = io::copy(&mut response.body_mut().as_reader(), &mut file)?;
+    file.sync_all()?;
+
+    let len = offset + written;
+
+    if let Some(total) = total {
+        if len != total {
+            let msg = format!("download incomplete: {} of {} bytes", len, total);
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, msg).into());
+        }
+    }
+
+    complete(part, meta, path, len)
+}
+
+fn complete(part: &Path, meta: &Path, path: &Path, len: u64) -> Result<u64, Error> {
+    fs::rename(part, path)?;
+    remove_if_exists(meta)?;
+    Ok(len)
+}
+
+/// Parse `Content-Range: bytes <start>-<end>/<total>` into start and total.
+///
+/// For a 416 response, the form is `bytes */<total>`, and start is 0.
+fn content_range(headers: &HeaderMap) -> Option<(u64, Option<u64>)> {
+    let v = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+    let v = v.trim().strip_prefix("bytes ")?;
+    let (range, total) = v.split_once('/')?;
+
+    let total = match total.trim() {
+        "*" => None,
+        t => Some(t.parse().ok()?),
+    };
+
+    let start = match range.trim() {
+        "*" => 0,
+        r => {
+            let (start, end) = r.split_once('-')?;
+            let start: u64 = start.parse().ok()?;
+            let end: u64 = end.parse().ok()?;
+            if end < start || total.map(|t| end >= t).unwrap_or(false) {
+                return None;
+            }
+            start
+        }
+    };
+
+    Some((start, total))
+}
+
+/// The validator for If-Range. Weak ETags can't be used for ranges.
+fn validator_of(headers: &HeaderMap) -> Option<&str> {
+    let etag = headers
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.starts_with("W/"));
+
+    etag.or_else(|| headers.get(LAST_MODIFIED).and_then(|v| v.to_str().ok()))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    name.into()
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(all(test, feature = "_test"))]
+mod test {
+    use super::*;
+    use crate::transport::set_handler_fn;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const CONTENT: &[u8] = b"0123456789";
+
+    /// Path in a directory of its own, which is removed on drop.
+    struct TempPath(PathBuf);
+
+    impl std::ops::Deref for TempPath {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl AsRef<Path> for TempPath {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            if let Some(dir) = self.0.parent() {
+                let _ = fs::remove_dir_all(dir);
+            }
+        }
+    }
+
+    fn temp_path(name: &str) -> TempPath {
+        let dir =
+            std::env::temp_dir().join(format!("ureq-download-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TempPath(dir.join(name))
+    }
+
+    /// Serves CONTENT with an ETag, honoring Range when If-Range matches `etag`.
+    fn serve(pattern: &'static str, etag: &'static str, honor_range: bool) {
+        set_handler_fn(pattern, move |_uri, req, w| {
+            let header = |name: &str| {
+                req.headers()
+                    .get(name)
+                    .map(|v| v.to_str().unwrap().to_string())
+            };
+
+            assert_eq!(header("accept-encoding").as_deref(), Some("identity"));
+
+            let range = header("range").and_then(|r| {
+                let from: usize = r.strip_prefix("bytes=")?.strip_suffix('-')?.parse().ok()?;
+                let matches = header("if-range").as_deref() == Some(etag);
+                (honor_range && matches).then_some(from)
+            });
+
+            match range {
+                Some(from) if from >= CONTENT.len() => write!(
+                    w,
+                    "HTTP/1.1 416 Range Not Satisfiable\r\n\
+                    content-range: bytes */{}\r\n\
+                    content-length: 0\r\n\r\n",
+                    CONTENT.len()
+                ),
+                Some(from) => {
+                    write!(
+                        w,
+                        "HTTP/1.1 206 Partial Content\r\n\
+                        etag: {}\r\n\
+                        content-range: bytes {}-{}/{}\r\n\
+                        content-length: {}\r\n\r\n",
+                        etag,
+                        from,
+                        CONTENT.len() - 1,
+                        CONTENT.len(),
+                        CONTENT.len() - from
+                    )?;
+                    w.write_all(&CONTENT[from..])
+                }
+                None => {
+                    write!(
+                        w,
+                        "HTTP/1.1 200 OK\r\n\
+                        etag: {}\r\n\
+                        content-length: {}\r\n\r\n",
+                        etag,
+                        CONTENT.len()
+                    )?;
+                    w.write_all(CONTENT)
+                }
+            }
+        });
+    }
+
+    fn partial(path: &Path, data: &[u8], validator: &str) {
+        fs::write(with_suffix(path, ".part"), data).unwrap();
+        fs::write(with_suffix(path, ".part.meta"), validator).unwrap();
+    }
+
+    #[test]
+    fn download_full() {
+        serve("/full", "\"v1\"", true);
+        let path = temp_path("full");
+
+        let len = Agent::new_with_defaults()
+            .download("https://my.test/full", &path)
+            .unwrap();
+
+        assert_eq!(len, 10);
+        assert_eq!(fs::read(&path).unwrap(), CONTENT);
+        assert!(!with_suffix(&path, ".part").exists());
+        assert!(!with_suffix(&path, ".part.meta").exists());
+    }
+
+    #[test]
+    fn download_resume() {
+        serve("/resume", "\"v1\"", true);
+        let path = temp_path("resume");
+        partial(&path, b"01234", "\"v1\"");
+
+        let len = Agent::new_with_defaults()
+            .download("https://my.test/resume", &path)
+            .unwrap();
+
+        assert_eq!(len, 10);
+        assert_eq!(fs::read(&path).unwrap(), CONTENT);
+    }
+
+    #[test]
+    fn download_resume_already_complete() {
+        serve("/complete", "\"v1\"", true);
+        let path = temp_path("complete");
+        partial(&path, CONTENT, "\"v1\"");
+
+        let len = Agent::new_with_defaults()
+            .download("https://my.test/complete", &path)
+            .unwrap();
+
+        assert_eq!(len, 10);
+        assert_eq!(fs::read(&path).unwrap(), CONTENT);
+    }
+
+    #[test]
+    fn download_changed_content() {
+        serve("/changed", "\"v2\"", true);
+        let path = temp_path("changed");
+        partial(&path, b"abcde", "\"v1\"");
+
+        Agent::new_with_defaults()
+            .download("https://my.test/changed", &path)
+            .unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), CONTENT);
+    }
+
+    #[test]
+    fn download_range_ignored() {
+        serve("/ignored", "\"v1\"", false);
+        let path = temp_path("ignored");
+        partial(&path, b"01234", "\"v1\"");
+
+        Agent::new_with_defaults()
+            .download("https://my.test/ignored", &path)
+            .unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), CONTENT);
+    }
+
+    /// Serves 100 bytes in ranges of `bytes=<start>-<end>`.
+    ///
+    /// The first `truncate` range responses are cut short, to test retries.
+    fn serve_ranges(pattern: &'static str, accept_ranges: bool, truncate: usize) -> Vec<u8> {
+        let content: Vec<u8> = (0..100).collect();
+        let served = content.clone();
+        let truncated = AtomicUsize::new(0);
+
+        set_handler_fn(pattern, move |_uri, req, w| {
+            let ranges = if accept_ranges { "bytes" } else { "none" };
+
+            if req.method() == "HEAD" {
+                return write!(
+                    w,
+                    "HTTP/1.1 200 OK\r\n\
+                    accept-ranges: {}\r\n\
+                    etag: \"v1\"\r\n\
+                    content-length: {}\r\n\r\n",
+                    ranges,
+                    served.len()
+                );
+            }
+
+            let range = req.headers().get("range").and_then(|r| {
+                let r = r.to_str().ok()?.strip_prefix("bytes=")?;
+                let (start, end) = r.split_once('-')?;
+                Some((start.parse::<usize>().ok()?, end.parse::<usize>().ok()?))
+            });
+
+            let Some((start, end)) = range else {
+                write!(
+                    w,
+                    "HTTP/1.1 200 OK\r\n\
+                    content-length: {}\r\n\r\n",
+                    served.len()
+                )?;
+                return w.write_all(&served);
+            };
+
+            assert_eq!(req.headers().get("if-range").unwrap(), "\"v1\"");
+
+            write!(
+                w,
+                "HTTP/1.1 206 Partial Content\r\n\
+                content-range: bytes {}-{}/{}\r\n\
+                content-length: {}\r\n\r\n",
+                start,
+                end,
+                served.len(),
+                end + 1 - start
+            )?;
+
+            if truncated.fetch_add(1, Ordering::SeqCst) < truncate {
+                // Half the body, then the connection closes.
+                w.write_all(&served[start..start + (end + 1 - start) / 2])
+            } else {
+                w.write_all(&served[start..=end])
+            }
+        });
+
+        content
+    }
+
+    #[test]
+    fn download_segmented() {
+        let content = serve_ranges("/segmented", true, 0);
+        let path = temp_path("segmented");
+
+        let len = Agent::new_with_defaults()
+            .download_segmented("https://my.test/segmented", &path, 4)
+            .unwrap();
+
+        assert_eq!(len, 100);
+        assert_eq!(fs::read(&path).unwrap(), content);
+        assert!(!with_suffix(&path, ".part").exists());
+    }
+
+    #[test]
+    fn download_segmented_no_ranges() {
+        let content = serve_ranges("/segmented-none", false, 0);
+        let path = temp_path("segmented-none");
+
+        let len = Agent::new_with_defaults()
+            .download_segmented("https://my.test/segmented-none", &path, 4)
+            .unwrap();
+
+        assert_eq!(len, 100);
+        assert_eq!(fs::read(&path).unwrap(), content);
+    }
+
+    #[test]
+    fn download_segmented_retry() {
+        let content = serve_ranges("/segmented-retry", true, 2);
+        let path = temp_path("segmented-retry");
+
+        let len = Agent::new_with_defaults()
+            .download_segmented("https://my.test/segmented-retry", &path, 4)
+            .unwrap();
+
+        assert_eq!(len, 100);
+        assert_eq!(fs::read(&path).unwrap(), content);
+    }
+
+    #[test]
+    fn parse_content_range() {
+        let parse = |v: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_RANGE, v.parse().unwrap());
+            content_range(&headers)
+        };
+
+        assert_eq!(parse("bytes 5-9/10"), Some((5, Some(10))));
+        assert_eq!(parse("bytes 5-9/*"), Some((5, None)));
+        assert_eq!(parse("bytes */10"), Some((0, Some(10))));
+        assert_eq!(parse("bytes 5-10/10"), None);
+        assert_eq!(parse("bytes 9-5/10"), None);
+        assert_eq!(parse("items 5-9/10"), None);
+    }
+}
@@ -1,7 +1,14 @@
+use std::borrow::Cow;
+use std::io::Read;
 use std::{fmt, io};
 
+use http::{HeaderMap, Response, StatusCode};
+
 use crate::http;
-use crate::Timeout;
+use crate::{Body, Timeout};
+
+/// How much of the body of a 4xx or 5xx response to keep in [`ErrorResponse`].
+const ERROR_BODY_LIMIT: u64 = 64 * 1024;
 
 /// Errors from ureq.
 #[derive(Debug)]
@@ -13,6 +20,12 @@ pub enum Error {
     /// This is the default behavior.
     StatusCode(u16),
 
+    /// Instead of [`Error::StatusCode`] when
+    /// [`status_error_response()`](crate::config::ConfigBuilder::status_error_response) is true.
+    ///
+    /// The [`ErrorResponse`] has the headers, and the start of the body.
+    StatusResponse(Box<ErrorResponse>),
+
     /// Errors arising from the http-crate.
     ///
     /// These errors happen for things like invalid characters in header names.
@@ -45,6 +58,16 @@ pub enum Error {
     /// redirect the POST body and instead raises this error.
     RedirectFailed,
 
+    /// A redirect was refused by the [`redirect_policy()`](crate::config::ConfigBuilder::redirect_policy).
+    ///
+    /// Holds the value of the `Location` header.
+    RedirectDenied(String),
+
+    /// The response is not a `101 Switching Protocols`.
+    ///
+    /// Returned by [`Body::into_upgraded()`](crate::Body::into_upgraded).
+    NotUpgraded,
+
     /// Error when creating proxy settings.
     InvalidProxyUrl,
 
@@ -54,6 +77,10 @@ pub enum Error {
     /// A send body (Such as `&str`) is larger than the `content-length` header.
     BodyExceedsLimit(u64),
 
+    /// A decompressed response body is larger than the
+    /// [`max_decompressed_size`](crate::config::ConfigBuilder::max_decompressed_size).
+    DecompressedBodyExceedsLimit(u64),
+
     /// Some error with TLS.
     #[cfg(feature = "_tls")]
     Tls(&'static str),
@@ -121,14 +148,35 @@ pub enum Error {
     /// The response header, from status up until body, is too big.
     LargeResponseHeader(usize, usize),
 
-    /// Body decompression failed (gzip or brotli).
-    #[cfg(any(feature = "gzip", feature = "brotli"))]
+    /// A response has stacked `Content-Encoding` where one of them can't be decoded.
+    ///
+    /// Holds the unsupported encoding.
+    UnsupportedContentEncoding(String),
+
+    /// A `multipart/*` body could not be parsed, or the body is not multipart.
+    ///
+    /// Also when a [`Multipart`](crate::Multipart) form has an invalid part.
+    Multipart(&'static str),
+
+    /// The response for an [`EventSource`](crate::EventSource) is not an event stream.
+    EventStream(&'static str),
+
+    /// Body decompression failed (gzip, deflate, brotli or zstd).
+    #[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
     Decompress(&'static str, io::Error),
 
     /// Serde JSON error.
     #[cfg(feature = "json")]
     Json(serde_json::Error),
 
+    /// A WebSocket handshake or protocol error.
+    #[cfg(feature = "websocket")]
+    WebSocket(&'static str),
+
+    /// An HTTP/2 protocol error.
+    #[cfg(feature = "http2")]
+    Http2(&'static str),
+
     /// Attempt to connect to a CONNECT proxy failed.
     ConnectProxyFailed(String),
 
@@ -154,11 +202,169 @@ impl Error {
         }
     }
 
+    /// The status code of [`Error::StatusCode`] or [`Error::StatusResponse`].
+    ///
+    /// ```
+    /// # fn no_run() {
+    /// let err = ureq::get("http://httpbin.org/status/500").call().unwrap_err();
+    /// assert_eq!(err.status_code(), Some(500));
+    /// # }
+    /// ```
+    pub fn status_code(&self) -> Option<u16> {
+        match self {
+            Error::StatusCode(v) => Some(*v),
+            Error::StatusResponse(v) => Some(v.status().as_u16()),
+            _ => None,
+        }
+    }
+
+    /// The response of [`Error::StatusResponse`].
+    pub fn response(&self) -> Option<&ErrorResponse> {
+        match self {
+            Error::StatusResponse(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Whether making the request again might succeed.
+    pub(crate) fn is_retryable(&self) -> bool {
+        match self {
+            // InvalidData is a response we can't use, which won't change by retrying.
+            Error::Io(e) => e.kind() != io::ErrorKind::InvalidData,
+            Error::Timeout(_) | Error::ConnectionFailed => true,
+            _ => self.status_code().map(|v| v >= 500).unwrap_or(false),
+        }
+    }
+
     pub(crate) fn disconnected() -> Error {
         io::Error::new(io::ErrorKind::UnexpectedEof, "Peer disconnected").into()
     }
 }
 
+/// A 4xx or 5xx response, as held by [`Error::StatusResponse`].
+///
+/// The body is read up to 64kB, which is enough for the error details APIs
+/// typically send.
+///
+/// ```
+/// use ureq::Error;
+///
+/// # fn no_run() -> Result<(), Error> {
+/// let result = ureq::get("http://httpbin.org/status/429")
+///     .config()
+///     .status_error_response(true)
+///     .build()
+///     .call();
+///
+/// match result {
+///     Err(Error::StatusResponse(res)) => {
+///         let retry_after = res.headers().get("retry-after");
+///         println!("{}: {} (retry after {:?})", res.status(), res.body_text(), retry_after);
+///     }
+///     r => { r?; }
+/// }
+/// # Ok(())}
+/// ```
+pub struct ErrorResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Vec<u8>,
+    truncated: bool,
+}
+
+impl ErrorResponse {
+    /// Keep the status, headers and start of the body of a response.
+    pub(crate) fn new(mut response: Response<Body>) -> Self {
+        let mut body = Vec::new();
+        let reader = response.body_mut().as_reader();
+
+        // The status is the error. Failing to read the details shouldn't hide it.
+        if let Err(e) = reader.take(ERROR_BODY_LIMIT + 1).read_to_end(&mut body) {
+            debug!("Failed to read error response body: {}", e);
+        }
+
+        let truncated = body.len() as u64 > ERROR_BODY_LIMIT;
+        body.truncate(ERROR_BODY_LIMIT as usize);
+
+        let (parts, _) = response.into_parts();
+
+        ErrorResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+            truncated,
+        }
+    }
+
+    /// The response status.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The response headers.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The body, after decompression, up to 64kB.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// The body as text, replacing invalid UTF-8.
+    pub fn body_text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.body)
+    }
+
+    /// Whether the body was longer than what is kept.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Deserialize the body as JSON, into the error type of an API.
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct ApiError {
+    ///     message: String,
+    /// }
+    ///
+    /// # fn no_run() -> Result<(), ureq::Error> {
+    /// let err = ureq::get("http://httpbin.org/status/400")
+    ///     .config()
+    ///     .status_error_response(true)
+    ///     .build()
+    ///     .call()
+    ///     .unwrap_err();
+    ///
+    /// if let Some(res) = err.response() {
+    ///     let api_error: ApiError = res.body_json()?;
+    ///     println!("{}", api_error.message);
+    /// }
+    /// # Ok(())}
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn body_json<T: serde::de::DeserializeOwned>(&self) -> Result<T, Error> {
+        if self.truncated {
+            return Err(Error::BodyExceedsLimit(ERROR_BODY_LIMIT));
+        }
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
+
+impl fmt::Debug for ErrorResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorResponse")
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .field("body_len", &self.body.len())
+            .field("truncated", &self.truncated)
+            .finish()
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Self {
         let is_wrapped_ureq_error = e.get_ref().map(|x| x.is::<Error>()).unwrap_or(false);
@@ -178,6 +384,7 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::StatusCode(v) => write!(f, "http status: {}", v),
+            Error::StatusResponse(v) => write!(f, "http status: {}", v.status.as_u16()),
             Error::Http(v) => write!(f, "http: {}", v),
             Error::BadUri(v) => write!(f, "bad uri: {}", v),
             Error::Protocol(v) => write!(f, "protocol: {}", v),
@@ -185,11 +392,20 @@ impl fmt::Display for Error {
             Error::Timeout(v) => write!(f, "timeout: {}", v),
             Error::HostNotFound => write!(f, "host not found"),
             Error::RedirectFailed => write!(f, "redirect failed"),
+            Error::RedirectDenied(v) => write!(f, "redirect denied: {}", v),
+            Error::NotUpgraded => write!(f, "response is not a protocol upgrade"),
             Error::InvalidProxyUrl => write!(f, "invalid proxy url"),
             Error::ConnectionFailed => write!(f, "connection failed"),
             Error::BodyExceedsLimit(v) => {
                 write!(f, "the response body is larger than request limit: {}", v)
             }
+            Error::DecompressedBodyExceedsLimit(v) => {
+                write!(
+                    f,
+                    "the decompressed response body is larger than limit: {}",
+                    v
+                )
+            }
             #[cfg(feature = "_tls")]
             Error::Tls(v) => write!(f, "{}", v),
             #[cfg(feature = "_tls")]
@@ -212,10 +428,19 @@ impl fmt::Display for Error {
             Error::LargeResponseHeader(x, y) => {
                 write!(f, "response header is too big: {} > {}", x, y)
             }
-            #[cfg(any(feature = "gzip", feature = "brotli"))]
+            Error::UnsupportedContentEncoding(v) => {
+                write!(f, "unsupported content-encoding: {}", v)
+            }
+            #[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
             Error::Decompress(x, y) => write!(f, "{} decompression failed: {}", x, y),
             #[cfg(feature = "json")]
             Error::Json(v) => write!(f, "json: {}", v),
+            Error::Multipart(v) => write!(f, "multipart: {}", v),
+            Error::EventStream(v) => write!(f, "event stream: {}", v),
+            #[cfg(feature = "websocket")]
+            Error::WebSocket(v) => write!(f, "websocket: {}", v),
+            #[cfg(feature = "http2")]
+            Error::Http2(v) => write!(f, "http2: {}", v),
             Error::ConnectProxyFailed(v) => write!(f, "CONNECT proxy failed: {}", v),
             Error::BodyStalled => write!(f, "body data reading stalled"),
         }
@@ -304,6 +529,64 @@ mod test {
             .call()
             .unwrap_err();
         assert!(matches!(err, Error::StatusCode(500)));
+        assert_eq!(err.status_code(), Some(500));
+    }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn status_response_error() {
+        use crate::test::init_test_log;
+        use crate::transport::set_handler;
+        init_test_log();
+        set_handler(
+            "/status/429",
+            429,
+            &[("retry-after", "5"), ("content-length", "18")],
+            b"{\"error\":\"limit\"}",
+        );
+        let err = crate::get("http://example.org/status/429")
+            .config()
+            .status_error_response(true)
+            .build()
+            .call()
+            .unwrap_err();
+
+        let res = err.response().unwrap();
+        assert_eq!(res.status(), 429);
+        assert_eq!(res.headers()["retry-after"], "5");
+        assert_eq!(res.body_text(), r#"{"error":"limit"}"#);
+        assert!(!res.is_truncated());
+
+        #[cfg(feature = "json")]
+        {
+            let value: serde_json::Value = res.body_json().unwrap();
+            assert_eq!(value["error"], "limit");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn status_response_truncated() {
+        use crate::test::init_test_log;
+        use crate::transport::set_handler;
+        init_test_log();
+        let body = vec![b'x'; 100_000];
+        set_handler(
+            "/status/500-large",
+            500,
+            &[("content-length", "100000")],
+            &body,
+        );
+        let err = crate::get("http://example.org/status/500-large")
+            .config()
+            .status_error_response(true)
+            .build()
+            .call()
+            .unwrap_err();
+
+        let res = err.response().unwrap();
+        assert_eq!(res.body().len(), 64 * 1024);
+        assert!(res.is_truncated());
     }
 
     #[test]
@@ -0,0 +1,804 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use super::frame::{self, FrameHeader, HEADER_LEN, MAX_FRAME_SIZE, MAX_WINDOW};
+use super::hpack::{self, Decoder, Field};
+use crate::http::{HeaderName, HeaderValue};
+use crate::transport::time::{Duration, Instant};
+use crate::transport::{NextTimeout, Transport};
+use crate::Error;
+
+/// The receive window we advertise per stream.
+const STREAM_WINDOW: u32 = 1024 * 1024;
+
+/// The receive window for the entire connection.
+const CONNECTION_WINDOW: u32 = 4 * STREAM_WINDOW;
+
+/// The default receive window before SETTINGS (RFC 9113, 6.9.2).
+const DEFAULT_WINDOW: u32 = 65_535;
+
+/// Longest time a thread holds the transport to wait for input.
+///
+/// A read can't be interrupted to let another stream write. Reading in slices bounds how
+/// long a write waits for a stream that idles.
+const READ_SLICE: std::time::Duration = std::time::Duration::from_millis(20);
+
+/// A connection shared by all streams multiplexed over it.
+///
+/// There is no background thread. The thread of whichever stream needs input reads
+/// frames for all streams, while the others wait for it to hand back the transport.
+pub(crate) struct Http2Connection {
+    inner: Mutex<Inner>,
+    cond: Condvar,
+    tls_handshake: Option<std::time::Duration>,
+}
+
+/// What the remote sent on a stream, in order.
+#[derive(Debug)]
+pub(crate) enum Event {
+    Headers {
+        fields: Vec<Field>,
+        end_stream: bool,
+    },
+    Data {
+        data: Vec<u8>,
+        end_stream: bool,
+    },
+    Reset(u32),
+    /// The header fields were malformed, and we reset the stream.
+    Malformed,
+}
+
+struct Inner {
+    /// `None` while a thread is reading or writing.
+    transport: Option<Box<dyn Transport>>,
+    /// Threads waiting to write, which go before threads waiting to read.
+    waiting_writers: usize,
+    /// Frames to send by the next thread using the transport, like SETTINGS acks.
+    pending: Vec<u8>,
+    /// Received input not yet parsed to frames.
+    input: Vec<u8>,
+    decoder: Decoder,
+    streams: HashMap<u32, Stream>,
+    next_stream_id: u32,
+    /// Header block awaiting CONTINUATION frames: stream id, block, end of stream.
+    continuation: Option<(u32, Vec<u8>, bool)>,
+    max_header_size: usize,
+    peer_max_frame_size: usize,
+    peer_initial_window: i64,
+    peer_max_concurrent_streams: usize,
+    send_window: i64,
+    recv_unacked: u32,
+    goaway: bool,
+    failure: Option<Failure>,
+}
+
+struct Stream {
+    events: VecDeque<Event>,
+    send_window: i64,
+    recv_unacked: u32,
+    remote_closed: bool,
+    local_closed: bool,
+}
+
+/// A connection error, which all streams get.
+#[derive(Clone)]
+enum Failure {
+    Io(io::ErrorKind, String),
+    Protocol(&'static str),
+}
+
+impl Http2Connection {
+    /// Start HTTP/2 on a transport, by sending the connection preface.
+    pub(crate) fn handshake(
+        mut transport: Box<dyn Transport>,
+        max_header_size: usize,
+        timeout: NextTimeout,
+    ) -> Result<Arc<Self>, Error> {
+        let tls_handshake = transport.tls_handshake_duration();
+
+        let mut out = frame::PREFACE.to_vec();
+        frame::write_settings(
+            &mut out,
+            &[
+                (frame::SETTINGS_ENABLE_PUSH, 0),
+                (frame::SETTINGS_INITIAL_WINDOW_SIZE, STREAM_WINDOW),
+            ],
+        );
+        frame::write_window_update(&mut out, 0, CONNECTION_WINDOW - DEFAULT_WINDOW);
+
+        transmit(&mut *transport, &out, timeout)?;
+
+        let inner = Inner {
+            transport: Some(transport),
+            waiting_writers: 0,
+            pending: Vec::new(),
+            input: Vec::new(),
+            decoder: Decoder::new(),
+            streams: HashMap::new(),
+            next_stream_id: 1,
+            continuation: None,
+            max_header_size,
+            peer_max_frame_size: MAX_FRAME_SIZE,
+            peer_initial_window: DEFAULT_WINDOW as i64,
+            // Unlimited until the peer says otherwise.
+            peer_max_concurrent_streams: usize::MAX,
+            send_window: DEFAULT_WINDOW as i64,
+            recv_unacked: 0,
+            goaway: false,
+            failure: None,
+        };
+
+        Ok(Arc::new(Http2Connection {
+            inner: Mutex::new(inner),
+            cond: Condvar::new(),
+            tls_handshake,
+        }))
+    }
+
+    pub(crate) fn tls_handshake_duration(&self) -> Option<std::time::Duration> {
+        self.tls_handshake
+    }
+
+    /// Whether new streams can be opened.
+    pub(crate) fn is_usable(&self) -> bool {
+        self.lock().is_usable()
+    }
+
+    /// Open a stream by sending the request header.
+    pub(crate) fn open_stream(
+        &self,
+        fields: &[(&str, &[u8])],
+        end_stream: bool,
+        timeout: NextTimeout,
+    ) -> Result<u32, Error> {
+        let ready = |inner: &mut Inner| {
+            if !inner.is_usable() {
+                inner.check_failure()?;
+                // A GOAWAY or exhausted ids. Retried on a new connection if pooled.
+                return Err(reset_error(frame::REFUSED_STREAM));
+            }
+            Ok(inner.active_streams() < inner.peer_max_concurrent_streams)
+        };
+
+        self.write(timeout, ready, |inner, out| {
+            let id = inner.next_stream_id;
+            inner.next_stream_id += 2;
+
+            let mut block = Vec::new();
+            hpack::encode(fields.iter().cloned(), &mut block);
+            frame::write_headers(out, id, &block, end_stream, inner.peer_max_frame_size);
+
+            let stream = Stream {
+                events: VecDeque::new(),
+                send_window: inner.peer_initial_window,
+                recv_unacked: 0,
+                remote_closed: false,
+                local_closed: end_stream,
+            };
+            inner.streams.insert(id, stream);
+
+            id
+        })
+    }
+
+    /// Send request body data, waiting for flow control window as needed.
+    pub(crate) fn send_data(
+        &self,
+        id: u32,
+        data: &[u8],
+        end_stream: bool,
+        timeout: NextTimeout,
+    ) -> Result<(), Error> {
+        let mut sent = 0;
+
+        loop {
+            let remaining = data.len() - sent;
+
+            let ready = |inner: &mut Inner| {
+                let window = inner.send_window;
+                let stream = inner.stream(id)?;
+
+                // The server might reset the stream to stop the upload.
+                match stream.events.back() {
+                    Some(Event::Reset(code)) => return Err(reset_error(*code)),
+                    Some(Event::Malformed) => return Err(MALFORMED),
+                    _ => {}
+                }
+
+                // The empty frame to end the stream doesn't need window.
+                Ok(remaining == 0 || stream.send_window.min(window) > 0)
+            };
+
+            sent += self.write(timeout, ready, |inner, out| {
+                let max_frame_size = inner.peer_max_frame_size;
+                let window = inner.send_window;
+                let stream = inner.streams.get_mut(&id).expect("stream checked in ready");
+
+                let window = stream.send_window.min(window) as usize;
+                let amount = remaining.min(window).min(max_frame_size);
+                let last = amount == remaining;
+
+                stream.send_window -= amount as i64;
+
+                let flags = if last && end_stream {
+                    stream.local_closed = true;
+                    frame::FLAG_END_STREAM
+                } else {
+                    0
+                };
+
+                inner.send_window -= amount as i64;
+
+                frame::write(out, frame::DATA, flags, id, &data[sent..sent + amount]);
+
+                amount
+            })?;
+
+            if sent == data.len() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Wait for the next event on a stream.
+    pub(crate) fn next_event(&self, id: u32, timeout: NextTimeout) -> Result<Event, Error> {
+        let (_lock, event) = self.wait(timeout, false, |inner| inner.pop_event(id))?;
+        Ok(event)
+    }
+
+    /// The next event on a stream, if already received.
+    pub(crate) fn try_next_event(&self, id: u32) -> Option<Event> {
+        self.lock().pop_event(id).ok().flatten()
+    }
+
+    /// Forget a stream, resetting it if it's not complete.
+    pub(crate) fn close_stream(&self, id: u32) {
+        let mut inner = self.lock();
+
+        let Some(stream) = inner.streams.remove(&id) else {
+            return;
+        };
+
+        if !(stream.remote_closed && stream.local_closed) {
+            debug!("Cancel HTTP/2 stream: {}", id);
+            frame::write_rst_stream(&mut inner.pending, id, frame::CANCEL);
+        }
+
+        // Might let a stream waiting for a free slot proceed.
+        self.cond.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap()
+    }
+
+    /// Wait until `ready` gives a value, reading frames as needed.
+    ///
+    /// Returns the lock, to continue with the state `ready` saw.
+    fn wait<T>(
+        &self,
+        timeout: NextTimeout,
+        is_writer: bool,
+        mut ready: impl FnMut(&mut Inner) -> Result<Option<T>, Error>,
+    ) -> Result<(MutexGuard<'_, Inner>, T), Error> {
+        let deadline = Instant::now() + timeout.after;
+        let mut inner = self.lock();
+
+        loop {
+            if let Some(v) = ready(&mut inner)? {
+                return Ok((inner, v));
+            }
+
+            inner.check_failure()?;
+
+            let remaining = deadline.duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::Timeout(timeout.reason));
+            }
+
+            // A read holds the transport for a slice, which would starve writes if
+            // readers took turns.
+            let yield_to_writer = !is_writer && inner.waiting_writers > 0;
+
+            let transport = if yield_to_writer {
+                None
+            } else {
+                inner.transport.take()
+            };
+
+            let Some(mut transport) = transport else {
+                // Another thread is using the transport, or is about to.
+                inner = match remaining {
+                    Duration::Exact(v) => self.cond.wait_timeout(inner, v).unwrap().0,
+                    Duration::NotHappening => self.cond.wait(inner).unwrap(),
+                };
+                continue;
+            };
+
+            let pending = mem::take(&mut inner.pending);
+            drop(inner);
+
+            let write_timeout = NextTimeout {
+                after: remaining,
+                reason: timeout.reason,
+            };
+
+            let slice = NextTimeout {
+                after: remaining.min(Duration::Exact(READ_SLICE)),
+                reason: timeout.reason,
+            };
+
+            // A failed write breaks the connection, while a read timeout is only the
+            // end of the slice.
+            let result = match transmit(&mut *transport, &pending, write_timeout) {
+                Ok(()) => match transport.await_input(slice) {
+                    Err(Error::Timeout(_)) => Ok(None),
+                    r => r.map(Some),
+                },
+                Err(e) => Err(e),
+            };
+
+            inner = self.lock();
+
+            let buffers = transport.buffers();
+            let input = buffers.input();
+            inner.input.extend_from_slice(input);
+            let amount = input.len();
+            buffers.input_consume(amount);
+
+            inner.transport = Some(transport);
+
+            match result {
+                Ok(Some(true)) => {
+                    if let Err(e) = inner.process_input() {
+                        inner.fail(e);
+                    }
+                }
+                Ok(Some(false)) => inner.fail(Error::disconnected()),
+                Ok(None) => {}
+                Err(e) => inner.fail(e),
+            }
+
+            self.cond.notify_all();
+        }
+    }
+
+    /// Write frames, once `ready` allows it.
+    fn write<T>(
+        &self,
+        timeout: NextTimeout,
+        mut ready: impl FnMut(&mut Inner) -> Result<bool, Error>,
+        build: impl FnOnce(&mut Inner, &mut Vec<u8>) -> T,
+    ) -> Result<T, Error> {
+        self.lock().waiting_writers += 1;
+
+        let result = self.wait(timeout, true, |inner| {
+            let ok = ready(inner)? && inner.transport.is_some();
+            Ok(ok.then_some(()))
+        });
+
+        let mut inner = match result {
+            Ok((inner, ())) => inner,
+            Err(e) => {
+                self.lock().waiting_writers -= 1;
+                return Err(e);
+            }
+        };
+
+        inner.waiting_writers -= 1;
+
+        let mut out = mem::take(&mut inner.pending);
+        let value = build(&mut inner, &mut out);
+
+        let mut transport = inner.transport.take().expect("transport checked in wait");
+        drop(inner);
+
+        let result = transmit(&mut *transport, &out, timeout);
+
+        let mut inner = self.lock();
+        inner.transport = Some(transport);
+
+        // A partially written frame breaks the connection.
+        if let Err(e) = &result {
+            inner.fail(copy_error(e));
+        }
+
+        self.cond.notify_all();
+
+        result.map(|_| value)
+    }
+}
+
+impl Inner {
+    fn is_usable(&self) -> bool {
+        !self.goaway && self.failure.is_none() && self.next_stream_id < (1 << 31)
+    }
+
+    fn active_streams(&self) -> usize {
+        self.streams
+            .values()
+            .filter(|s| !(s.remote_closed && s.local_closed))
+            .count()
+    }
+
+    fn stream(&mut self, id: u32) -> Result<&mut Stream, Error> {
+        match self.streams.get_mut(&id) {
+            Some(stream) => Ok(stream),
+            None => Err(Error::Http2("stream is closed")),
+        }
+    }
+
+    fn pop_event(&mut self, id: u32) -> Result<Option<Event>, Error> {
+        let Inner {
+            streams, pending, ..
+        } = self;
+
+        let Some(stream) = streams.get_mut(&id) else {
+            return Err(Error::Http2("stream is closed"));
+        };
+
+        let Some(event) = stream.events.pop_front() else {
+            return Ok(None);
+        };
+
+        // Open the window as data is consumed, not as it's received, to not buffer
+        // more than the window for a slow reader.
+        if let Event::Data { data, .. } = &event {
+            stream.recv_unacked += data.len() as u32;
+
+            if !stream.remote_closed && stream.recv_unacked >= STREAM_WINDOW / 2 {
+                let increment = mem::take(&mut stream.recv_unacked);
+                frame::write_window_update(pending, id, increment);
+            }
+        }
+
+        Ok(Some(event))
+    }
+
+    fn check_failure(&self) -> Result<(), Error> {
+        match &self.failure {
+            None => Ok(()),
+            Some(Failure::Io(kind, msg)) => Err(io::Error::new(*kind, msg.clone()).into()),
+            Some(Failure::Protocol(msg)) => Err(Error::Http2(msg)),
+        }
+    }
+
+    fn fail(&mut self, error: Error) {
+        debug!("HTTP/2 connection failed: {}", error);
+
+        if let Error::Http2(msg) = error {
+            self.failure = Some(Failure::Protocol(msg));
+        } else {
+            let e = error.into_io();
+            self.failure = Some(Failure::Io(e.kind(), e.to_string()));
+        }
+    }
+
+    fn process_input(&mut self) -> Result<(), Error> {
+        let mut pos = 0;
+
+        while let Some(header) = FrameHeader::parse(&self.input[pos..]) {
+            if header.len > MAX_FRAME_SIZE {
+                return Err(Error::Http2("frame too large"));
+            }
+
+            let end = pos + HEADER_LEN + header.len;
+            if self.input.len() < end {
+                break;
+            }
+
+            let payload = mem::take(&mut self.input);
+            let result = self.handle_frame(header, &payload[pos + HEADER_LEN..end]);
+            self.input = payload;
+            result?;
+
+            pos = end;
+        }
+
+        self.input.drain(..pos);
+
+        Ok(())
+    }
+
+    fn handle_frame(&mut self, header: FrameHeader, payload: &[u8]) -> Result<(), Error> {
+        trace!("Received HTTP/2 frame: {:?}", header);
+
+        let id = header.stream_id;
+
+        if self.continuation.is_some() && header.kind != frame::CONTINUATION {
+            return Err(Error::Http2("expected CONTINUATION frame"));
+        }
+
+        match header.kind {
+            frame::DATA => {
+                if id == 0 {
+                    return Err(frame::PROTOCOL);
+                }
+
+                // The connection window counts padding too. It's opened on receipt, so
+                // one slow stream doesn't stall the others.
+                self.recv_unacked += header.len as u32;
+                if self.recv_unacked >= CONNECTION_WINDOW / 2 {
+                    let increment = mem::take(&mut self.recv_unacked);
+                    frame::write_window_update(&mut self.pending, 0, increment);
+                }
+
+                let data = frame::unpad(&header, payload)?.to_vec();
+                let end_stream = header.has(frame::FLAG_END_STREAM);
+
+                // Frames for streams we closed are dropped.
+                if let Some(stream) = self.streams.get_mut(&id) {
+                    if !stream.remote_closed {
+                        stream.remote_closed = end_stream;
+                        stream.events.push_back(Event::Data { data, end_stream });
+                    }
+                }
+            }
+
+            frame::HEADERS => {
+                if id == 0 {
+                    return Err(frame::PROTOCOL);
+                }
+
+                let block = frame::unpad(&header, payload)?.to_vec();
+                let end_stream = header.has(frame::FLAG_END_STREAM);
+
+                if header.has(frame::FLAG_END_HEADERS) {
+                    self.headers_complete(id, &block, end_stream)?;
+                } else {
+                    self.continuation = Some((id, block, end_stream));
+                }
+            }
+
+            frame::CONTINUATION => {
+                let Some((cid, mut block, end_stream)) = self.continuation.take() else {
+                    return Err(Error::Http2("unexpected CONTINUATION frame"));
+                };
+
+                if cid != id {
+                    return Err(Error::Http2("unexpected CONTINUATION frame"));
+                }
+
+                block.extend_from_slice(payload);
+
+                if block.len() > self.max_header_size {
+                    return Err(Error::LargeResponseHeader(
+                        block.len(),
+                        self.max_header_size,
+                    ));
+                }
+
+                if header.has(frame::FLAG_END_HEADERS) {
+                    self.headers_complete(id, &block, end_stream)?;
+                } else {
+                    self.continuation = Some((id, block, end_stream));
+                }
+            }
+
+            frame::RST_STREAM => {
+                let code = frame::read_u32(payload)?;
+
+                if let Some(stream) = self.streams.get_mut(&id) {
+                    debug!("HTTP/2 stream {} reset: {}", id, frame::error_name(code));
+                    stream.remote_closed = true;
+                    stream.local_closed = true;
+                    stream.events.push_back(Event::Reset(code));
+                }
+            }
+
+            frame::SETTINGS => {
+                if header.has(frame::FLAG_ACK) {
+                    return Ok(());
+                }
+
+                if id != 0 || payload.len() % 6 != 0 {
+                    return Err(frame::PROTOCOL);
+                }
+
+                for setting in payload.chunks_exact(6) {
+                    let key = u16::from_be_bytes([setting[0], setting[1]]);
+                    let value = frame::read_u32(&setting[2..])?;
+                    self.apply_setting(key, value)?;
+                }
+
+                frame::write(&mut self.pending, frame::SETTINGS, frame::FLAG_ACK, 0, &[]);
+            }
+
+            frame::PUSH_PROMISE => {
+                // We disabled server push in our SETTINGS.
+                return Err(frame::PROTOCOL);
+            }
+
+            frame::PING if !header.has(frame::FLAG_ACK) => {
+                frame::write(&mut self.pending, frame::PING, frame::FLAG_ACK, 0, payload);
+            }
+
+            frame::GOAWAY => {
+                let last_id = frame::read_u32(payload)? & 0x7fff_ffff;
+                let code = frame::read_u32(payload.get(4..).unwrap_or_default())?;
+
+                debug!(
+                    "HTTP/2 GOAWAY ({}), last stream: {}",
+                    frame::error_name(code),
+                    last_id
+                );
+
+                self.goaway = true;
+
+                // Streams after the last one were not processed, and are safe to retry.
+                for (_, stream) in self.streams.iter_mut().filter(|(i, _)| **i > last_id) {
+                    stream.remote_closed = true;
+                    stream.local_closed = true;
+                    stream.events.push_back(Event::Reset(frame::REFUSED_STREAM));
+                }
+            }
+
+            frame::WINDOW_UPDATE => {
+                let increment = (frame::read_u32(payload)? & 0x7fff_ffff) as i64;
+
+                if id == 0 {
+                    self.send_window += increment;
+                    if increment == 0 || self.send_window > MAX_WINDOW {
+                        return Err(Error::Http2("flow control error"));
+                    }
+                } else if let Some(stream) = self.streams.get_mut(&id) {
+                    stream.send_window += increment;
+                }
+            }
+
+            // PRIORITY and unknown frames.
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn headers_complete(&mut self, id: u32, block: &[u8], end_stream: bool) -> Result<(), Error> {
+        if block.len() > self.max_header_size {
+            return Err(Error::LargeResponseHeader(
+                block.len(),
+                self.max_header_size,
+            ));
+        }
+
+        // Always decode, to keep the dynamic table in sync.
+        let fields = self.decoder.decode(block)?;
+
+        let Some(stream) = self.streams.get_mut(&id) else {
+            return Ok(());
+        };
+
+        if stream.remote_closed {
+            return Ok(());
+        }
+
+        // The fields become HTTP/1.1 text, which a CR or LF would corrupt.
+        if !is_well_formed(&fields) {
+            debug!("HTTP/2 stream {} has malformed header fields", id);
+            frame::write_rst_stream(&mut self.pending, id, frame::PROTOCOL_ERROR);
+            stream.remote_closed = true;
+            stream.local_closed = true;
+            stream.events.push_back(Event::Malformed);
+            return Ok(());
+        }
+
+        stream.remote_closed = end_stream;
+        stream
+            .events
+            .push_back(Event::Headers { fields, end_stream });
+
+        Ok(())
+    }
+
+    fn apply_setting(&mut self, key: u16, value: u32) -> Result<(), Error> {
+        match key {
+            frame::SETTINGS_INITIAL_WINDOW_SIZE => {
+                let value = value as i64;
+                if value > MAX_WINDOW {
+                    return Err(Error::Http2("flow control error"));
+                }
+
+                let delta = value - self.peer_initial_window;
+                self.peer_initial_window = value;
+
+                for stream in self.streams.values_mut() {
+                    stream.send_window += delta;
+                }
+            }
+            frame::SETTINGS_MAX_FRAME_SIZE => {
+                let value = value as usize;
+                if !(MAX_FRAME_SIZE..(1 << 24)).contains(&value) {
+                    return Err(frame::PROTOCOL);
+                }
+                self.peer_max_frame_size = value;
+            }
+            frame::SETTINGS_MAX_CONCURRENT_STREAMS => {
+                self.peer_max_concurrent_streams = value as usize;
+            }
+            // HEADER_TABLE_SIZE doesn't matter, since we don't use the dynamic table.
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// The error for a response with malformed header fields.
+pub(crate) const MALFORMED: Error = Error::Http2("malformed response header");
+
+/// Whether received header fields are well-formed (RFC 9113, 8.2.1 and 8.3.2).
+///
+/// Names must be lowercase tokens, and values must not hold CR, LF or NUL. The only
+/// pseudo header of a response is `:status`, which goes before the regular fields.
+fn is_well_formed(fields: &[Field]) -> bool {
+    let mut seen_regular = false;
+
+    for (name, value) in fields {
+        if HeaderValue::from_bytes(value).is_err() {
+            return false;
+        }
+
+        if name == b":status" {
+            if seen_regular {
+                return false;
+            }
+            continue;
+        }
+
+        seen_regular = true;
+
+        let is_lowercase = !name.iter().any(|c| c.is_ascii_uppercase());
+        if !is_lowercase || HeaderName::from_bytes(name).is_err() {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// The error for a stream reset by the server.
+///
+/// This is an io error to let a refused request on a pooled connection be retried.
+pub(crate) fn reset_error(code: u32) -> Error {
+    let msg = format!("HTTP/2 stream reset: {}", frame::error_name(code));
+    io::Error::new(io::ErrorKind::ConnectionReset, msg).into()
+}
+
+fn copy_error(error: &Error) -> Error {
+    match error {
+        Error::Timeout(v) => Error::Timeout(*v),
+        Error::Http2(v) => Error::Http2(v),
+        Error::Io(e) => io::Error::new(e.kind(), e.to_string()).into(),
+        e => io::Error::new(io::ErrorKind::Other, e.to_string()).into(),
+    }
+}
+
+fn transmit(
+    transport: &mut dyn Transport,
+    mut data: &[u8],
+    timeout: NextTimeout,
+) -> Result<(), Error> {
+    while !data.is_empty() {
+        let output = transport.buffers().output();
+        let amount = output.len().min(data.len());
+        output[..amount].copy_from_slice(&data[..amount]);
+        transport.transmit_output(amount, timeout)?;
+        data = &data[amount..];
+    }
+    Ok(())
+}
+
+impl fmt::Debug for Http2Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.lock();
+        f.debug_struct("Http2Connection")
+            .field("streams", &inner.streams.len())
+            .field("next_stream_id", &inner.next_stream_id)
+            .field("goaway", &inner.goaway)
+            .finish()
+    }
+}
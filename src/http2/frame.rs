@@ -0,0 +1,220 @@
+//! HTTP/2 framing (RFC 9113, section 4 and 6).
+
+use crate::Error;
+
+pub(crate) const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+pub(crate) const HEADER_LEN: usize = 9;
+
+/// The SETTINGS_MAX_FRAME_SIZE we accept, which is the default.
+pub(crate) const MAX_FRAME_SIZE: usize = 16_384;
+
+pub(crate) const DATA: u8 = 0x0;
+pub(crate) const HEADERS: u8 = 0x1;
+pub(crate) const RST_STREAM: u8 = 0x3;
+pub(crate) const SETTINGS: u8 = 0x4;
+pub(crate) const PUSH_PROMISE: u8 = 0x5;
+pub(crate) const PING: u8 = 0x6;
+pub(crate) const GOAWAY: u8 = 0x7;
+pub(crate) const WINDOW_UPDATE: u8 = 0x8;
+pub(crate) const CONTINUATION: u8 = 0x9;
+
+pub(crate) const FLAG_END_STREAM: u8 = 0x1;
+pub(crate) const FLAG_ACK: u8 = 0x1;
+pub(crate) const FLAG_END_HEADERS: u8 = 0x4;
+pub(crate) const FLAG_PADDED: u8 = 0x8;
+pub(crate) const FLAG_PRIORITY: u8 = 0x20;
+
+pub(crate) const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+pub(crate) const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+pub(crate) const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+pub(crate) const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+
+pub(crate) const PROTOCOL_ERROR: u32 = 0x1;
+pub(crate) const REFUSED_STREAM: u32 = 0x7;
+pub(crate) const CANCEL: u32 = 0x8;
+
+/// The largest flow control window.
+pub(crate) const MAX_WINDOW: i64 = (1 << 31) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FrameHeader {
+    pub len: usize,
+    pub kind: u8,
+    pub flags: u8,
+    pub stream_id: u32,
+}
+
+impl FrameHeader {
+    pub(crate) fn parse(input: &[u8]) -> Option<FrameHeader> {
+        let h = input.get(..HEADER_LEN)?;
+
+        Some(FrameHeader {
+            len: u32::from_be_bytes([0, h[0], h[1], h[2]]) as usize,
+            kind: h[3],
+            flags: h[4],
+            stream_id: u32::from_be_bytes([h[5], h[6], h[7], h[8]]) & 0x7fff_ffff,
+        })
+    }
+
+    pub(crate) fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+}
+
+/// Append a frame with `payload` to `out`.
+pub(crate) fn write(out: &mut Vec<u8>, kind: u8, flags: u8, stream_id: u32, payload: &[u8]) {
+    let len = (payload.len() as u32).to_be_bytes();
+    out.extend_from_slice(&len[1..]);
+    out.push(kind);
+    out.push(flags);
+    out.extend_from_slice(&stream_id.to_be_bytes());
+    out.extend_from_slice(payload);
+}
+
+/// Append a header block as a HEADERS frame and CONTINUATION frames, as needed.
+pub(crate) fn write_headers(
+    out: &mut Vec<u8>,
+    stream_id: u32,
+    block: &[u8],
+    end_stream: bool,
+    max_frame_size: usize,
+) {
+    let mut chunks = block.chunks(max_frame_size).peekable();
+    let mut kind = HEADERS;
+    let mut flags = if end_stream { FLAG_END_STREAM } else { 0 };
+
+    // An empty block still needs one frame.
+    if chunks.peek().is_none() {
+        write(out, kind, flags | FLAG_END_HEADERS, stream_id, &[]);
+        return;
+    }
+
+    while let Some(chunk) = chunks.next() {
+        if chunks.peek().is_none() {
+            flags |= FLAG_END_HEADERS;
+        }
+        write(out, kind, flags, stream_id, chunk);
+        kind = CONTINUATION;
+        flags = 0;
+    }
+}
+
+pub(crate) fn write_settings(out: &mut Vec<u8>, settings: &[(u16, u32)]) {
+    let mut payload = Vec::with_capacity(settings.len() * 6);
+    for (id, value) in settings {
+        payload.extend_from_slice(&id.to_be_bytes());
+        payload.extend_from_slice(&value.to_be_bytes());
+    }
+    write(out, SETTINGS, 0, 0, &payload);
+}
+
+pub(crate) fn write_window_update(out: &mut Vec<u8>, stream_id: u32, increment: u32) {
+    write(out, WINDOW_UPDATE, 0, stream_id, &increment.to_be_bytes());
+}
+
+pub(crate) fn write_rst_stream(out: &mut Vec<u8>, stream_id: u32, code: u32) {
+    write(out, RST_STREAM, 0, stream_id, &code.to_be_bytes());
+}
+
+/// The payload without padding, and for HEADERS, without the priority fields.
+pub(crate) fn unpad<'a>(header: &FrameHeader, payload: &'a [u8]) -> Result<&'a [u8], Error> {
+    let mut payload = payload;
+
+    if header.has(FLAG_PADDED) {
+        let (&pad, rest) = payload.split_first().ok_or(PROTOCOL)?;
+        let end = rest.len().checked_sub(pad as usize).ok_or(PROTOCOL)?;
+        payload = &rest[..end];
+    }
+
+    if header.kind == HEADERS && header.has(FLAG_PRIORITY) {
+        payload = payload.get(5..).ok_or(PROTOCOL)?;
+    }
+
+    Ok(payload)
+}
+
+pub(crate) fn read_u32(payload: &[u8]) -> Result<u32, Error> {
+    let b = payload.get(..4).ok_or(PROTOCOL)?;
+    Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+pub(crate) const PROTOCOL: Error = Error::Http2("protocol error");
+
+/// Name of an error code for logs and errors.
+pub(crate) fn error_name(code: u32) -> &'static str {
+    match code {
+        0x0 => "NO_ERROR",
+        0x1 => "PROTOCOL_ERROR",
+        0x2 => "INTERNAL_ERROR",
+        0x3 => "FLOW_CONTROL_ERROR",
+        0x4 => "SETTINGS_TIMEOUT",
+        0x5 => "STREAM_CLOSED",
+        0x6 => "FRAME_SIZE_ERROR",
+        0x7 => "REFUSED_STREAM",
+        0x8 => "CANCEL",
+        0x9 => "COMPRESSION_ERROR",
+        0xa => "CONNECT_ERROR",
+        0xb => "ENHANCE_YOUR_CALM",
+        0xc => "INADEQUATE_SECURITY",
+        0xd => "HTTP_1_1_REQUIRED",
+        _ => "unknown error",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn header_roundtrip() {
+        let mut out = Vec::new();
+        write(&mut out, DATA, FLAG_END_STREAM, 3, b"hello");
+
+        let header = FrameHeader::parse(&out).unwrap();
+        assert_eq!(
+            header,
+            FrameHeader {
+                len: 5,
+                kind: DATA,
+                flags: FLAG_END_STREAM,
+                stream_id: 3
+            }
+        );
+        assert_eq!(&out[HEADER_LEN..], b"hello");
+    }
+
+    #[test]
+    fn headers_with_continuation() {
+        let mut out = Vec::new();
+        write_headers(&mut out, 1, &[7; 25], true, 10);
+
+        let first = FrameHeader::parse(&out).unwrap();
+        assert_eq!(first.kind, HEADERS);
+        assert_eq!(first.flags, FLAG_END_STREAM);
+
+        let second = FrameHeader::parse(&out[19..]).unwrap();
+        assert_eq!(second.kind, CONTINUATION);
+        assert_eq!(second.flags, 0);
+
+        let third = FrameHeader::parse(&out[38..]).unwrap();
+        assert_eq!(third.kind, CONTINUATION);
+        assert_eq!(third.flags, FLAG_END_HEADERS);
+        assert_eq!(third.len, 5);
+    }
+
+    #[test]
+    fn unpad_payload() {
+        let header = FrameHeader {
+            len: 0,
+            kind: HEADERS,
+            flags: FLAG_PADDED | FLAG_PRIORITY,
+            stream_id: 1,
+        };
+        let payload = [2, 0, 0, 0, 0, 16, b'a', b'b', 0, 0];
+        assert_eq!(unpad(&header, &payload).unwrap(), b"ab");
+
+        // More padding than payload.
+        assert!(unpad(&header, &[9, 1, 2]).is_err());
+    }
+}
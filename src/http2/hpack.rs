@@ -0,0 +1,413 @@
+//! HPACK header compression (RFC 7541).
+//!
+//! The decoder is complete. The encoder never adds to the dynamic table, which
+//! keeps it stateless at the cost of some compression.
+
+use std::collections::VecDeque;
+
+use super::huffman;
+use crate::Error;
+
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// Overhead per dynamic table entry, on top of name and value.
+const ENTRY_OVERHEAD: usize = 32;
+
+/// The default SETTINGS_HEADER_TABLE_SIZE, which we never change.
+pub(crate) const DEFAULT_TABLE_SIZE: usize = 4096;
+
+const ERR: Error = Error::Http2("header compression error");
+
+/// A decoded header field.
+pub(crate) type Field = (Vec<u8>, Vec<u8>);
+
+pub(crate) struct Decoder {
+    /// Newest entry first, as it's indexed.
+    table: VecDeque<Field>,
+    size: usize,
+    max_size: usize,
+}
+
+impl Decoder {
+    pub(crate) fn new() -> Self {
+        Decoder {
+            table: VecDeque::new(),
+            size: 0,
+            max_size: DEFAULT_TABLE_SIZE,
+        }
+    }
+
+    /// Decode a complete header block.
+    pub(crate) fn decode(&mut self, mut block: &[u8]) -> Result<Vec<Field>, Error> {
+        let mut fields = Vec::new();
+        let mut allow_size_update = true;
+
+        while let Some(&first) = block.first() {
+            if first & 0b1000_0000 != 0 {
+                // Indexed header field.
+                let index = read_int(&mut block, 7)?;
+                fields.push(self.get(index)?);
+            } else if first & 0b1100_0000 == 0b0100_0000 {
+                // Literal with incremental indexing.
+                let field = self.read_literal(&mut block, 6)?;
+                self.insert(field.clone());
+                fields.push(field);
+            } else if first & 0b1110_0000 == 0b0010_0000 {
+                // Dynamic table size update, only allowed first in the block.
+                let size = read_int(&mut block, 5)?;
+                if !allow_size_update || size > DEFAULT_TABLE_SIZE {
+                    return Err(ERR);
+                }
+                self.max_size = size;
+                self.evict(0);
+                continue;
+            } else {
+                // Literal without indexing, or never indexed.
+                fields.push(self.read_literal(&mut block, 4)?);
+            }
+
+            allow_size_update = false;
+        }
+
+        Ok(fields)
+    }
+
+    fn get(&self, index: usize) -> Result<Field, Error> {
+        if index == 0 {
+            return Err(ERR);
+        }
+
+        if let Some((name, value)) = STATIC_TABLE.get(index - 1) {
+            return Ok((name.as_bytes().to_vec(), value.as_bytes().to_vec()));
+        }
+
+        self.table
+            .get(index - STATIC_TABLE.len() - 1)
+            .cloned()
+            .ok_or(ERR)
+    }
+
+    fn read_literal(&self, block: &mut &[u8], prefix: u8) -> Result<Field, Error> {
+        let index = read_int(block, prefix)?;
+
+        let name = if index == 0 {
+            read_string(block)?
+        } else {
+            self.get(index)?.0
+        };
+
+        let value = read_string(block)?;
+
+        Ok((name, value))
+    }
+
+    fn insert(&mut self, field: Field) {
+        let size = field.0.len() + field.1.len() + ENTRY_OVERHEAD;
+
+        self.evict(size);
+
+        // An entry larger than the table empties it, and is not added.
+        if size <= self.max_size {
+            self.size += size;
+            self.table.push_front(field);
+        }
+    }
+
+    /// Evict entries until there is room for `incoming` bytes.
+    fn evict(&mut self, incoming: usize) {
+        while self.size + incoming > self.max_size {
+            let Some((name, value)) = self.table.pop_back() else {
+                break;
+            };
+            self.size -= name.len() + value.len() + ENTRY_OVERHEAD;
+        }
+    }
+}
+
+/// Encode header fields, without using the dynamic table.
+///
+/// `sensitive` headers are marked to never be indexed, also by intermediaries.
+pub(crate) fn encode<'a>(fields: impl Iterator<Item = (&'a str, &'a [u8])>, out: &mut Vec<u8>) {
+    for (name, value) in fields {
+        let full = STATIC_TABLE
+            .iter()
+            .position(|(n, v)| *n == name && v.as_bytes() == value);
+
+        if let Some(i) = full {
+            write_int(out, 0b1000_0000, 7, i + 1);
+            continue;
+        }
+
+        let sensitive = matches!(name, "authorization" | "proxy-authorization" | "cookie");
+        let flags = if sensitive { 0b0001_0000 } else { 0 };
+
+        match STATIC_TABLE.iter().position(|(n, _)| *n == name) {
+            Some(i) => write_int(out, flags, 4, i + 1),
+            None => {
+                out.push(flags);
+                write_string(out, name.as_bytes());
+            }
+        }
+
+        write_string(out, value);
+    }
+}
+
+fn read_int(block: &mut &[u8], prefix: u8) -> Result<usize, Error> {
+    let mask = (1_u16 << prefix) as u8 - 1;
+
+    let (&first, mut rest) = block.split_first().ok_or(ERR)?;
+    let mut value = (first & mask) as usize;
+
+    if value == mask as usize {
+        let mut shift = 0;
+
+        loop {
+            let (&byte, r) = rest.split_first().ok_or(ERR)?;
+            rest = r;
+
+            // Nothing sensible needs more than 28 bits.
+            if shift > 21 {
+                return Err(ERR);
+            }
+
+            value += ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+    }
+
+    *block = rest;
+    Ok(value)
+}
+
+fn read_string(block: &mut &[u8]) -> Result<Vec<u8>, Error> {
+    let is_huffman = block.first().ok_or(ERR)? & 0x80 != 0;
+    let len = read_int(block, 7)?;
+
+    if block.len() < len {
+        return Err(ERR);
+    }
+
+    let (data, rest) = block.split_at(len);
+    *block = rest;
+
+    if is_huffman {
+        let mut out = Vec::with_capacity(len * 8 / 5);
+        huffman::decode(data, &mut out)?;
+        Ok(out)
+    } else {
+        Ok(data.to_vec())
+    }
+}
+
+fn write_int(out: &mut Vec<u8>, flags: u8, prefix: u8, mut value: usize) {
+    let mask = ((1_u16 << prefix) - 1) as usize;
+
+    if value < mask {
+        out.push(flags | value as u8);
+        return;
+    }
+
+    out.push(flags | mask as u8);
+    value -= mask;
+
+    while value >= 0x80 {
+        out.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+
+    out.push(value as u8);
+}
+
+fn write_string(out: &mut Vec<u8>, value: &[u8]) {
+    write_int(out, 0, 7, value.len());
+    out.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fields(decoded: Vec<Field>) -> Vec<(String, String)> {
+        decoded
+            .into_iter()
+            .map(|(n, v)| (String::from_utf8(n).unwrap(), String::from_utf8(v).unwrap()))
+            .collect()
+    }
+
+    fn pairs(v: &[(&str, &str)]) -> Vec<(String, String)> {
+        v.iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn decode_rfc_responses_huffman() {
+        // RFC 7541, C.6 with a 256 byte table, here checked with the default size.
+        let mut decoder = Decoder::new();
+
+        let first = [
+            0x48, 0x82, 0x64, 0x02, 0x58, 0x85, 0xae, 0xc3, 0x77, 0x1a, 0x4b, 0x61, 0x96, 0xd0,
+            0x7a, 0xbe, 0x94, 0x10, 0x54, 0xd4, 0x44, 0xa8, 0x20, 0x05, 0x95, 0x04, 0x0b, 0x81,
+            0x66, 0xe0, 0x82, 0xa6, 0x2d, 0x1b, 0xff, 0x6e, 0x91, 0x9d, 0x29, 0xad, 0x17, 0x18,
+            0x63, 0xc7, 0x8f, 0x0b, 0x97, 0xc8, 0xe9, 0xae, 0x82, 0xae, 0x43, 0xd3,
+        ];
+        assert_eq!(
+            fields(decoder.decode(&first).unwrap()),
+            pairs(&[
+                (":status", "302"),
+                ("cache-control", "private"),
+                ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+                ("location", "https://www.example.com"),
+            ])
+        );
+
+        // Refers to the entries added by the first block.
+        let second = [0x48, 0x83, 0x64, 0x0e, 0xff, 0xc1, 0xc0, 0xbf];
+        assert_eq!(
+            fields(decoder.decode(&second).unwrap()),
+            pairs(&[
+                (":status", "307"),
+                ("cache-control", "private"),
+                ("date", "Mon, 21 Oct 2013 20:13:21 GMT"),
+                ("location", "https://www.example.com"),
+            ])
+        );
+    }
+
+    #[test]
+    fn decode_rfc_request_plain() {
+        // RFC 7541, C.3.1
+        let block = [
+            0x82, 0x86, 0x84, 0x41, 0x0f, 0x77, 0x77, 0x77, 0x2e, 0x65, 0x78, 0x61, 0x6d, 0x70,
+            0x6c, 0x65, 0x2e, 0x63, 0x6f, 0x6d,
+        ];
+        let mut decoder = Decoder::new();
+        assert_eq!(
+            fields(decoder.decode(&block).unwrap()),
+            pairs(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+            ])
+        );
+        assert_eq!(decoder.size, 57);
+    }
+
+    #[test]
+    fn decode_table_size_update() {
+        let mut decoder = Decoder::new();
+        decoder.decode(&[0x41, 0x01, b'a']).unwrap();
+        assert_eq!(decoder.table.len(), 1);
+
+        // Shrinking to 0 evicts everything.
+        decoder.decode(&[0x20]).unwrap();
+        assert_eq!(decoder.table.len(), 0);
+
+        // Not allowed after a field, or above the setting.
+        assert!(decoder.decode(&[0x82, 0x20]).is_err());
+        assert!(Decoder::new().decode(&[0x3f, 0xe2, 0x1f]).is_err());
+    }
+
+    #[test]
+    fn encode_roundtrip() {
+        let input: Vec<(&str, &[u8])> = vec![
+            (":method", b"GET"),
+            (":path", b"/a/long/path"),
+            ("accept", b"*/*"),
+            ("authorization", b"secret"),
+            ("x-custom", &[b'x'; 200]),
+        ];
+
+        let mut out = Vec::new();
+        encode(input.iter().cloned(), &mut out);
+
+        // :method GET is fully indexed.
+        assert_eq!(out[0], 0x82);
+
+        let decoded = Decoder::new().decode(&out).unwrap();
+        let expected: Vec<Field> = input
+            .iter()
+            .map(|(n, v)| (n.as_bytes().to_vec(), v.to_vec()))
+            .collect();
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn int_roundtrip() {
+        for value in [0, 30, 31, 127, 128, 1337, 1 << 20] {
+            let mut out = Vec::new();
+            write_int(&mut out, 0, 5, value);
+            let mut block = &out[..];
+            assert_eq!(read_int(&mut block, 5).unwrap(), value);
+            assert!(block.is_empty());
+        }
+    }
+}
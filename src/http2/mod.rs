@@ -0,0 +1,290 @@
+//! HTTP/2 (RFC 9113).
+//!
+//! An [`Http2Connection`] multiplexes streams over a transport, which is negotiated
+//! with ALPN for https, or assumed with prior knowledge for http. Each request gets an
+//! [`Http2Transport`] that looks like an HTTP/1.1 connection to the rest of ureq.
+
+mod conn;
+mod frame;
+mod hpack;
+mod huffman;
+mod transport;
+
+pub(crate) use conn::Http2Connection;
+pub(crate) use transport::Http2Transport;
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::http::Version;
+
+    use super::frame::{self, FrameHeader, HEADER_LEN};
+    use super::hpack::{self, Decoder, Field};
+    use crate::config::Config;
+    use crate::test::local;
+    use crate::Agent;
+
+    /// h2c server with prior knowledge, answering requests by path.
+    ///
+    /// Returns the port and the number of accepted connections.
+    fn server() -> (u16, Arc<AtomicUsize>) {
+        local::server(serve)
+    }
+
+    fn serve(mut stream: TcpStream) {
+        let mut preface = [0; 24];
+        if stream.read_exact(&mut preface).is_err() {
+            return;
+        }
+        assert_eq!(preface, frame::PREFACE);
+
+        let mut out = Vec::new();
+        frame::write_settings(&mut out, &[(frame::SETTINGS_MAX_CONCURRENT_STREAMS, 10)]);
+        stream.write_all(&out).unwrap();
+
+        let mut decoder = Decoder::new();
+        let mut requests: HashMap<u32, (Vec<Field>, Vec<u8>)> = HashMap::new();
+        // Responses to /slow wait for the next request to be answered.
+        let mut held = Vec::new();
+
+        loop {
+            let mut head = [0; HEADER_LEN];
+            if stream.read_exact(&mut head).is_err() {
+                return;
+            }
+            let header = FrameHeader::parse(&head).unwrap();
+            let mut payload = vec![0; header.len];
+            stream.read_exact(&mut payload).unwrap();
+
+            let id = header.stream_id;
+
+            match header.kind {
+                frame::SETTINGS if !header.has(frame::FLAG_ACK) => {
+                    let mut out = Vec::new();
+                    frame::write(&mut out, frame::SETTINGS, frame::FLAG_ACK, 0, &[]);
+                    stream.write_all(&out).unwrap();
+                }
+                frame::HEADERS => {
+                    assert!(header.has(frame::FLAG_END_HEADERS));
+                    let fields = decoder.decode(&payload).unwrap();
+                    requests.insert(id, (fields, Vec::new()));
+                }
+                frame::DATA => requests.get_mut(&id).unwrap().1.extend_from_slice(&payload),
+                _ => {}
+            }
+
+            let is_request = matches!(header.kind, frame::HEADERS | frame::DATA);
+            if !is_request || !header.has(frame::FLAG_END_STREAM) {
+                continue;
+            }
+
+            let (fields, body) = requests.remove(&id).unwrap();
+            let path = field(&fields, ":path");
+
+            if path == "/slow" {
+                held.push(id);
+                continue;
+            }
+
+            let mut out = Vec::new();
+            respond(&mut out, id, &path, &fields, &body);
+            for id in held.drain(..) {
+                respond(&mut out, id, "/hello", &fields, &[]);
+            }
+            stream.write_all(&out).unwrap();
+        }
+    }
+
+    fn respond(out: &mut Vec<u8>, id: u32, path: &str, fields: &[Field], body: &[u8]) {
+        let headers = |out: &mut Vec<u8>, fields: &[(&str, &[u8])], end_stream: bool| {
+            let mut block = Vec::new();
+            hpack::encode(fields.iter().cloned(), &mut block);
+            frame::write_headers(out, id, &block, end_stream, frame::MAX_FRAME_SIZE);
+        };
+
+        match path {
+            "/hello" => {
+                headers(out, &[(":status", b"200"), ("content-length", b"5")], false);
+                frame::write(out, frame::DATA, frame::FLAG_END_STREAM, id, b"hello");
+            }
+            "/chunks" => {
+                headers(out, &[(":status", b"200")], false);
+                frame::write(out, frame::DATA, 0, id, b"ab");
+                frame::write(out, frame::DATA, 0, id, b"cd");
+                headers(out, &[("x-trailer", b"yes")], true);
+            }
+            "/echo" => {
+                let method = field(fields, ":method");
+                let len = body.len().to_string();
+                let fields: &[(&str, &[u8])] = &[
+                    (":status", b"200"),
+                    ("content-length", len.as_bytes()),
+                    ("x-method", method.as_bytes()),
+                ];
+                headers(out, fields, false);
+                frame::write(out, frame::DATA, frame::FLAG_END_STREAM, id, body);
+            }
+            "/empty" => headers(out, &[(":status", b"204")], true),
+            "/injected" => {
+                let fields: &[(&str, &[u8])] = &[
+                    (":status", b"200"),
+                    ("x-injected", b"a\r\ncontent-length: 0"),
+                ];
+                headers(out, fields, true);
+            }
+            "/injected-trailer" => {
+                headers(out, &[(":status", b"200")], false);
+                frame::write(out, frame::DATA, 0, id, b"ab");
+                headers(out, &[("x-trailer", b"\r\n\r\nHTTP/1.1 200 OK")], true);
+            }
+            "/uppercase" => headers(out, &[(":status", b"200"), ("X-Upper", b"1")], true),
+            _ => frame::write_rst_stream(out, id, 0x2),
+        }
+    }
+
+    fn field(fields: &[Field], name: &str) -> String {
+        let (_, value) = fields.iter().find(|(n, _)| n == name.as_bytes()).unwrap();
+        String::from_utf8(value.clone()).unwrap()
+    }
+
+    fn agent() -> Agent {
+        let config = Config::builder()
+            .http2_prior_knowledge(true)
+            .timeout_global(Some(Duration::from_secs(5)))
+            .build();
+
+        local::agent(config)
+    }
+
+    #[test]
+    fn get_with_length() {
+        let (port, _) = server();
+        let mut res = agent()
+            .get(format!("http://127.0.0.1:{}/hello", port))
+            .call()
+            .unwrap();
+
+        assert_eq!(res.version(), Version::HTTP_2);
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "hello");
+    }
+
+    #[test]
+    fn get_without_length() {
+        let (port, _) = server();
+        let mut res = agent()
+            .get(format!("http://127.0.0.1:{}/chunks", port))
+            .call()
+            .unwrap();
+
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "abcd");
+    }
+
+    #[test]
+    fn post_body() {
+        let (port, _) = server();
+        let mut res = agent()
+            .post(format!("http://127.0.0.1:{}/echo", port))
+            .send("request body")
+            .unwrap();
+
+        assert_eq!(res.headers()["x-method"], "POST");
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "request body");
+    }
+
+    #[test]
+    fn no_content() {
+        let (port, _) = server();
+        let res = agent()
+            .get(format!("http://127.0.0.1:{}/empty", port))
+            .call()
+            .unwrap();
+
+        assert_eq!(res.status(), 204);
+    }
+
+    #[test]
+    fn reset_stream() {
+        let (port, _) = server();
+        let err = agent()
+            .get(format!("http://127.0.0.1:{}/reset", port))
+            .call()
+            .unwrap_err();
+
+        assert!(err.to_string().contains("INTERNAL_ERROR"), "{}", err);
+    }
+
+    #[test]
+    fn malformed_header_fields() {
+        let (port, _) = server();
+        let agent = agent();
+
+        let err = agent
+            .get(format!("http://127.0.0.1:{}/injected", port))
+            .call()
+            .unwrap_err();
+        assert!(err.to_string().contains("malformed"), "{}", err);
+
+        let err = agent
+            .get(format!("http://127.0.0.1:{}/uppercase", port))
+            .call()
+            .unwrap_err();
+        assert!(err.to_string().contains("malformed"), "{}", err);
+
+        let err = agent
+            .get(format!("http://127.0.0.1:{}/injected-trailer", port))
+            .call()
+            .unwrap()
+            .body_mut()
+            .read_to_string()
+            .unwrap_err();
+        assert!(err.to_string().contains("malformed"), "{}", err);
+
+        // The connection is still usable.
+        let mut res = agent
+            .get(format!("http://127.0.0.1:{}/hello", port))
+            .call()
+            .unwrap();
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "hello");
+    }
+
+    #[test]
+    fn concurrent_streams_share_connection() {
+        let (port, accepted) = server();
+        let agent = agent();
+
+        // The server holds the response to /slow until it answers another request,
+        // which must then be on the same connection.
+        let slow = {
+            let agent = agent.clone();
+            thread::spawn(move || {
+                agent
+                    .get(format!("http://127.0.0.1:{}/slow", port))
+                    .call()
+                    .unwrap()
+                    .body_mut()
+                    .read_to_string()
+                    .unwrap()
+            })
+        };
+
+        thread::sleep(Duration::from_millis(100));
+
+        for _ in 0..3 {
+            let mut res = agent
+                .get(format!("http://127.0.0.1:{}/hello", port))
+                .call()
+                .unwrap();
+            assert_eq!(res.body_mut().read_to_string().unwrap(), "hello");
+        }
+
+        assert_eq!(slow.join().unwrap(), "hello");
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    }
+}
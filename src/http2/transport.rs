@@ -0,0 +1,545 @@
+use std::fmt;
+use std::sync::Arc;
+
+use http::uri::Scheme;
+use http::{header, HeaderName, Method, StatusCode};
+use ureq_proto::parser::try_parse_request;
+
+use super::conn::{reset_error, Event, Http2Connection, MALFORMED};
+use super::hpack::Field;
+use crate::http;
+use crate::transport::{Buffers, ConnectionDetails, LazyBuffers, NextTimeout, Transport};
+use crate::Error;
+
+const MAX_REQUEST_HEADERS: usize = 256;
+
+/// An HTTP/1.1 transport running each request as a stream of a shared HTTP/2 connection.
+///
+/// The rest of ureq speaks HTTP/1.1 with this transport. The request head and body
+/// written to it are translated to HEADERS and DATA frames, and the frames received are
+/// translated back to an HTTP/1.1 response. A response without `content-length`
+/// becomes chunked, which also carries the trailers.
+///
+/// This keeps redirects, timeouts, body decoding and so on working the same for both
+/// protocols. Once a response is complete, the transport can go back in the pool, and
+/// the next request on it opens a new stream.
+pub(crate) struct Http2Transport {
+    conn: Arc<Http2Connection>,
+    scheme: Scheme,
+    buffers: LazyBuffers,
+    /// The TLS handshake is only reported for the first stream.
+    tls_handshake: Option<std::time::Duration>,
+    stream: Option<u32>,
+    send: Send,
+    recv: Recv,
+    /// Translated response bytes that didn't fit in the input buffer.
+    pending: Vec<u8>,
+}
+
+enum Send {
+    /// Collecting the request head.
+    Head(Vec<u8>),
+    Length(u64),
+    Chunked(ChunkedDecoder),
+    Done,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Recv {
+    /// Awaiting the response header.
+    Head { is_head_request: bool },
+    /// DATA frames are the body as is.
+    Raw,
+    /// DATA frames become chunks.
+    Chunked,
+    /// DATA frames are ignored.
+    NoBody,
+}
+
+impl Http2Transport {
+    pub(crate) fn new(
+        conn: Arc<Http2Connection>,
+        details: &ConnectionDetails,
+        is_first: bool,
+    ) -> Self {
+        let tls_handshake = if is_first {
+            conn.tls_handshake_duration()
+        } else {
+            None
+        };
+
+        Http2Transport {
+            conn,
+            scheme: details.uri.scheme().cloned().unwrap_or(Scheme::HTTPS),
+            buffers: LazyBuffers::new(
+                details.config.input_buffer_size,
+                details.config.output_buffer_size,
+            ),
+            tls_handshake,
+            stream: None,
+            send: Send::Head(Vec::new()),
+            recv: Recv::Head {
+                is_head_request: false,
+            },
+            pending: Vec::new(),
+        }
+    }
+
+    fn send(&mut self, data: &[u8], timeout: NextTimeout) -> Result<(), Error> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        match &mut self.send {
+            Send::Head(head) => {
+                head.extend_from_slice(data);
+
+                let Some(end) = head.windows(4).position(|w| w == b"\r\n\r\n") else {
+                    return Ok(());
+                };
+
+                let head = std::mem::take(head);
+                let (head, rest) = head.split_at(end + 4);
+                self.start_request(head, timeout)?;
+
+                // Any body bytes after the head.
+                self.send(rest, timeout)
+            }
+            Send::Length(remaining) => {
+                if data.len() as u64 > *remaining {
+                    return Err(Error::Http2("request body longer than content-length"));
+                }
+
+                *remaining -= data.len() as u64;
+                let end_stream = *remaining == 0;
+                if end_stream {
+                    self.send = Send::Done;
+                }
+
+                self.conn.send_data(self.id()?, data, end_stream, timeout)
+            }
+            Send::Chunked(decoder) => {
+                let mut body = Vec::with_capacity(data.len());
+                let end_stream = decoder.decode(data, &mut body)?;
+                if end_stream {
+                    self.send = Send::Done;
+                }
+
+                if body.is_empty() && !end_stream {
+                    return Ok(());
+                }
+
+                self.conn.send_data(self.id()?, &body, end_stream, timeout)
+            }
+            Send::Done => Err(Error::Http2("unexpected data after request")),
+        }
+    }
+
+    fn start_request(&mut self, head: &[u8], timeout: NextTimeout) -> Result<(), Error> {
+        // Returning from the pool, a previous stream might not have been closed.
+        self.close();
+
+        let (_, request) = try_parse_request::<MAX_REQUEST_HEADERS>(head)?
+            .ok_or(Error::Http2("incomplete request head"))?;
+
+        // The parser doesn't keep the request target.
+        let target = std::str::from_utf8(head)
+            .ok()
+            .and_then(|h| h.split(' ').nth(1))
+            .ok_or(Error::Http2("missing request target"))?;
+
+        let method = request.method();
+        let headers = request.headers();
+
+        if method == Method::CONNECT || headers.contains_key(header::UPGRADE) {
+            return Err(Error::Http2("protocol upgrades need HTTP/1.1"));
+        }
+
+        let authority = headers
+            .get(header::HOST)
+            .ok_or(Error::Http2("missing host header"))?;
+
+        let mut fields: Vec<(&str, &[u8])> = vec![
+            (":method", method.as_str().as_bytes()),
+            (":scheme", self.scheme.as_str().as_bytes()),
+            (":authority", authority.as_bytes()),
+            (":path", target.as_bytes()),
+        ];
+
+        for (name, value) in headers {
+            let is_te_trailers = name == header::TE && value == "trailers";
+            if is_connection_specific(name) && !is_te_trailers {
+                continue;
+            }
+            fields.push((name.as_str(), value.as_bytes()));
+        }
+
+        let is_chunked = headers
+            .get_all(header::TRANSFER_ENCODING)
+            .iter()
+            .any(|v| v.to_str().map(|v| v.contains("chunked")).unwrap_or(false));
+
+        let length = headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+
+        let send = match (is_chunked, length) {
+            (true, _) => Send::Chunked(ChunkedDecoder::default()),
+            (false, Some(v)) if v > 0 => Send::Length(v),
+            _ => Send::Done,
+        };
+
+        let end_stream = matches!(send, Send::Done);
+
+        let id = self.conn.open_stream(&fields, end_stream, timeout)?;
+        trace!("Opened HTTP/2 stream: {}", id);
+
+        self.stream = Some(id);
+        self.send = send;
+        self.recv = Recv::Head {
+            is_head_request: method == Method::HEAD,
+        };
+
+        Ok(())
+    }
+
+    fn id(&self) -> Result<u32, Error> {
+        self.stream.ok_or(Error::Http2("stream is closed"))
+    }
+
+    /// Translate a received event to HTTP/1.1 in `pending`.
+    fn recv(&mut self, event: Event) -> Result<(), Error> {
+        let end_stream = match event {
+            Event::Headers { fields, end_stream } => match self.recv {
+                Recv::Head { is_head_request } => {
+                    self.response_head(&fields, end_stream, is_head_request)?;
+                    end_stream
+                }
+                Recv::Chunked => {
+                    // The trailers go in the last chunk.
+                    self.pending.extend_from_slice(b"0\r\n");
+                    write_fields(&mut self.pending, &fields);
+                    self.pending.extend_from_slice(b"\r\n");
+                    self.finish();
+                    return Ok(());
+                }
+                Recv::Raw | Recv::NoBody => end_stream,
+            },
+            Event::Data { data, end_stream } => {
+                match self.recv {
+                    Recv::Head { .. } => return Err(Error::Http2("data before response header")),
+                    Recv::Raw => self.pending.extend_from_slice(&data),
+                    Recv::Chunked if !data.is_empty() => {
+                        self.pending
+                            .extend_from_slice(format!("{:x}\r\n", data.len()).as_bytes());
+                        self.pending.extend_from_slice(&data);
+                        self.pending.extend_from_slice(b"\r\n");
+                    }
+                    Recv::Chunked | Recv::NoBody => {}
+                }
+                end_stream
+            }
+            Event::Reset(code) => {
+                self.finish();
+                return Err(reset_error(code));
+            }
+            Event::Malformed => {
+                self.finish();
+                return Err(MALFORMED);
+            }
+        };
+
+        if end_stream {
+            if self.recv == Recv::Chunked {
+                self.pending.extend_from_slice(b"0\r\n\r\n");
+            }
+            self.finish();
+        }
+
+        Ok(())
+    }
+
+    fn response_head(
+        &mut self,
+        fields: &[Field],
+        end_stream: bool,
+        is_head_request: bool,
+    ) -> Result<(), Error> {
+        let status = fields
+            .iter()
+            .find(|(n, _)| n == b":status")
+            .and_then(|(_, v)| StatusCode::from_bytes(v).ok())
+            .ok_or(Error::Http2("missing response :status"))?;
+
+        let out = &mut self.pending;
+
+        out.extend_from_slice(b"HTTP/1.1 ");
+        out.extend_from_slice(status.as_str().as_bytes());
+        out.push(b' ');
+        out.extend_from_slice(status.canonical_reason().unwrap_or("").as_bytes());
+        out.extend_from_slice(b"\r\n");
+
+        write_fields(out, fields);
+
+        if status.is_informational() {
+            // The final response follows.
+            out.extend_from_slice(b"\r\n");
+            return Ok(());
+        }
+
+        let has_body = !is_head_request
+            && status != StatusCode::NO_CONTENT
+            && status != StatusCode::NOT_MODIFIED;
+
+        let has_length = fields.iter().any(|(n, _)| n == b"content-length");
+
+        self.recv = if !has_body {
+            Recv::NoBody
+        } else if has_length {
+            Recv::Raw
+        } else if end_stream {
+            out.extend_from_slice(b"content-length: 0\r\n");
+            Recv::Raw
+        } else {
+            out.extend_from_slice(b"transfer-encoding: chunked\r\n");
+            Recv::Chunked
+        };
+
+        out.extend_from_slice(b"\r\n");
+
+        Ok(())
+    }
+
+    /// The response is complete.
+    fn finish(&mut self) {
+        self.close();
+        self.send = Send::Head(Vec::new());
+        self.recv = Recv::Head {
+            is_head_request: false,
+        };
+    }
+
+    fn close(&mut self) {
+        if let Some(id) = self.stream.take() {
+            self.conn.close_stream(id);
+        }
+    }
+
+    /// Move pending response bytes to the input buffer.
+    fn fill_input(&mut self) -> bool {
+        let buf = self.buffers.input_append_buf();
+        let amount = buf.len().min(self.pending.len());
+        buf[..amount].copy_from_slice(&self.pending[..amount]);
+        self.buffers.input_appended(amount);
+        self.pending.drain(..amount);
+        amount > 0
+    }
+}
+
+impl Transport for Http2Transport {
+    fn buffers(&mut self) -> &mut dyn Buffers {
+        &mut self.buffers
+    }
+
+    fn transmit_output(&mut self, amount: usize, timeout: NextTimeout) -> Result<(), Error> {
+        let output = self.buffers.output()[..amount].to_vec();
+        self.send(&output, timeout)
+    }
+
+    fn await_input(&mut self, timeout: NextTimeout) -> Result<bool, Error> {
+        if self.buffers.can_use_input() {
+            return Ok(true);
+        }
+
+        loop {
+            if !self.pending.is_empty() {
+                return Ok(self.fill_input());
+            }
+
+            // Like a closed socket, when there's no request.
+            let Some(id) = self.stream else {
+                return Ok(false);
+            };
+
+            let event = self.conn.next_event(id, timeout)?;
+            self.recv(event)?;
+        }
+    }
+
+    fn is_open(&mut self) -> bool {
+        // A response body with content-length can be read to the end before the
+        // END_STREAM arrives. It might already be here.
+        if let (Some(id), Send::Done) = (self.stream, &self.send) {
+            while let Some(event) = self.conn.try_next_event(id) {
+                if self.recv(event).is_err() {
+                    return false;
+                }
+            }
+        }
+
+        self.stream.is_none() && self.pending.is_empty() && self.conn.is_usable()
+    }
+
+    fn tls_handshake_duration(&self) -> Option<std::time::Duration> {
+        self.tls_handshake
+    }
+
+    fn is_http2(&self) -> bool {
+        true
+    }
+}
+
+impl Drop for Http2Transport {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Headers that are not allowed in HTTP/2 (RFC 9113, 8.2.2).
+fn is_connection_specific(name: &HeaderName) -> bool {
+    name == header::CONNECTION
+        || name == header::HOST
+        || name == header::TE
+        || name == header::TRANSFER_ENCODING
+        || name == header::UPGRADE
+        || name == "keep-alive"
+        || name == "proxy-connection"
+}
+
+/// Write fields as HTTP/1.1 header lines, leaving out pseudo headers.
+fn write_fields(out: &mut Vec<u8>, fields: &[Field]) {
+    for (name, value) in fields {
+        let skip = name.starts_with(b":")
+            || HeaderName::from_bytes(name)
+                .map(|n| is_connection_specific(&n))
+                .unwrap_or(true);
+
+        if skip {
+            continue;
+        }
+
+        out.extend_from_slice(name);
+        out.extend_from_slice(b": ");
+        out.extend_from_slice(value);
+        out.extend_from_slice(b"\r\n");
+    }
+}
+
+/// Decodes the chunked request body ureq writes, to send it as DATA frames.
+#[derive(Default)]
+struct ChunkedDecoder {
+    state: Chunk,
+    line: Vec<u8>,
+}
+
+#[derive(Default, PartialEq, Eq)]
+enum Chunk {
+    #[default]
+    Size,
+    Data(usize),
+    DataEnd(usize),
+    Trailer,
+    Done,
+}
+
+impl ChunkedDecoder {
+    /// Decode `input` to `out`, returning true once the last chunk is complete.
+    fn decode(&mut self, mut input: &[u8], out: &mut Vec<u8>) -> Result<bool, Error> {
+        const ERR: Error = Error::Http2("invalid chunked request body");
+
+        while let Some(&byte) = input.first() {
+            match self.state {
+                Chunk::Size | Chunk::Trailer => {
+                    self.line.push(byte);
+                    input = &input[1..];
+
+                    if !self.line.ends_with(b"\n") {
+                        continue;
+                    }
+
+                    let line = std::mem::take(&mut self.line);
+                    let line = std::str::from_utf8(&line).map_err(|_| ERR)?.trim();
+
+                    self.state = if self.state == Chunk::Trailer {
+                        // Trailers are not sent, the empty line ends the body.
+                        if line.is_empty() {
+                            Chunk::Done
+                        } else {
+                            Chunk::Trailer
+                        }
+                    } else {
+                        let size = line.split(';').next().unwrap_or_default().trim();
+                        match usize::from_str_radix(size, 16).map_err(|_| ERR)? {
+                            0 => Chunk::Trailer,
+                            n => Chunk::Data(n),
+                        }
+                    };
+                }
+                Chunk::Data(n) => {
+                    let amount = n.min(input.len());
+                    out.extend_from_slice(&input[..amount]);
+                    input = &input[amount..];
+                    self.state = if amount == n {
+                        Chunk::DataEnd(2)
+                    } else {
+                        Chunk::Data(n - amount)
+                    };
+                }
+                Chunk::DataEnd(n) => {
+                    // The CRLF after the chunk data.
+                    input = &input[1..];
+                    self.state = if n == 1 {
+                        Chunk::Size
+                    } else {
+                        Chunk::DataEnd(n - 1)
+                    };
+                }
+                Chunk::Done => return Err(ERR),
+            }
+        }
+
+        Ok(self.state == Chunk::Done)
+    }
+}
+
+impl fmt::Debug for Http2Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Http2Transport")
+            .field("conn", &self.conn)
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_chunked() {
+        let mut decoder = ChunkedDecoder::default();
+        let mut out = Vec::new();
+
+        assert!(!decoder.decode(b"5\r\nhel", &mut out).unwrap());
+        assert!(!decoder
+            .decode(b"lo\r\n6;ext=1\r\n world\r", &mut out)
+            .unwrap());
+        assert!(decoder.decode(b"\n0\r\n\r\n", &mut out).unwrap());
+        assert_eq!(out, b"hello world");
+
+        assert!(decoder.decode(b"1", &mut out).is_err());
+    }
+
+    #[test]
+    fn response_fields() {
+        let fields = vec![
+            (b":status".to_vec(), b"200".to_vec()),
+            (b"content-type".to_vec(), b"text/plain".to_vec()),
+            (b"connection".to_vec(), b"close".to_vec()),
+        ];
+        let mut out = Vec::new();
+        write_fields(&mut out, &fields);
+        assert_eq!(out, b"content-type: text/plain\r\n");
+    }
+}
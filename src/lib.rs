@@ -150,12 +150,16 @@
 //! * **socks-proxy** enables proxy config using the `socks4://`, `socks4a://`, `socks5://`
 //!    and `socks://` (equal to `socks5://`) prefix
 //! * **cookies** enables cookies
-//! * **gzip** enables requests of gzip-compressed responses and decompresses them
+//! * **gzip** enables requests of gzip and deflate compressed responses and decompresses them
 //! * **brotli** enables requests brotli-compressed responses and decompresses them
+//! * **zstd** enables requests zstd-compressed responses and decompresses them
 //! * **charset** enables interpreting the charset part of the Content-Type header
 //!    (e.g.  `Content-Type: text/plain; charset=iso-8859-1`). Without this, the
 //!    library defaults to Rust's built in `utf-8`
 //! * **json** enables JSON sending and receiving via serde_json
+//! * **websocket** enables a blocking WebSocket client via [`Agent::websocket()`]
+//! * **http2** enables HTTP/2, negotiated for https and optionally with prior knowledge for http.
+//!   It must also be turned on with [`ConfigBuilder::http2()`](config::ConfigBuilder::http2)
 //! * **vendored** compiles and statically links to a copy of non-Rust vendors (e.g. OpenSSL from `native-tls`)
 //!
 //! # TLS (https)
@@ -425,7 +429,7 @@ use std::convert::TryFrom;
 /// Re-exported http-crate.
 pub use ureq_proto::http;
 
-pub use body::{Body, BodyBuilder, BodyReader, BodyWithConfig};
+pub use body::{Body, BodyBuilder, BodyPart, BodyParts, BodyReader, BodyWithConfig};
 use http::Method;
 use http::{Request, Response, Uri};
 pub use proxy::Proxy;
@@ -436,14 +440,19 @@ pub use send_body::AsSendBody;
 mod agent;
 mod body;
 pub mod config;
+mod download;
 mod error;
+mod multipart;
 mod pool;
 mod proxy;
 mod query;
+mod redirect;
 mod request;
 mod run;
 mod send_body;
+mod sse;
 mod timings;
+mod upgrade;
 mod util;
 
 pub mod middleware;
@@ -453,15 +462,27 @@ pub mod transport;
 #[cfg(feature = "_tls")]
 pub mod tls;
 
+#[cfg(feature = "http2")]
+mod http2;
+
 #[cfg(feature = "cookies")]
 mod cookies;
 #[cfg(feature = "cookies")]
 pub use cookies::{Cookie, CookieJar};
 
+#[cfg(feature = "websocket")]
+mod websocket;
+#[cfg(feature = "websocket")]
+pub use websocket::{CloseFrame, Message, WebSocket, WebSocketBuilder};
+
 pub use agent::Agent;
-pub use error::Error;
+pub use error::{Error, ErrorResponse};
+pub use multipart::{Multipart, Part};
+pub use redirect::{RedirectAction, RedirectHistory};
 pub use send_body::SendBody;
-pub use timings::Timeout;
+pub use sse::{Event, EventSource, EventSourceBuilder, EventStream};
+pub use timings::{HopTimings, ResponseTimings, Timeout};
+pub use upgrade::Upgraded;
 
 /// Run a [`http::Request<impl AsSendBody>`].
 pub fn run(request: Request<impl AsSendBody>) -> Result<Response<Body>, Error> {
@@ -519,6 +540,67 @@ pub(crate) mod test {
         *INIT_LOG
     }
 
+    /// Servers on localhost, for protocols the test connector doesn't speak.
+    #[cfg(any(feature = "websocket", feature = "http2"))]
+    pub mod local {
+        use std::net::{SocketAddr, TcpListener, TcpStream};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use std::thread;
+
+        use crate::config::Config;
+        use crate::http::Uri;
+        use crate::resolver::{ResolvedSocketAddrs, Resolver};
+        use crate::transport::{NextTimeout, TcpConnector};
+        use crate::util::ArrayVec;
+        use crate::{Agent, Error};
+
+        /// Server handling every connection in a thread of its own.
+        ///
+        /// Returns the port and the number of accepted connections.
+        pub fn server(
+            handler: impl Fn(TcpStream) + Send + Sync + 'static,
+        ) -> (u16, Arc<AtomicUsize>) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let accepted = Arc::new(AtomicUsize::new(0));
+
+            let count = accepted.clone();
+            let handler = Arc::new(handler);
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    count.fetch_add(1, Ordering::SeqCst);
+                    let handler = handler.clone();
+                    thread::spawn(move || handler(stream.unwrap()));
+                }
+            });
+
+            (port, accepted)
+        }
+
+        /// Agent for a [`server()`], which bypasses the test connector.
+        pub fn agent(config: Config) -> Agent {
+            Agent::with_parts(config, TcpConnector::default(), Localhost)
+        }
+
+        /// Resolves every host to localhost.
+        #[derive(Debug)]
+        struct Localhost;
+
+        impl Resolver for Localhost {
+            fn resolve(
+                &self,
+                uri: &Uri,
+                _config: &Config,
+                _timeout: NextTimeout,
+            ) -> Result<ResolvedSocketAddrs, Error> {
+                let mut addrs = ArrayVec::from_fn(|_| "0.0.0.0:1".parse().unwrap());
+                addrs.push(SocketAddr::from(([127, 0, 0, 1], uri.port_u16().unwrap())));
+                Ok(addrs)
+            }
+        }
+    }
+
     #[test]
     fn connect_http_google() {
         init_test_log();
@@ -711,6 +793,338 @@ pub(crate) mod test {
         assert_eq!(txt, "");
     }
 
+    #[test]
+    fn redirect_history() {
+        init_test_log();
+        let res = get("http://httpbin.org/redirect-to?url=%2Fget")
+            .call()
+            .unwrap();
+        let history = res.extensions().get::<RedirectHistory>().unwrap();
+        let visited: Vec<_> = history
+            .visited()
+            .iter()
+            .map(|(u, s)| (u.to_string(), s.as_u16()))
+            .collect();
+        assert_eq!(
+            visited,
+            [
+                ("http://httpbin.org/redirect-to?url=%2Fget".to_string(), 302),
+                ("http://httpbin.org/get".to_string(), 200)
+            ]
+        );
+        assert_eq!(history.effective_uri(), "http://httpbin.org/get");
+    }
+
+    #[test]
+    fn response_timings() {
+        init_test_log();
+        let mut res = get("http://httpbin.org/redirect-to?url=%2Fget")
+            .call()
+            .unwrap();
+
+        let timings = res.extensions().get::<ResponseTimings>().unwrap().clone();
+        assert_eq!(timings.hops().len(), 2);
+
+        let first = &timings.hops()[0];
+        assert!(first.dns().is_some());
+        assert!(first.connect().is_some());
+        assert!(first.time_to_first_byte().is_some());
+        assert!(first.body_download().is_some());
+
+        let last = &timings.hops()[1];
+        assert!(last.time_to_first_byte().is_some());
+        assert!(last.body_download().is_none());
+
+        res.body_mut().read_to_string().unwrap();
+        assert!(last.body_download().is_some());
+    }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn redirect_policy_stop() {
+        init_test_log();
+        let agent: Agent = Config::builder()
+            .redirect_policy(|req, status, location| {
+                assert_eq!(req.uri(), "http://httpbin.org/redirect-to?url=%2Fget");
+                assert_eq!(status, 302);
+                assert_eq!(location, "/get");
+                RedirectAction::Stop
+            })
+            .build()
+            .into();
+        let mut res = agent
+            .get("http://httpbin.org/redirect-to?url=%2Fget")
+            .call()
+            .unwrap();
+        assert_eq!(res.status(), 302);
+        let txt = res.body_mut().read_to_string().unwrap();
+        assert_eq!(txt, "You've been redirected");
+    }
+
+    #[test]
+    fn redirect_policy_error() {
+        init_test_log();
+        let agent: Agent = Config::builder()
+            .redirect_policy(|_, _, _| RedirectAction::Error)
+            .build()
+            .into();
+        let err = agent
+            .get("http://httpbin.org/redirect-to?url=%2Fget")
+            .call()
+            .unwrap_err();
+        assert!(matches!(err, Error::RedirectDenied(_)));
+    }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn informational_responses() {
+        use std::sync::{Arc, Mutex};
+
+        init_test_log();
+        transport::set_handler(
+            "/early-hints",
+            103,
+            &[("link", "</style.css>; rel=preload")],
+            b"HTTP/1.1 102 Processing\r\n\r\n\
+            HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok",
+        );
+
+        let seen = Arc::new(Mutex::new(vec![]));
+        let seen2 = seen.clone();
+
+        let agent: Agent = Config::builder()
+            .informational_handler(move |status, headers| {
+                let link = headers.get("link").map(|v| v.to_str().unwrap().to_string());
+                seen2.lock().unwrap().push((status.as_u16(), link));
+            })
+            .build()
+            .into();
+
+        let mut res = agent.get("https://my.test/early-hints").call().unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "ok");
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                (103, Some("</style.css>; rel=preload".to_string())),
+                (102, None)
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn upload_progress() {
+        use std::sync::{Arc, Mutex};
+
+        init_test_log();
+        transport::set_handler("/upload", 200, &[("content-length", "0")], b"");
+
+        let seen = Arc::new(Mutex::new(vec![]));
+        let seen2 = seen.clone();
+
+        let agent: Agent = Config::builder()
+            .upload_progress(move |sent, total| seen2.lock().unwrap().push((sent, total)))
+            .build()
+            .into();
+
+        agent
+            .post("https://my.test/upload")
+            .send(&[7; 1000][..])
+            .unwrap();
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.last(), Some(&(1000, Some(1000))));
+    }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn send_multipart() {
+        init_test_log();
+        transport::set_handler_fn("/multipart", |_uri, req, w| {
+            let content_type = req.headers()["content-type"].to_str().unwrap();
+            assert!(content_type.starts_with("multipart/form-data; boundary=ureq-boundary-"));
+            assert!(req.headers().contains_key("content-length"));
+            write!(w, "HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+        });
+
+        let form = Multipart::new()
+            .text("a", "1")
+            .part("b", Part::bytes("2").file_name("b.txt"));
+
+        post("https://my.test/multipart")
+            .send_multipart(form)
+            .unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn download_progress() {
+        use std::sync::{Arc, Mutex};
+
+        init_test_log();
+        transport::set_handler("/length", 200, &[("content-length", "5")], b"hello");
+        transport::set_handler(
+            "/chunked",
+            200,
+            &[("transfer-encoding", "chunked")],
+            b"5\r\nhello\r\n0\r\n\r\n",
+        );
+
+        let seen = Arc::new(Mutex::new((0, 0, None)));
+        let seen2 = seen.clone();
+
+        let agent: Agent = Config::builder()
+            .download_progress(move |p| {
+                *seen2.lock().unwrap() = (p.raw(), p.decoded(), p.content_length())
+            })
+            .build()
+            .into();
+
+        let mut res = agent.get("https://my.test/length").call().unwrap();
+        res.body_mut().read_to_string().unwrap();
+        assert_eq!(*seen.lock().unwrap(), (5, 5, Some(5)));
+
+        let mut res = agent.get("https://my.test/chunked").call().unwrap();
+        res.body_mut().read_to_string().unwrap();
+        assert_eq!(*seen.lock().unwrap(), (15, 5, None));
+    }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn status_error_policy() {
+        init_test_log();
+        transport::set_handler("/missing", 404, &[("content-length", "4")], b"gone");
+        transport::set_handler("/moved", 302, &[("location", "/elsewhere")], b"");
+
+        let agent: Agent = Config::builder()
+            .max_redirects(0)
+            .status_error_policy(|status, _| status != 404 && status.as_u16() >= 300)
+            .build()
+            .into();
+
+        let mut res = agent.get("https://my.test/missing").call().unwrap();
+        assert_eq!(res.status(), 404);
+        assert_eq!(res.body_mut().read_to_string().unwrap(), "gone");
+
+        let err = agent.get("https://my.test/moved").call().unwrap_err();
+        assert_eq!(err.status_code(), Some(302));
+
+        // Request level policy overrides the agent.
+        let err = agent
+            .get("https://my.test/missing")
+            .config()
+            .status_error_policy(|status, _| status.is_client_error())
+            .build()
+            .call()
+            .unwrap_err();
+        assert_eq!(err.status_code(), Some(404));
+    }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn upgrade_connection() {
+        init_test_log();
+        transport::set_handler(
+            "/upgrade",
+            101,
+            &[("connection", "upgrade"), ("upgrade", "foo")],
+            b"hello foo",
+        );
+
+        let agent = Agent::new_with_defaults();
+
+        let res = agent
+            .get("https://my.test/upgrade")
+            .header("connection", "upgrade")
+            .header("upgrade", "foo")
+            .call()
+            .unwrap();
+        assert_eq!(res.status(), 101);
+
+        let mut stream = res.into_body().into_upgraded().unwrap();
+
+        // The bytes after the response header are read first.
+        let mut buf = vec![];
+        io::Read::read_to_end(&mut stream, &mut buf).unwrap();
+        assert_eq!(buf, b"hello foo");
+
+        drop(stream);
+        assert_eq!(agent.pool_count(), 0);
+    }
+
+    #[test]
+    #[cfg(all(feature = "_test", feature = "http2"))]
+    fn upgrade_connection_uses_http1() {
+        init_test_log();
+        transport::set_handler("/upgrade-h1", 101, &[("upgrade", "foo")], b"");
+
+        // The test server only speaks HTTP/1.1, which prior knowledge would skip.
+        let agent: Agent = Config::builder()
+            .http2(true)
+            .http2_prior_knowledge(true)
+            .build()
+            .into();
+
+        let res = agent
+            .get("http://my.test/upgrade-h1")
+            .header("connection", "upgrade")
+            .header("upgrade", "foo")
+            .call()
+            .unwrap();
+
+        assert_eq!(res.version(), http::Version::HTTP_11);
+        assert!(res.into_body().into_upgraded().is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn upgrade_not_switching_protocols() {
+        init_test_log();
+        let res = get("https://my.test/get").call().unwrap();
+        let err = res.into_body().into_upgraded().unwrap_err();
+        assert!(matches!(err, Error::NotUpgraded));
+    }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn tunnel_through_proxy() {
+        init_test_log();
+        transport::set_handler("db.test:5432", 200, &[], b"hello from db");
+
+        let agent: Agent = Config::builder()
+            .proxy(Some(Proxy::new("http://my.proxy:3128").unwrap()))
+            .build()
+            .into();
+
+        let mut stream = agent.tunnel("db.test", 5432).unwrap();
+
+        let mut buf = vec![];
+        io::Read::read_to_end(&mut stream, &mut buf).unwrap();
+        assert_eq!(buf, b"hello from db");
+    }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn tunnel_refused_by_proxy() {
+        init_test_log();
+        transport::set_handler("forbidden.test", 403, &[], b"");
+
+        let agent: Agent = Config::builder()
+            .proxy(Some(Proxy::new("http://my.proxy:3128").unwrap()))
+            .build()
+            .into();
+
+        let err = agent.tunnel("forbidden.test", 22).unwrap_err();
+        assert!(matches!(err, Error::ConnectProxyFailed(_)));
+
+        let err = Agent::new_with_defaults()
+            .tunnel("db.test", 5432)
+            .unwrap_err();
+        assert!(matches!(err, Error::ConnectProxyFailed(_)));
+    }
+
     #[test]
     fn connect_https_invalid_name() {
         let result = get("https://example.com{REQUEST_URI}/").call();
@@ -729,6 +1143,38 @@ pub(crate) mod test {
             .expect("to send correctly");
     }
 
+    #[test]
+    #[cfg(feature = "_test")]
+    fn retry_on_stale_pooled_connection() {
+        init_test_log();
+        let agent = Agent::new_with_defaults();
+
+        let mut res = agent.get("https://httpbin.org/get").call().unwrap();
+        res.body_mut().read_to_string().unwrap();
+        assert_eq!(agent.pool_count(), 1);
+
+        // The test server closes after each request, which makes the pooled connection stale.
+        let mut res = agent.put("https://httpbin.org/put").send("foo").unwrap();
+        res.body_mut().read_to_string().unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "_test")]
+    fn no_retry_on_stale_pooled_connection_for_post() {
+        init_test_log();
+        let agent = Agent::new_with_defaults();
+
+        let mut res = agent.get("https://httpbin.org/get").call().unwrap();
+        res.body_mut().read_to_string().unwrap();
+        assert_eq!(agent.pool_count(), 1);
+
+        let err = agent
+            .post("https://httpbin.org/post")
+            .send("foo")
+            .unwrap_err();
+        assert!(matches!(err, Error::Io(_)));
+    }
+
     #[test]
     #[cfg(all(feature = "cookies", feature = "_test"))]
     fn store_response_cookies() {
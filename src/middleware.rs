@@ -7,6 +7,9 @@ use crate::http;
 use crate::run::run;
 use crate::{Agent, Body, Error, SendBody};
 
+mod retry;
+pub use retry::{RetryMiddleware, RetryableError};
+
 /// Chained processing of request (and response).
 ///
 /// # Middleware as `fn`
@@ -181,6 +184,7 @@ impl MiddlewareChain {
 }
 
 /// Continuation of a [`Middleware`] chain.
+#[derive(Clone)]
 pub struct MiddlewareNext<'a> {
     agent: &'a Agent,
     index: usize,
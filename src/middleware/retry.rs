@@ -0,0 +1,692 @@
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use http::{header, HeaderMap, Method, Request, Response, StatusCode};
+
+use crate::config::RequestLevelConfig;
+use crate::http;
+use crate::util::random_u64;
+use crate::{AsSendBody, Body, Error, SendBody};
+
+use super::{Middleware, MiddlewareNext};
+
+/// Middleware that retries failed requests.
+///
+/// A request is retried when it fails with one of the configured [`RetryableError`],
+/// or the response has one of the configured status codes. Between attempts, the
+/// middleware sleeps using exponential backoff with jitter, or the duration given by
+/// a `Retry-After` response header.
+///
+/// Only requests that are safe to replay are retried:
+///
+/// * The method must be one of the configured methods, which by default are the
+///   idempotent methods `GET`, `HEAD`, `OPTIONS`, `TRACE`, `PUT` and `DELETE`.
+/// * The request body must be empty or a byte slice (such as `&str` or `Vec<u8>`).
+///   Bodies from a [`Read`](std::io::Read) are consumed by the first attempt and
+///   never retried.
+///
+/// Since the middleware needs to see the status of the response, it handles the
+/// [`http_status_as_error`](crate::config::ConfigBuilder::http_status_as_error) setting
+/// itself once the retries are exhausted.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use ureq::Agent;
+/// use ureq::middleware::RetryMiddleware;
+///
+/// let retry = RetryMiddleware::new()
+///     .max_attempts(5)
+///     .base_delay(Duration::from_millis(200));
+///
+/// let agent: Agent = Agent::config_builder()
+///     .middleware(retry)
+///     .build()
+///     .into();
+///
+/// agent.get("http://httpbin.org/get").call()?;
+/// # Ok::<_, ureq::Error>(())
+/// ```
+pub struct RetryMiddleware {
+    max_attempts: u32,
+    errors: Vec<RetryableError>,
+    statuses: Vec<StatusCode>,
+    methods: Vec<Method>,
+    base_delay: Duration,
+    max_delay: Duration,
+    budget: Option<Arc<RetryBudget>>,
+}
+
+/// Errors that can be retried by [`RetryMiddleware`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RetryableError {
+    /// Failure to establish a connection, such as connection refused.
+    Connect,
+
+    /// The connection was reset or closed by the remote while in use.
+    Reset,
+
+    /// The request hit one of the configured timeouts.
+    Timeout,
+}
+
+impl RetryableError {
+    fn classify(error: &Error) -> Option<RetryableError> {
+        match error {
+            Error::ConnectionFailed => Some(RetryableError::Connect),
+            Error::Timeout(_) => Some(RetryableError::Timeout),
+            Error::Io(e) => match e.kind() {
+                io::ErrorKind::ConnectionRefused => Some(RetryableError::Connect),
+                io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::UnexpectedEof => Some(RetryableError::Reset),
+                io::ErrorKind::TimedOut => Some(RetryableError::Timeout),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl RetryMiddleware {
+    /// Creates a retry middleware with default settings.
+    pub fn new() -> Self {
+        RetryMiddleware {
+            max_attempts: 3,
+            errors: vec![
+                RetryableError::Connect,
+                RetryableError::Reset,
+                RetryableError::Timeout,
+            ],
+            statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            methods: vec![
+                Method::GET,
+                Method::HEAD,
+                Method::OPTIONS,
+                Method::TRACE,
+                Method::PUT,
+                Method::DELETE,
+            ],
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            budget: Some(Arc::new(RetryBudget::new(0.2, 10))),
+        }
+    }
+
+    /// Max number of attempts, including the first one.
+    ///
+    /// Defaults to 3.
+    pub fn max_attempts(mut self, v: u32) -> Self {
+        self.max_attempts = v;
+        self
+    }
+
+    /// The errors that cause a retry.
+    ///
+    /// Defaults to all of [`RetryableError`].
+    pub fn errors(mut self, v: impl IntoIterator<Item = RetryableError>) -> Self {
+        self.errors = v.into_iter().collect();
+        self
+    }
+
+    /// The response status codes that cause a retry.
+    ///
+    /// Defaults to 429, 502, 503 and 504.
+    pub fn statuses(mut self, v: impl IntoIterator<Item = StatusCode>) -> Self {
+        self.statuses = v.into_iter().collect();
+        self
+    }
+
+    /// The request methods that are retried.
+    ///
+    /// Defaults to the idempotent methods `GET`, `HEAD`, `OPTIONS`, `TRACE`,
+    /// `PUT` and `DELETE`. Adding non-idempotent methods such as `POST` risks
+    /// the server acting on the request more than once.
+    pub fn methods(mut self, v: impl IntoIterator<Item = Method>) -> Self {
+        self.methods = v.into_iter().collect();
+        self
+    }
+
+    /// Delay before the first retry.
+    ///
+    /// The delay doubles for each following retry, and a random jitter of up to
+    /// half the delay is subtracted.
+    ///
+    /// Defaults to 100ms.
+    pub fn base_delay(mut self, v: Duration) -> Self {
+        self.base_delay = v;
+        self
+    }
+
+    /// Max delay between two attempts.
+    ///
+    /// This caps the exponential backoff. A `Retry-After` header asking for a
+    /// longer delay than this stops the retries and returns the response.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn max_delay(mut self, v: Duration) -> Self {
+        self.max_delay = v;
+        self
+    }
+
+    /// Limit the number of retries in relation to the number of requests.
+    ///
+    /// The budget is a bucket holding at most `reserve` retries. Every request passing
+    /// the middleware adds `ratio` of a retry to the bucket, and every retry takes one
+    /// out. When the bucket is empty, failed requests are not retried. This prevents
+    /// retries from multiplying the load on a server that is already struggling.
+    ///
+    /// The budget is shared by all requests using this middleware instance.
+    ///
+    /// Set to `None` to not limit the retries other than by
+    /// [`max_attempts`](Self::max_attempts).
+    ///
+    /// Defaults to a ratio of `0.2` and a reserve of `10`.
+    pub fn budget(mut self, v: Option<(f64, u32)>) -> Self {
+        self.budget = v.map(|(ratio, reserve)| Arc::new(RetryBudget::new(ratio, reserve)));
+        self
+    }
+
+    fn is_retryable_error(&self, error: &Error) -> bool {
+        RetryableError::classify(error)
+            .map(|e| self.errors.contains(&e))
+            .unwrap_or(false)
+    }
+
+    /// Delay before the given retry. `retry` starts at 1.
+    fn backoff(&self, retry: u32) -> Duration {
+        let exp = retry.saturating_sub(1).min(31);
+        let delay = self.base_delay.saturating_mul(1 << exp).min(self.max_delay);
+
+        // Equal jitter: half of the delay is fixed, half is random.
+        let half = delay / 2;
+        let jitter_nanos = half.as_nanos() as u64;
+        let jitter = if jitter_nanos > 0 {
+            Duration::from_nanos(random_u64() % jitter_nanos)
+        } else {
+            Duration::ZERO
+        };
+
+        half + jitter
+    }
+}
+
+impl Middleware for RetryMiddleware {
+    fn handle(
+        &self,
+        request: Request<SendBody>,
+        next: MiddlewareNext,
+    ) -> Result<Response<Body>, Error> {
+        let (mut parts, mut body) = request.into_parts();
+
+        if let Some(budget) = &self.budget {
+            budget.deposit();
+        }
+
+        let can_retry =
+            self.max_attempts > 1 && self.methods.contains(&parts.method) && body.is_replayable();
+
+        if !can_retry {
+            let request = Request::from_parts(parts, body);
+            return next.handle(request);
+        }
+
+        // We need to see the status of the response to decide on retries, which means
+        // we must handle http_status_as_error ourselves.
+        if parts.extensions.get::<RequestLevelConfig>().is_none() {
+            let config = next.agent.new_request_level_config();
+            parts.extensions.insert(config);
+        }
+        // unwrap is ok because of above.
+        let config = &mut parts.extensions.get_mut::<RequestLevelConfig>().unwrap().0;
+        let status_config = config.clone();
+        config.http_status_as_error = false;
+
+        let mut attempt = 1;
+
+        let response = loop {
+            let request = Request::from_parts(parts.clone(), body.as_body());
+            let result = next.clone().handle(request);
+
+            let last_attempt = attempt >= self.max_attempts;
+
+            let delay = match &result {
+                Ok(response) if !last_attempt && self.statuses.contains(&response.status()) => {
+                    match retry_after(response.headers()) {
+                        Some(v) if v > self.max_delay => {
+                            debug!("Retry-After {:?} exceeds max delay", v);
+                            None
+                        }
+                        Some(v) => Some(v),
+                        None => Some(self.backoff(attempt)),
+                    }
+                }
+                Err(e) if !last_attempt && self.is_retryable_error(e) => {
+                    Some(self.backoff(attempt))
+                }
+                _ => None,
+            };
+
+            let Some(delay) = delay else {
+                break result?;
+            };
+
+            let within_budget = self.budget.as_ref().map(|b| b.withdraw()).unwrap_or(true);
+
+            if !within_budget {
+                debug!("Retry budget exhausted");
+                break result?;
+            }
+
+            match &result {
+                Ok(r) => debug!(
+                    "Retry {} in {:?} after status {}",
+                    attempt,
+                    delay,
+                    r.status()
+                ),
+                Err(e) => debug!("Retry {} in {:?} after error: {}", attempt, delay, e),
+            }
+
+            // Drop the previous response before sleeping, to not hold on to the connection.
+            drop(result);
+
+            thread::sleep(delay);
+
+            attempt += 1;
+        };
+
+        if status_config.is_status_error(response.status(), response.headers()) {
+            return Err(status_config.status_error(response));
+        }
+
+        Ok(response)
+    }
+}
+
+/// Parse a `Retry-After` header, which is either delay-seconds or an HTTP-date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(header::RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let at = parse_http_date(value)?;
+
+    // A date in the past means we can retry right away.
+    Some(
+        at.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// Parse an IMF-fixdate such as `Sun, 06 Nov 1994 08:49:37 GMT`.
+///
+/// The obsolete RFC 850 and asctime formats are not supported.
+fn parse_http_date(s: &str) -> Option<SystemTime> {
+    let (_, rest) = s.split_once(", ")?;
+    let mut parts = rest.split(' ');
+
+    let day: u64 = parts.next()?.parse().ok()?;
+    let month = match parts.next()? {
+        "Jan" => 1,
+        "Feb" => 2,
+        "Mar" => 3,
+        "Apr" => 4,
+        "May" => 5,
+        "Jun" => 6,
+        "Jul" => 7,
+        "Aug" => 8,
+        "Sep" => 9,
+        "Oct" => 10,
+        "Nov" => 11,
+        "Dec" => 12,
+        _ => return None,
+    };
+    let year: u64 = parts.next()?.parse().ok()?;
+
+    let mut hms = parts.next()?.split(':');
+    let hour: u64 = hms.next()?.parse().ok()?;
+    let minute: u64 = hms.next()?.parse().ok()?;
+    let second: u64 = hms.next()?.parse().ok()?;
+
+    if parts.next()? != "GMT" || year < 1970 || day == 0 || day > 31 {
+        return None;
+    }
+
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let days = days_since_epoch(year, month, day);
+    let secs = days * 86_400 + hour * 3600 + minute * 60 + second;
+
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Days from 1970-01-01 to the given date in the proleptic Gregorian calendar.
+fn days_since_epoch(year: u64, month: u64, day: u64) -> u64 {
+    // Algorithm from http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    // shifted such that the year starts in March.
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
+
+/// Token bucket limiting retries in relation to requests.
+struct RetryBudget {
+    // Tokens are in thousandths of a retry.
+    tokens: AtomicI64,
+    max: i64,
+    deposit: i64,
+}
+
+impl RetryBudget {
+    fn new(ratio: f64, reserve: u32) -> Self {
+        let max = reserve as i64 * 1000;
+        RetryBudget {
+            tokens: AtomicI64::new(max),
+            max,
+            deposit: (ratio * 1000.0) as i64,
+        }
+    }
+
+    fn deposit(&self) {
+        let _ = self
+            .tokens
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |t| {
+                Some((t + self.deposit).min(self.max))
+            });
+    }
+
+    fn withdraw(&self) -> bool {
+        self.tokens
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |t| {
+                if t >= 1000 {
+                    Some(t - 1000)
+                } else {
+                    None
+                }
+            })
+            .is_ok()
+    }
+}
+
+impl Default for RetryMiddleware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for RetryMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryMiddleware")
+            .field("max_attempts", &self.max_attempts)
+            .field("errors", &self.errors)
+            .field("statuses", &self.statuses)
+            .field("methods", &self.methods)
+            .field("base_delay", &self.base_delay)
+            .field("max_delay", &self.max_delay)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::Agent;
+
+    /// Fake middleware responding with a sequence of statuses without doing any request.
+    struct Sequence {
+        count: Arc<AtomicUsize>,
+        statuses: &'static [u16],
+        headers: &'static [(&'static str, &'static str)],
+    }
+
+    impl Middleware for Sequence {
+        fn handle(&self, _: Request<SendBody>, _: MiddlewareNext) -> Result<Response<Body>, Error> {
+            let n = self.count.fetch_add(1, Ordering::SeqCst);
+            let status = self.statuses[n.min(self.statuses.len() - 1)];
+            let mut builder = Response::builder().status(status);
+            for (k, v) in self.headers {
+                builder = builder.header(*k, *v);
+            }
+            Ok(builder.body(Body::builder().data("")).unwrap())
+        }
+    }
+
+    fn status_sequence(
+        statuses: &'static [u16],
+        headers: &'static [(&'static str, &'static str)],
+    ) -> (Arc<AtomicUsize>, Sequence) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let mw = Sequence {
+            count: count.clone(),
+            statuses,
+            headers,
+        };
+        (count, mw)
+    }
+
+    fn agent_with(retry: RetryMiddleware, mw: impl Middleware) -> Agent {
+        Config::builder()
+            .middleware(retry.base_delay(Duration::ZERO))
+            .middleware(mw)
+            .build()
+            .into()
+    }
+
+    #[test]
+    fn retry_on_status() {
+        let (count, mw) = status_sequence(&[503, 502, 200], &[]);
+        let agent = agent_with(RetryMiddleware::new(), mw);
+
+        let res = agent.get("https://example.test/").call().unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn retry_gives_up_after_max_attempts() {
+        let (count, mw) = status_sequence(&[503], &[]);
+        let agent = agent_with(RetryMiddleware::new().max_attempts(2), mw);
+
+        let err = agent.get("https://example.test/").call().unwrap_err();
+        assert!(matches!(err, Error::StatusCode(503)));
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn retry_keeps_status_without_status_as_error() {
+        let (_, mw) = status_sequence(&[503], &[]);
+        let agent = agent_with(RetryMiddleware::new(), mw);
+
+        let res = agent
+            .get("https://example.test/")
+            .config()
+            .http_status_as_error(false)
+            .build()
+            .call()
+            .unwrap();
+        assert_eq!(res.status(), 503);
+    }
+
+    #[test]
+    fn no_retry_for_post() {
+        let (count, mw) = status_sequence(&[503, 200], &[]);
+        let agent = agent_with(RetryMiddleware::new(), mw);
+
+        // The fake middleware bypasses the status as error handling in run().
+        let res = agent.post("https://example.test/").send("hi").unwrap();
+        assert_eq!(res.status(), 503);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn no_retry_for_reader_body() {
+        let (count, mw) = status_sequence(&[503, 200], &[]);
+        let agent = agent_with(RetryMiddleware::new(), mw);
+
+        let mut data: &[u8] = b"hi";
+        let res = agent
+            .put("https://example.test/")
+            .send(SendBody::from_reader(&mut data))
+            .unwrap();
+        assert_eq!(res.status(), 503);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn retry_replays_slice_body() {
+        let seen = Arc::new(std::sync::Mutex::new(vec![]));
+        let seen2 = seen.clone();
+
+        let mw = move |req: Request<SendBody>, _: MiddlewareNext| {
+            let mut body = req.into_body();
+            let mut buf = [0; 10];
+            let n = body.read(&mut buf).unwrap();
+            let mut seen = seen2.lock().unwrap();
+            seen.push(buf[..n].to_vec());
+            let status = if seen.len() == 1 { 503 } else { 200 };
+            Ok(Response::builder()
+                .status(status)
+                .body(Body::builder().data(""))
+                .unwrap())
+        };
+
+        let agent = agent_with(RetryMiddleware::new(), mw);
+        agent.put("https://example.test/").send("hello").unwrap();
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [b"hello".to_vec(), b"hello".to_vec()]
+        );
+    }
+
+    #[test]
+    fn retry_on_error() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let count2 = count.clone();
+
+        let mw = move |_: Request<SendBody>, _: MiddlewareNext| {
+            if count2.fetch_add(1, Ordering::SeqCst) == 0 {
+                return Err(Error::disconnected());
+            }
+            Ok(Response::builder().body(Body::builder().data("")).unwrap())
+        };
+
+        let agent = agent_with(RetryMiddleware::new(), mw);
+        agent.get("https://example.test/").call().unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn no_retry_on_other_error() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let count2 = count.clone();
+
+        let mw = move |_: Request<SendBody>, _: MiddlewareNext| {
+            count2.fetch_add(1, Ordering::SeqCst);
+            Err(Error::HostNotFound)
+        };
+
+        let agent = agent_with(RetryMiddleware::new(), mw);
+        agent.get("https://example.test/").call().unwrap_err();
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn retry_after_too_long() {
+        let (count, mw) = status_sequence(&[429, 200], &[("retry-after", "3600")]);
+        let agent = agent_with(RetryMiddleware::new(), mw);
+
+        let err = agent.get("https://example.test/").call().unwrap_err();
+        assert!(matches!(err, Error::StatusCode(429)));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn retry_budget_exhausted() {
+        let (count, mw) = status_sequence(&[503], &[]);
+        let agent = agent_with(RetryMiddleware::new().budget(Some((0.0, 1))), mw);
+
+        // First request may retry once, using up the budget.
+        agent.get("https://example.test/").call().unwrap_err();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+
+        // Second request is not retried.
+        agent.get("https://example.test/").call().unwrap_err();
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn retry_after_seconds() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", "120".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn retry_after_date_in_past() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "retry-after",
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn http_date() {
+        let t = parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+        assert_eq!(
+            t.duration_since(UNIX_EPOCH).unwrap(),
+            Duration::from_secs(784111777)
+        );
+
+        let t = parse_http_date("Thu, 29 Feb 2024 00:00:00 GMT").unwrap();
+        assert_eq!(
+            t.duration_since(UNIX_EPOCH).unwrap(),
+            Duration::from_secs(1709164800)
+        );
+
+        assert!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT").is_none());
+        assert!(parse_http_date("garbage").is_none());
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let retry = RetryMiddleware::new()
+            .base_delay(Duration::from_secs(1))
+            .max_delay(Duration::from_secs(4));
+
+        for retry_no in 1..40 {
+            let d = retry.backoff(retry_no);
+            assert!(d <= Duration::from_secs(4));
+        }
+
+        let d = retry.backoff(1);
+        assert!(d >= Duration::from_millis(500) && d <= Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,391 @@
+//! Multipart form data.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+
+use ureq_proto::BodyMode;
+
+use crate::http::HeaderValue;
+use crate::util::random_u64;
+use crate::Error;
+
+/// A `multipart/form-data` request body.
+///
+/// Used to upload files, together with text fields, the same way as an HTML form with
+/// `enctype="multipart/form-data"`. The body is streamed, which means files are read
+/// while the request is sent, not held in memory.
+///
+/// If every part has a known size, the request is sent with a `Content-Length` header,
+/// otherwise with `Transfer-Encoding: chunked`.
+///
+/// Send the form with [`RequestBuilder::send_multipart()`](crate::RequestBuilder::send_multipart),
+/// which also sets the `Content-Type` header.
+///
+/// # Example
+///
+/// ```no_run
+/// use ureq::{Multipart, Part};
+///
+/// let form = Multipart::new()
+///     .text("description", "Holiday pictures")
+///     .file("picture", "beach.jpg")?
+///     .part(
+///         "thumbnail",
+///         Part::bytes(vec![0xff, 0xd8, 0xff])
+///             .file_name("beach-small.jpg")
+///             .content_type("image/jpeg"),
+///     );
+///
+/// let res = ureq::post("http://httpbin.org/post")
+///     .send_multipart(form)?;
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct Multipart {
+    boundary: String,
+    segments: VecDeque<Segment>,
+    ended: bool,
+    /// Set when a part can't be sent, which fails the form.
+    invalid: Option<&'static str>,
+}
+
+/// One part of a [`Multipart`] form.
+///
+/// # Example
+///
+/// ```
+/// use std::io::Cursor;
+/// use ureq::Part;
+///
+/// let part = Part::reader(Cursor::new(b"col1,col2\n1,2\n"))
+///     .file_name("report.csv")
+///     .content_type("text/csv");
+/// ```
+pub struct Part {
+    data: Segment,
+    file_name: Option<String>,
+    content_type: Option<String>,
+}
+
+enum Segment {
+    Bytes(Cursor<Vec<u8>>),
+    Reader(Box<dyn Read>, Option<u64>),
+}
+
+impl Multipart {
+    /// Creates an empty form with a random boundary.
+    pub fn new() -> Self {
+        let boundary = format!("ureq-boundary-{:016x}{:016x}", random_u64(), random_u64());
+        Self::with_boundary(boundary)
+    }
+
+    fn with_boundary(boundary: String) -> Self {
+        Multipart {
+            boundary,
+            segments: VecDeque::new(),
+            ended: false,
+            invalid: None,
+        }
+    }
+
+    /// Add a text field.
+    pub fn text(self, name: impl AsRef<str>, value: impl Into<String>) -> Self {
+        self.part(name, Part::text(value))
+    }
+
+    /// Add a file field read from `path`.
+    ///
+    /// The file name is taken from the path, and the content type is
+    /// `application/octet-stream`. Use [`Part::file()`] to change these.
+    pub fn file(self, name: impl AsRef<str>, path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(self.part(name, Part::file(path)?))
+    }
+
+    /// Add a part.
+    pub fn part(mut self, name: impl AsRef<str>, part: Part) -> Self {
+        let mut header = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"",
+            self.boundary,
+            escape(name.as_ref())
+        );
+        if let Some(file_name) = &part.file_name {
+            header.push_str(&format!("; filename=\"{}\"", escape(file_name)));
+        }
+        header.push_str("\r\n");
+        if let Some(content_type) = &part.content_type {
+            // The content type is written as is, and must not break out of the header.
+            if HeaderValue::from_str(content_type).is_ok() {
+                header.push_str(&format!("Content-Type: {}\r\n", content_type));
+            } else {
+                self.invalid = Some("invalid part content type");
+            }
+        }
+        header.push_str("\r\n");
+
+        self.segments.push_back(Segment::bytes(header));
+        self.segments.push_back(part.data);
+        self.segments.push_back(Segment::bytes("\r\n"));
+        self
+    }
+
+    /// The boundary separating the parts.
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// The value for the `Content-Type` header.
+    ///
+    /// Like `multipart/form-data; boundary=ureq-boundary-1234`.
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    fn closing(&self) -> String {
+        format!("--{}--\r\n", self.boundary)
+    }
+
+    /// Length of the remaining body, if every part has a known size.
+    fn len(&self) -> Option<u64> {
+        let closing = if self.ended {
+            0
+        } else {
+            self.closing().len() as u64
+        };
+
+        self.segments
+            .iter()
+            .map(Segment::len)
+            .try_fold(closing, |acc, len| Some(acc + len?))
+    }
+
+    /// Fails if any part can't be sent.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        match self.invalid {
+            Some(v) => Err(Error::Multipart(v)),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn body_mode(&self) -> BodyMode {
+        match self.len() {
+            Some(v) => BodyMode::LengthDelimited(v),
+            None => BodyMode::Chunked,
+        }
+    }
+
+    pub(crate) fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.validate().map_err(Error::into_io)?;
+
+        if !self.ended {
+            self.ended = true;
+            let closing = self.closing();
+            self.segments.push_back(Segment::bytes(closing));
+        }
+
+        while let Some(segment) = self.segments.front_mut() {
+            let n = match segment {
+                Segment::Bytes(v) => v.read(buf)?,
+                Segment::Reader(v, _) => v.read(buf)?,
+            };
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            self.segments.pop_front();
+        }
+
+        Ok(0)
+    }
+}
+
+impl Default for Multipart {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Part {
+    /// A text value.
+    pub fn text(value: impl Into<String>) -> Self {
+        Self::bytes(value.into())
+    }
+
+    /// A value from bytes.
+    pub fn bytes(value: impl Into<Vec<u8>>) -> Self {
+        Part {
+            data: Segment::bytes(value),
+            file_name: None,
+            content_type: None,
+        }
+    }
+
+    /// A value read from `path`.
+    ///
+    /// The file name is taken from the path, and the content type is
+    /// `application/octet-stream`.
+    pub fn file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+
+        let mut part = Self::reader_with_size(file, size).content_type("application/octet-stream");
+        if let Some(name) = path.file_name() {
+            part = part.file_name(name.to_string_lossy());
+        }
+        Ok(part)
+    }
+
+    /// A value read from a reader of unknown size.
+    ///
+    /// This makes the form use `Transfer-Encoding: chunked`.
+    pub fn reader(reader: impl Read + 'static) -> Self {
+        Part {
+            data: Segment::Reader(Box::new(reader), None),
+            file_name: None,
+            content_type: None,
+        }
+    }
+
+    /// A value read from a reader of `size` bytes.
+    ///
+    /// The reader must produce exactly `size` bytes.
+    pub fn reader_with_size(reader: impl Read + 'static, size: u64) -> Self {
+        Part {
+            data: Segment::Reader(Box::new(reader), Some(size)),
+            file_name: None,
+            content_type: None,
+        }
+    }
+
+    /// Set the file name of the part.
+    pub fn file_name(mut self, file_name: impl Into<String>) -> Self {
+        self.file_name = Some(file_name.into());
+        self
+    }
+
+    /// Set the content type of the part.
+    ///
+    /// Sending the form fails if this is not a valid header value.
+    pub fn content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+}
+
+impl Segment {
+    fn bytes(v: impl Into<Vec<u8>>) -> Self {
+        Segment::Bytes(Cursor::new(v.into()))
+    }
+
+    fn len(&self) -> Option<u64> {
+        match self {
+            Segment::Bytes(v) => Some(v.get_ref().len() as u64 - v.position()),
+            Segment::Reader(_, size) => *size,
+        }
+    }
+}
+
+/// Escape a name or file name in the `Content-Disposition` header.
+///
+/// Follows the HTML form submission, which percent encodes `"` and line breaks.
+fn escape(s: &str) -> String {
+    s.replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+impl fmt::Debug for Multipart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Multipart")
+            .field("boundary", &self.boundary)
+            .finish()
+    }
+}
+
+impl fmt::Debug for Part {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Part")
+            .field("file_name", &self.file_name)
+            .field("content_type", &self.content_type)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn read_all(mut form: Multipart) -> String {
+        let mut buf = [0; 7];
+        let mut out = vec![];
+        loop {
+            let n = form.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn encode_parts() {
+        let form = Multipart::with_boundary("XyZ".into())
+            .text("title", "Hello")
+            .part(
+                "file\"1",
+                Part::bytes("abc")
+                    .file_name("a.txt")
+                    .content_type("text/plain"),
+            );
+
+        let len = form.len().unwrap();
+        let body = read_all(form);
+
+        assert_eq!(
+            body,
+            "--XyZ\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\
+            \r\n\
+            Hello\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"file%221\"; filename=\"a.txt\"\r\n\
+            Content-Type: text/plain\r\n\
+            \r\n\
+            abc\r\n\
+            --XyZ--\r\n"
+        );
+        assert_eq!(len, body.len() as u64);
+    }
+
+    #[test]
+    fn body_mode() {
+        let form = Multipart::new().part("a", Part::reader_with_size(Cursor::new("abc"), 3));
+        assert!(matches!(form.body_mode(), BodyMode::LengthDelimited(_)));
+
+        let form = form.part("b", Part::reader(Cursor::new("abc")));
+        assert_eq!(form.body_mode(), BodyMode::Chunked);
+    }
+
+    #[test]
+    fn invalid_content_type() {
+        let form = Multipart::with_boundary("XyZ".into()).part(
+            "a",
+            Part::bytes("abc").content_type("text/plain\r\nX-Injected: 1"),
+        );
+        assert!(matches!(form.validate(), Err(Error::Multipart(_))));
+
+        let mut form = form;
+        let err = form.read(&mut [0; 10]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert!(err.to_string().contains("invalid part content type"));
+    }
+
+    #[test]
+    fn random_boundary() {
+        assert_ne!(Multipart::new().boundary(), Multipart::new().boundary());
+    }
+}
@@ -7,7 +7,11 @@ use http::Uri;
 
 use crate::config::Config;
 use crate::http;
+#[cfg(feature = "http2")]
+use crate::http2::{Http2Connection, Http2Transport};
 use crate::proxy::Proxy;
+#[cfg(feature = "_tls")]
+use crate::tls::TlsConfig;
 use crate::transport::time::{Duration, Instant};
 use crate::transport::{Buffers, ConnectionDetails, Connector, NextTimeout, Transport};
 use crate::util::DebugAuthority;
@@ -16,6 +20,9 @@ use crate::Error;
 pub(crate) struct ConnectionPool {
     connector: Box<dyn Connector>,
     pool: Arc<Mutex<Pool>>,
+    /// HTTP/2 connections, which are shared by concurrent requests rather than pooled.
+    #[cfg(feature = "http2")]
+    http2: Mutex<Vec<(PoolKey, Weak<Http2Connection>)>>,
 }
 
 impl ConnectionPool {
@@ -23,6 +30,8 @@ impl ConnectionPool {
         ConnectionPool {
             connector: Box::new(connector),
             pool: Arc::new(Mutex::new(Pool::new(config))),
+            #[cfg(feature = "http2")]
+            http2: Mutex::new(Vec::new()),
         }
     }
 
@@ -31,35 +40,111 @@ impl ConnectionPool {
         details: &ConnectionDetails,
         max_idle_age: Duration,
     ) -> Result<Connection, Error> {
-        let key = PoolKey::new(details.uri, &details.config.proxy);
+        let key = PoolKey::new(details.uri, details.config);
 
         {
             let mut pool = self.pool.lock().unwrap();
             pool.purge(details.now);
 
-            if let Some(conn) = pool.get(&key, max_idle_age, details.now) {
+            if let Some(mut conn) = pool.get(&key, max_idle_age, details.now) {
                 debug!("Use pooled: {:?}", key);
+                conn.pooled = true;
+                conn.received_input = false;
                 return Ok(conn);
             }
         }
 
+        #[cfg(feature = "http2")]
+        let uses_http2 = details.config.http2 || details.config.http2_prior_knowledge;
+
+        #[cfg(feature = "http2")]
+        if let Some(conn) = self.shared_http2(&key).filter(|_| uses_http2) {
+            debug!("Use shared HTTP/2: {:?}", key);
+            let transport = Http2Transport::new(conn, details, false);
+            let mut conn = self.connection(Box::new(transport), key, details.now);
+            conn.pooled = true;
+            return Ok(conn);
+        }
+
+        self.connect_new(details, key)
+    }
+
+    /// Open a new connection, bypassing the pool.
+    pub fn connect_fresh(&self, details: &ConnectionDetails) -> Result<Connection, Error> {
+        let key = PoolKey::new(details.uri, details.config);
+        self.connect_new(details, key)
+    }
+
+    fn connect_new(&self, details: &ConnectionDetails, key: PoolKey) -> Result<Connection, Error> {
         let transport = self
             .connector
             .connect(details, None)?
             .ok_or(Error::ConnectionFailed)?;
 
-        let conn = Connection {
+        #[cfg(feature = "http2")]
+        let transport = self.maybe_http2(details, &key, transport)?;
+
+        Ok(self.connection(transport, key, details.now))
+    }
+
+    fn connection(&self, transport: Box<dyn Transport>, key: PoolKey, now: Instant) -> Connection {
+        Connection {
             transport,
             key,
-            last_use: details.now,
+            last_use: now,
             pool: Arc::downgrade(&self.pool),
             position_per_host: None,
-        };
+            pooled: false,
+            received_input: false,
+        }
+    }
+
+    /// A usable HTTP/2 connection to share.
+    #[cfg(feature = "http2")]
+    fn shared_http2(&self, key: &PoolKey) -> Option<Arc<Http2Connection>> {
+        let mut http2 = self.http2.lock().unwrap();
+
+        // Forget connections that are closed, or soon will be.
+        http2.retain(|(_, c)| c.upgrade().map(|c| c.is_usable()).unwrap_or(false));
 
-        Ok(conn)
+        http2
+            .iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, c)| c.upgrade())
     }
 
-    #[cfg(test)]
+    /// Start HTTP/2 if it was negotiated, or assumed with prior knowledge.
+    #[cfg(feature = "http2")]
+    fn maybe_http2(
+        &self,
+        details: &ConnectionDetails,
+        key: &PoolKey,
+        transport: Box<dyn Transport>,
+    ) -> Result<Box<dyn Transport>, Error> {
+        let config = details.config;
+
+        let prior_knowledge =
+            config.http2_prior_knowledge && details.uri.scheme() == Some(&Scheme::HTTP);
+
+        if !transport.is_http2() && !prior_knowledge {
+            return Ok(transport);
+        }
+
+        debug!("Start HTTP/2: {:?}", key);
+
+        let conn = Http2Connection::handshake(
+            transport,
+            config.max_response_header_size,
+            details.timeout,
+        )?;
+
+        let mut http2 = self.http2.lock().unwrap();
+        http2.push((key.clone(), Arc::downgrade(&conn)));
+
+        Ok(Box::new(Http2Transport::new(conn, details, true)))
+    }
+
+    #[cfg(all(test, feature = "_test"))]
     pub fn pool_count(&self) -> usize {
         let lock = self.pool.lock().unwrap();
         lock.lru.len()
@@ -91,6 +176,12 @@ pub(crate) struct Connection {
     /// Once we have that enumeration, we can drop elements from the front where there
     /// position_per_host >= idle_per_host.
     position_per_host: Option<usize>,
+
+    /// Whether this connection was taken from the pool rather than newly opened.
+    pooled: bool,
+
+    /// Whether any input has been received since the connection was handed out.
+    received_input: bool,
 }
 
 impl Connection {
@@ -103,7 +194,31 @@ impl Connection {
     }
 
     pub fn await_input(&mut self, timeout: NextTimeout) -> Result<bool, Error> {
-        self.transport.await_input(timeout)
+        let made_progress = self.transport.await_input(timeout)?;
+        if made_progress {
+            self.received_input = true;
+        }
+        Ok(made_progress)
+    }
+
+    pub fn is_pooled(&self) -> bool {
+        self.pooled
+    }
+
+    pub fn tls_handshake_duration(&self) -> Option<std::time::Duration> {
+        self.transport.tls_handshake_duration()
+    }
+
+    pub fn is_http2(&self) -> bool {
+        self.transport.is_http2()
+    }
+
+    /// Tells if this is a reused connection that failed before receiving anything.
+    ///
+    /// The remote might close an idle connection at the same time as we start
+    /// sending a request on it. The is_open() probe can't detect that race.
+    pub fn is_possibly_stale(&self) -> bool {
+        self.pooled && !self.received_input
     }
 
     pub fn consume_input(&mut self, amount: usize) {
@@ -161,23 +276,62 @@ impl Connection {
 /// It's correct to include username/password since connections with differing such and
 /// the same host/port must not be mixed up.
 ///
+/// The TLS config is also part of the key. It can differ per request, and between agents
+/// sharing the same pool (see [`Agent::derive()`](crate::Agent::derive)). A connection
+/// verified with one set of root certs must not be handed to a request expecting another.
+///
 #[derive(Clone, PartialEq, Eq)]
 struct PoolKey(Arc<PoolKeyInner>);
 
 impl PoolKey {
-    fn new(uri: &Uri, proxy: &Option<Proxy>) -> Self {
-        let inner = PoolKeyInner(
-            uri.scheme().expect("uri with scheme").clone(),
-            uri.authority().expect("uri with authority").clone(),
-            proxy.clone(),
-        );
+    fn new(uri: &Uri, config: &Config) -> Self {
+        let inner = PoolKeyInner {
+            scheme: uri.scheme().expect("uri with scheme").clone(),
+            authority: uri.authority().expect("uri with authority").clone(),
+            proxy: config.proxy.clone(),
+            #[cfg(feature = "_tls")]
+            tls_config: config.tls_config.clone(),
+            #[cfg(feature = "http2")]
+            http2: (config.http2, config.http2_prior_knowledge),
+        };
 
         PoolKey(Arc::new(inner))
     }
 }
 
-#[derive(PartialEq, Eq)]
-struct PoolKeyInner(Scheme, Authority, Option<Proxy>);
+struct PoolKeyInner {
+    scheme: Scheme,
+    authority: Authority,
+    proxy: Option<Proxy>,
+    #[cfg(feature = "_tls")]
+    tls_config: TlsConfig,
+    #[cfg(feature = "http2")]
+    http2: (bool, bool),
+}
+
+impl PartialEq for PoolKeyInner {
+    fn eq(&self, other: &Self) -> bool {
+        #[cfg(feature = "_tls")]
+        {
+            if !self.tls_config.is_same_config(&other.tls_config) {
+                return false;
+            }
+        }
+
+        #[cfg(feature = "http2")]
+        {
+            if self.http2 != other.http2 {
+                return false;
+            }
+        }
+
+        self.scheme == other.scheme
+            && self.authority == other.authority
+            && self.proxy == other.proxy
+    }
+}
+
+impl Eq for PoolKeyInner {}
 
 #[derive(Debug)]
 struct Pool {
@@ -292,9 +446,9 @@ impl fmt::Debug for Connection {
 impl fmt::Debug for PoolKey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("PoolKey")
-            .field("scheme", &self.0 .0)
-            .field("authority", &DebugAuthority(&self.0 .1))
-            .field("proxy", &self.0 .2)
+            .field("scheme", &self.0.scheme)
+            .field("authority", &DebugAuthority(&self.0.authority))
+            .field("proxy", &self.0.proxy)
             .finish()
     }
 }
@@ -306,6 +460,22 @@ mod test {
     #[test]
     fn poolkey_new() {
         // Test that PoolKey::new() does not panic on unrecognized schemes.
-        PoolKey::new(&Uri::from_static("zzz://example.com"), &None);
+        PoolKey::new(&Uri::from_static("zzz://example.com"), &Config::default());
+    }
+
+    #[test]
+    #[cfg(feature = "_tls")]
+    fn poolkey_differs_by_tls_config() {
+        use crate::tls::RootCerts;
+
+        let uri = Uri::from_static("https://example.com");
+
+        let config1 = Config::default();
+        let config2 = Config::builder()
+            .tls_config(TlsConfig::builder().root_certs(RootCerts::from([])).build())
+            .build();
+
+        assert!(PoolKey::new(&uri, &config1) == PoolKey::new(&uri, &config1.clone()));
+        assert!(PoolKey::new(&uri, &config1) != PoolKey::new(&uri, &config2));
     }
 }
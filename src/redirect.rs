@@ -0,0 +1,112 @@
+use std::fmt;
+use std::sync::Arc;
+
+use http::{Request, StatusCode, Uri};
+
+use crate::http;
+
+/// What to do with a redirect response.
+///
+/// Returned by the callback set in
+/// [`ConfigBuilder::redirect_policy()`](crate::config::ConfigBuilder::redirect_policy).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RedirectAction {
+    /// Follow the redirect.
+    ///
+    /// This is still limited by [`max_redirects`](crate::config::ConfigBuilder::max_redirects).
+    Follow,
+
+    /// Do not follow the redirect, and return the 3xx response as is.
+    Stop,
+
+    /// Do not follow the redirect, and fail with [`Error::RedirectDenied`](crate::Error::RedirectDenied).
+    Error,
+}
+
+type PolicyFn = dyn Fn(&Request<()>, StatusCode, &str) -> RedirectAction + Send + Sync + 'static;
+
+/// Callback deciding whether to follow redirects.
+#[derive(Clone)]
+pub(crate) struct RedirectPolicy(Arc<PolicyFn>);
+
+impl RedirectPolicy {
+    pub(crate) fn new(
+        f: impl Fn(&Request<()>, StatusCode, &str) -> RedirectAction + Send + Sync + 'static,
+    ) -> Self {
+        RedirectPolicy(Arc::new(f))
+    }
+
+    pub(crate) fn decide(
+        &self,
+        request: &Request<()>,
+        status: StatusCode,
+        location: &str,
+    ) -> RedirectAction {
+        (self.0)(request, status, location)
+    }
+}
+
+/// The URIs visited by a request.
+///
+/// This is found in the extensions of every [`http::Response`] returned by ureq,
+/// and lists each request made while following redirects, together with the status
+/// of its response. The last entry is the request that produced the response.
+///
+/// ```
+/// use ureq::RedirectHistory;
+///
+/// let res = ureq::get("http://httpbin.org/redirect-to?url=%2Fget").call()?;
+///
+/// let history = res.extensions().get::<RedirectHistory>().unwrap();
+///
+/// assert_eq!(history.redirect_count(), 1);
+/// assert_eq!(history.effective_uri().to_string(), "http://httpbin.org/get");
+/// # Ok::<_, ureq::Error>(())
+/// ```
+#[derive(Clone, Default)]
+pub struct RedirectHistory {
+    visited: Vec<(Uri, StatusCode)>,
+}
+
+impl RedirectHistory {
+    pub(crate) fn push(&mut self, uri: Uri, status: StatusCode) {
+        self.visited.push((uri, status));
+    }
+
+    /// Each URI requested, in order, with the status of the response.
+    pub fn visited(&self) -> &[(Uri, StatusCode)] {
+        &self.visited
+    }
+
+    /// The URI of the last request, which produced the response.
+    ///
+    /// Differs from the URI of the original request if any redirect was followed.
+    pub fn effective_uri(&self) -> &Uri {
+        // unwrap is ok, because the history is only handed out with a response.
+        &self.visited.last().expect("at least one request").0
+    }
+
+    /// Number of redirects that were followed.
+    pub fn redirect_count(&self) -> usize {
+        self.visited.len().saturating_sub(1)
+    }
+}
+
+impl fmt::Debug for RedirectPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RedirectPolicy").finish()
+    }
+}
+
+impl fmt::Debug for RedirectHistory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(
+                self.visited
+                    .iter()
+                    .map(|(u, s)| (u.to_string(), s.as_u16())),
+            )
+            .finish()
+    }
+}
@@ -14,7 +14,7 @@ use crate::send_body::AsSendBody;
 use crate::util::private::Private;
 use crate::util::HeaderMapExt;
 use crate::util::UriExt;
-use crate::{Agent, Error, SendBody};
+use crate::{Agent, Error, Multipart, SendBody};
 
 /// Transparent wrapper around [`http::request::Builder`].
 ///
@@ -375,6 +375,26 @@ impl RequestBuilder<WithBody> {
         do_call(self.agent, request, self.query_extra, body.as_body())
     }
 
+    /// Send a `multipart/form-data` body.
+    ///
+    /// Sets the content-type header `multipart/form-data` with the boundary of the
+    /// form. See [`Multipart`] for an example.
+    pub fn send_multipart(self, mut form: Multipart) -> Result<Response<Body>, Error> {
+        form.validate()?;
+
+        let mut request = self.builder.body(())?;
+
+        if !request.headers().has_content_type() {
+            // unwrap is ok because the boundary is always a valid header value.
+            let value = HeaderValue::from_str(&form.content_type()).unwrap();
+            request
+                .headers_mut()
+                .append(http::header::CONTENT_TYPE, value);
+        }
+
+        do_call(self.agent, request, self.query_extra, form.as_body())
+    }
+
     /// Send body data as JSON.
     ///
     /// Requires the **json** feature.
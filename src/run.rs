@@ -1,24 +1,33 @@
+use std::borrow::Cow;
 use std::sync::Arc;
 use std::{io, mem};
 
 use http::uri::Scheme;
-use http::{header, HeaderValue, Request, Response, Uri};
+use http::Uri;
+use http::Version;
+use http::{header, HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
 use once_cell::sync::Lazy;
 use ureq_proto::client::flow::state::{Await100, RecvBody, RecvResponse, Redirect, SendRequest};
 use ureq_proto::client::flow::state::{Prepare, SendBody as SendBodyState};
 use ureq_proto::client::flow::{Await100Result, RecvBodyResult};
 use ureq_proto::client::flow::{RecvResponseResult, SendRequestResult};
+use ureq_proto::client::MAX_RESPONSE_HEADERS;
+use ureq_proto::parser::try_parse_response;
 use ureq_proto::BodyMode;
 
 use crate::body::ResponseInfo;
-use crate::config::{Config, RequestLevelConfig, DEFAULT_USER_AGENT};
+use crate::config::{Config, DownloadProgress, DownloadProgressHandler};
+use crate::config::{RequestLevelConfig, DEFAULT_USER_AGENT};
 use crate::http;
 use crate::pool::Connection;
+use crate::redirect::RedirectPolicy;
 use crate::timings::{CallTimings, CurrentTime};
 use crate::transport::time::{Duration, Instant};
 use crate::transport::ConnectionDetails;
-use crate::util::{DebugRequest, DebugResponse, DebugUri, HeaderMapExt, UriExt};
-use crate::{Agent, Body, Error, SendBody, Timeout};
+use crate::util::{find, parse_headers, DebugRequest, DebugResponse, DebugUri};
+use crate::util::{HeaderMapExt, UriExt};
+use crate::{Agent, AsSendBody, Body, Error, RedirectAction, RedirectHistory};
+use crate::{ResponseTimings, SendBody, Timeout};
 
 type Flow<T> = ureq_proto::client::flow::Flow<(), T>;
 
@@ -31,6 +40,8 @@ pub(crate) fn run(
     mut body: SendBody,
 ) -> Result<Response<Body>, Error> {
     let mut redirect_count = 0;
+    let mut history = RedirectHistory::default();
+    let mut response_timings = ResponseTimings::default();
 
     // Configuration on the request level overrides the agent level.
     let config = request
@@ -40,6 +51,39 @@ pub(crate) fn run(
         .map(Arc::new)
         .unwrap_or_else(|| agent.config.clone());
 
+    // A protocol upgrade takes over the connection, which needs HTTP/1.1.
+    #[cfg(feature = "http2")]
+    let config = if is_upgrade(request.headers()) && (config.http2 || config.http2_prior_knowledge)
+    {
+        Arc::new(Config {
+            http2: false,
+            http2_prior_knowledge: false,
+            ..(*config).clone()
+        })
+    } else {
+        config
+    };
+
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    if let Some(compression) = config.body_compression {
+        let headers = request.headers();
+        let has_body = !matches!(
+            body.body_mode(),
+            BodyMode::NoBody | BodyMode::LengthDelimited(0)
+        );
+        let is_encoded = headers.contains_key(header::CONTENT_ENCODING)
+            || headers.contains_key(header::CONTENT_LENGTH);
+
+        if has_body && !is_encoded {
+            debug!("Compressing request body: {}", compression.as_str());
+            let value = HeaderValue::from_static(compression.as_str());
+            request
+                .headers_mut()
+                .insert(header::CONTENT_ENCODING, value);
+            body = body.into_compressed(compression)?;
+        }
+    }
+
     let timeouts = config.timeouts;
 
     let mut timings = CallTimings::new(timeouts, CurrentTime::default());
@@ -67,6 +111,8 @@ pub(crate) fn run(
             &mut body,
             redirect_count,
             &mut timings,
+            &mut history,
+            &mut response_timings,
         )? {
             // Follow redirect
             FlowResult::Redirect(rflow, rtimings) => {
@@ -89,22 +135,26 @@ pub(crate) fn run(
         .map(|f| f.body_mode())
         .unwrap_or(BodyMode::NoBody);
 
-    let info = ResponseInfo::new(&parts.headers, recv_body_mode);
+    let info = ResponseInfo::new(&parts.headers, recv_body_mode, &config);
 
     let body = Body::new(handler, info);
 
-    let response = Response::from_parts(parts, body);
+    let mut response = Response::from_parts(parts, body);
 
-    let status = response.status();
-    let is_err = status.is_client_error() || status.is_server_error();
+    if history.redirect_count() > 0 {
+        info!("Effective uri: {:?}", DebugUri(history.effective_uri()));
+    }
+    response.extensions_mut().insert(history);
+    response.extensions_mut().insert(response_timings);
 
-    if config.http_status_as_error && is_err {
-        return Err(Error::StatusCode(status.as_u16()));
+    if config.is_status_error(response.status(), response.headers()) {
+        return Err(config.status_error(response));
     }
 
     Ok(response)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn flow_run(
     agent: &Agent,
     config: &Config,
@@ -112,6 +162,8 @@ fn flow_run(
     body: &mut SendBody,
     redirect_count: u32,
     timings: &mut CallTimings,
+    history: &mut RedirectHistory,
+    response_timings: &mut ResponseTimings,
 ) -> Result<FlowResult, Error> {
     let uri = flow.uri().clone();
     info!("{} {:?}", flow.method(), &DebugUri(flow.uri()));
@@ -122,35 +174,62 @@ fn flow_run(
 
     add_headers(&mut flow, agent, config, body, &uri)?;
 
-    let mut connection = connect(agent, config, &uri, timings)?;
+    // The redirect policy needs to see the request.
+    let policy_request = config.redirect_policy.as_ref().map(|_| copy_request(&flow));
 
-    let mut flow = flow.proceed();
+    let mut connection = connect(agent, config, &uri, timings, true)?;
 
-    if log_enabled!(log::Level::Info) {
-        let headers = flow.headers_map()?;
+    // A copy of the request to replay if the pooled connection turns out to be stale.
+    let replay = if connection.is_possibly_stale() && is_replay_safe(&flow, body) {
+        Some(copy_request(&flow))
+    } else {
+        None
+    };
 
-        let r = DebugRequest {
-            method: flow.method(),
-            uri: flow.uri(),
-            version: flow.version(),
-            headers,
-        };
-        info!("{:?}", r);
-    }
+    let result = {
+        // Reborrow the body, to be able to send it again from the start on retry.
+        let mut body = body.as_body();
+        send_and_recv(flow.proceed(), &mut body, &mut connection, config, timings)
+    };
 
-    let flow = match send_request(flow, &mut connection, timings)? {
-        SendRequestResult::Await100(flow) => match await_100(flow, &mut connection, timings)? {
-            Await100Result::SendBody(flow) => send_body(flow, body, &mut connection, timings)?,
-            Await100Result::RecvResponse(flow) => flow,
-        },
-        SendRequestResult::SendBody(flow) => send_body(flow, body, &mut connection, timings)?,
-        SendRequestResult::RecvResponse(flow) => flow,
+    let (mut response, response_result) = match result {
+        Err(e) if replay.is_some() && connection.is_possibly_stale() && is_stale_error(&e) => {
+            debug!(
+                "Pooled connection is stale ({}), retry on new connection",
+                e
+            );
+            connection.close();
+
+            // unwrap is ok because of the match guard.
+            let mut flow = Flow::new(replay.unwrap())?;
+            if config.force_send_body {
+                flow.send_body_despite_method();
+            }
+
+            timings.reset_connection();
+            connection = connect(agent, config, &uri, timings, false)?;
+            send_and_recv(flow.proceed(), body, &mut connection, config, timings)?
+        }
+        r => r?,
     };
 
-    let (response, response_result) = recv_response(flow, &mut connection, config, timings)?;
+    // The HTTP/2 transport presents the response as HTTP/1.1.
+    if connection.is_http2() {
+        *response.version_mut() = Version::HTTP_2;
+    }
 
     info!("{:?}", DebugResponse(&response));
 
+    history.push(uri.clone(), response.status());
+
+    // A pooled connection did the TLS handshake in an earlier call.
+    let tls_handshake = if connection.is_pooled() {
+        None
+    } else {
+        connection.tls_handshake_duration()
+    };
+    response_timings.push(timings.hop_timings(tls_handshake, connection.is_pooled()));
+
     #[cfg(feature = "cookies")]
     {
         let mut jar = agent.cookie_jar_lock();
@@ -165,33 +244,65 @@ fn flow_run(
         jar.store_response_cookies(iter, &uri);
     }
 
+    let follow_redirect = redirect_count < config.max_redirects
+        && match (&config.redirect_policy, &policy_request) {
+            (Some(policy), Some(request)) => {
+                should_follow(policy, request, &response, &response_result)?
+            }
+            _ => true,
+        };
+
     let ret = match response_result {
-        RecvResponseResult::RecvBody(flow) => {
+        RecvResponseResult::RecvBody(mut flow) => {
+            // Stop at chunk boundaries to catch the trailer section after the last chunk.
+            let is_chunked = flow.body_mode() == BodyMode::Chunked;
+            if is_chunked {
+                flow.stop_on_chunk_boundary(true);
+            }
+
             let timings = mem::take(timings);
             let mut handler = BodyHandler {
                 flow: Some(flow),
                 connection: Some(connection),
                 timings,
+                is_chunked,
+                max_trailer_size: config.max_response_header_size,
                 ..Default::default()
             };
 
-            if response.status().is_redirection() && redirect_count < config.max_redirects {
+            if response.status().is_redirection() && follow_redirect {
                 let flow = handler.consume_redirect_body()?;
 
                 FlowResult::Redirect(flow, handler.timings)
             } else {
+                handler.progress = config.download_progress.clone().map(|h| {
+                    let content_length = match handler.flow.as_ref().map(|f| f.body_mode()) {
+                        Some(BodyMode::LengthDelimited(v)) => Some(v),
+                        _ => None,
+                    };
+                    ProgressTracker::new(h, content_length)
+                });
+
                 FlowResult::Response(response, handler)
             }
         }
         RecvResponseResult::Redirect(flow) => {
             cleanup(connection, flow.must_close_connection(), timings.now());
 
-            if redirect_count >= config.max_redirects {
+            if !follow_redirect {
                 FlowResult::Response(response, BodyHandler::default())
             } else {
                 FlowResult::Redirect(flow, mem::take(timings))
             }
         }
+        RecvResponseResult::Cleanup(_) if response.status() == StatusCode::SWITCHING_PROTOCOLS => {
+            // The connection now speaks another protocol and must not go back in the pool.
+            let handler = BodyHandler {
+                upgraded: Some(connection),
+                ..Default::default()
+            };
+            FlowResult::Response(response, handler)
+        }
         RecvResponseResult::Cleanup(flow) => {
             cleanup(connection, flow.must_close_connection(), timings.now());
             FlowResult::Response(response, BodyHandler::default())
@@ -201,6 +312,104 @@ fn flow_run(
     Ok(ret)
 }
 
+fn send_and_recv(
+    mut flow: Flow<SendRequest>,
+    body: &mut SendBody,
+    connection: &mut Connection,
+    config: &Config,
+    timings: &mut CallTimings,
+) -> Result<(Response<()>, RecvResponseResult<()>), Error> {
+    if log_enabled!(log::Level::Info) {
+        let headers = flow.headers_map()?;
+
+        let r = DebugRequest {
+            method: flow.method(),
+            uri: flow.uri(),
+            version: flow.version(),
+            headers,
+        };
+        info!("{:?}", r);
+    }
+
+    let flow = match send_request(flow, connection, timings)? {
+        SendRequestResult::Await100(flow) => match await_100(flow, connection, config, timings)? {
+            Await100Result::SendBody(flow) => send_body(flow, body, connection, config, timings)?,
+            Await100Result::RecvResponse(flow) => flow,
+        },
+        SendRequestResult::SendBody(flow) => send_body(flow, body, connection, config, timings)?,
+        SendRequestResult::RecvResponse(flow) => flow,
+    };
+
+    recv_response(flow, connection, config, timings)
+}
+
+/// Whether the request can be sent again on a new connection.
+fn is_replay_safe(flow: &Flow<Prepare>, body: &SendBody) -> bool {
+    let idempotent = matches!(
+        *flow.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    );
+
+    idempotent && body.is_replayable()
+}
+
+fn copy_request(flow: &Flow<Prepare>) -> Request<()> {
+    let mut request = Request::new(());
+    *request.method_mut() = flow.method().clone();
+    *request.uri_mut() = flow.uri().clone();
+    *request.version_mut() = flow.version();
+    *request.headers_mut() = flow.headers().clone();
+    request
+}
+
+/// Errors that are expected when writing to, or reading from, a connection closed by the remote.
+fn is_stale_error(error: &Error) -> bool {
+    let Error::Io(e) = error else {
+        return false;
+    };
+
+    matches!(
+        e.kind(),
+        io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+    )
+}
+
+/// Ask the redirect policy whether to follow a redirect response.
+fn should_follow(
+    policy: &RedirectPolicy,
+    request: &Request<()>,
+    response: &Response<()>,
+    response_result: &RecvResponseResult<()>,
+) -> Result<bool, Error> {
+    let is_redirect = match response_result {
+        RecvResponseResult::RecvBody(_) => response.status().is_redirection(),
+        RecvResponseResult::Redirect(_) => true,
+        RecvResponseResult::Cleanup(_) => false,
+    };
+
+    // Without a usable location header, the redirect fails further on.
+    let location = response
+        .headers()
+        .get(header::LOCATION)
+        .and_then(|v| v.to_str().ok());
+
+    let (true, Some(location)) = (is_redirect, location) else {
+        return Ok(true);
+    };
+
+    match policy.decide(request, response.status(), location) {
+        RedirectAction::Follow => Ok(true),
+        RedirectAction::Stop => {
+            debug!("Redirect policy stops at: {}", location);
+            Ok(false)
+        }
+        RedirectAction::Error => Err(Error::RedirectDenied(location.to_string())),
+    }
+}
+
 /// Return type of [`flow_run`].
 #[allow(clippy::large_enum_variant)]
 enum FlowResult {
@@ -247,20 +456,31 @@ fn add_headers(
     {
         static ACCEPTS: Lazy<String> = Lazy::new(|| {
             #[allow(unused_mut)]
-            let mut value = String::with_capacity(10);
+            let mut value: Vec<&str> = vec![];
             #[cfg(feature = "gzip")]
-            value.push_str("gzip");
-            #[cfg(all(feature = "gzip", feature = "brotli"))]
-            value.push_str(", ");
+            value.extend(["gzip", "deflate"]);
             #[cfg(feature = "brotli")]
-            value.push_str("br");
-            value
+            value.push("br");
+            #[cfg(feature = "zstd")]
+            value.push("zstd");
+            value.join(", ")
         });
         if !has_header_accept_enc {
-            if let Some(v) = config.accept_encoding.as_str(&ACCEPTS) {
-                // unwrap is ok because above ACCEPTS will produce a valid value,
-                // or the value is user provided in which case it must be valid.
-                let value = HeaderValue::from_str(v).unwrap();
+            // Registered decoders are advertised along with the built in ones.
+            let accepts = if config.content_decoders.is_empty() {
+                Cow::Borrowed(ACCEPTS.as_str())
+            } else {
+                let mut value: Vec<&str> = ACCEPTS.split(", ").filter(|v| !v.is_empty()).collect();
+                for (token, _) in &config.content_decoders {
+                    if !value.contains(&token.as_str()) {
+                        value.push(token);
+                    }
+                }
+                Cow::Owned(value.join(", "))
+            };
+            if let Some(v) = config.accept_encoding.as_str(&accepts) {
+                // The value is either built from coding tokens, or user provided.
+                let value = HeaderValue::from_str(v).map_err(http::Error::from)?;
                 flow.header(header::ACCEPT_ENCODING, value)?;
             }
         }
@@ -301,11 +521,31 @@ fn add_headers(
     Ok(())
 }
 
+/// Connect to the uri via the proxy, without sending any request.
+pub(crate) fn tunnel(agent: &Agent, uri: &Uri) -> Result<Connection, Error> {
+    info!("Tunnel to {:?}", DebugUri(uri));
+
+    let config = &agent.config;
+
+    // The tunnel carries raw bytes, not HTTP/2 frames.
+    #[cfg(feature = "http2")]
+    let config = &Config {
+        http2: false,
+        http2_prior_knowledge: false,
+        ..(**config).clone()
+    };
+
+    let mut timings = CallTimings::new(config.timeouts, CurrentTime::default());
+
+    connect(agent, config, uri, &mut timings, false)
+}
+
 fn connect(
     agent: &Agent,
     config: &Config,
     uri: &Uri,
     timings: &mut CallTimings,
+    allow_pooled: bool,
 ) -> Result<Connection, Error> {
     // If we're using a CONNECT proxy, we need to resolve that hostname.
     let maybe_connect_uri = config.connect_proxy_uri();
@@ -333,7 +573,11 @@ fn connect(
         timeout: timings.next_timeout(Timeout::Connect),
     };
 
-    let connection = agent.pool.connect(&details, config.max_idle_age.into())?;
+    let connection = if allow_pooled {
+        agent.pool.connect(&details, config.max_idle_age.into())?
+    } else {
+        agent.pool.connect_fresh(&details)?
+    };
 
     timings.record_time(Timeout::Connect);
 
@@ -368,6 +612,7 @@ fn send_request(
 fn await_100(
     mut flow: Flow<Await100>,
     connection: &mut Connection,
+    config: &Config,
     timings: &mut CallTimings,
 ) -> Result<Await100Result<()>, Error> {
     while flow.can_keep_await_100() {
@@ -380,11 +625,14 @@ fn await_100(
 
         match connection.await_input(timeout) {
             Ok(_) => {
-                let input = connection.buffers().input();
-                if input.is_empty() {
+                if connection.buffers().input().is_empty() {
                     return Err(Error::disconnected());
                 }
 
+                // Informational responses other than 100 can arrive before it.
+                skip_all_informational(connection, config, false)?;
+
+                let input = connection.buffers().input();
                 let amount = flow.try_read_100(input)?;
                 if amount > 0 {
                     connection.consume_input(amount);
@@ -412,8 +660,15 @@ fn send_body(
     mut flow: Flow<SendBodyState>,
     body: &mut SendBody,
     connection: &mut Connection,
+    config: &Config,
     timings: &mut CallTimings,
 ) -> Result<Flow<RecvResponse>, Error> {
+    let total = match body.body_mode() {
+        BodyMode::LengthDelimited(v) => Some(v),
+        _ => None,
+    };
+    let mut sent = 0;
+
     loop {
         if flow.can_proceed() {
             break;
@@ -428,7 +683,7 @@ fn send_body(
         let input_fitting_in_output = flow.calculate_max_input(output.len());
         let max_input = input_len.min(input_fitting_in_output);
 
-        let output_used = if !flow.is_chunked() {
+        let (input_used, output_used) = if !flow.is_chunked() {
             // For non-chunked, The body can be written directly to the output.
             // This optimizes away a memcopy if we were to go via flow.write().
             let output_used = body.read(output)?;
@@ -436,7 +691,7 @@ fn send_body(
             // Size checking is still in the flow.
             flow.consume_direct_write(output_used)?;
 
-            output_used
+            (output_used, output_used)
         } else {
             let tmp = &mut tmp[..max_input];
             let n = body.read(tmp)?;
@@ -447,11 +702,18 @@ fn send_body(
             // the entire input we read from the body should also be shipped to the output.
             assert!(input_used == n);
 
-            output_used
+            (input_used, output_used)
         };
 
         let timeout = timings.next_timeout(Timeout::SendBody);
         connection.transmit_output(output_used, timeout)?;
+
+        if input_used > 0 {
+            sent += input_used as u64;
+            if let Some(handler) = &config.upload_progress {
+                handler.call(sent, total);
+            }
+        }
     }
 
     timings.record_time(Timeout::SendBody);
@@ -468,8 +730,15 @@ fn recv_response(
         let timeout = timings.next_timeout(Timeout::RecvResponse);
         let made_progress = connection.await_input(timeout)?;
 
+        skip_all_informational(connection, config, true)?;
+
         let input = connection.buffers().input();
 
+        if input.is_empty() && made_progress {
+            // Only informational responses so far.
+            continue;
+        }
+
         let (amount, maybe_response) = flow.try_response(input)?;
 
         if input.len() > config.max_response_header_size {
@@ -493,6 +762,56 @@ fn recv_response(
     Ok((response, flow.proceed().unwrap()))
 }
 
+/// Skip all complete informational (1xx) responses at the start of the input.
+fn skip_all_informational(
+    connection: &mut Connection,
+    config: &Config,
+    skip_continue: bool,
+) -> Result<(), Error> {
+    loop {
+        let input = connection.buffers().input();
+        let amount = skip_informational(input, config, skip_continue)?;
+
+        if amount == 0 {
+            return Ok(());
+        }
+
+        connection.consume_input(amount);
+    }
+}
+
+/// Parse and skip an informational (1xx) response at the start of the input.
+///
+/// Returns the amount of input used, or 0 if the input doesn't start with a complete
+/// informational response. `101 Switching Protocols` is a final response and never skipped.
+fn skip_informational(input: &[u8], config: &Config, skip_continue: bool) -> Result<usize, Error> {
+    // Check the status line before doing a full parse: HTTP/1.1 1xx
+    let Some(code) = input.get(9..12) else {
+        return Ok(0);
+    };
+
+    let skip = input.starts_with(b"HTTP/1.")
+        && code[0] == b'1'
+        && code != b"101"
+        && (skip_continue || code != b"100");
+
+    if !skip {
+        return Ok(0);
+    }
+
+    let Some((amount, response)) = try_parse_response::<MAX_RESPONSE_HEADERS>(input)? else {
+        return Ok(0);
+    };
+
+    debug!("Skip informational response: {}", response.status());
+
+    if let Some(handler) = &config.informational_handler {
+        handler.call(response.status(), response.headers());
+    }
+
+    Ok(amount)
+}
+
 fn handle_redirect(mut flow: Flow<Redirect>, config: &Config) -> Result<Flow<Prepare>, Error> {
     let maybe_new_flow = flow.as_new_flow(config.redirect_auth_headers)?;
     let status = flow.status();
@@ -526,13 +845,59 @@ pub(crate) struct BodyHandler {
     timings: CallTimings,
     remote_closed: bool,
     redirect: Option<Flow<Redirect>>,
+    is_chunked: bool,
+    max_trailer_size: usize,
+    trailers: Option<HeaderMap>,
+    upgraded: Option<Connection>,
+    progress: Option<ProgressTracker>,
+}
+
+/// Reports the progress of reading the body.
+struct ProgressTracker {
+    handler: DownloadProgressHandler,
+    progress: DownloadProgress,
+}
+
+impl ProgressTracker {
+    fn new(handler: DownloadProgressHandler, content_length: Option<u64>) -> Self {
+        ProgressTracker {
+            handler,
+            progress: DownloadProgress {
+                content_length,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn record(&mut self, raw: usize, decoded: usize) {
+        if raw == 0 && decoded == 0 {
+            return;
+        }
+        self.progress.raw += raw as u64;
+        self.progress.decoded += decoded as u64;
+        self.handler.call(&self.progress);
+    }
 }
 
 impl BodyHandler {
+    pub(crate) fn trailers(&self) -> Option<&HeaderMap> {
+        self.trailers.as_ref()
+    }
+
+    pub(crate) fn take_upgraded(&mut self) -> Option<Connection> {
+        self.upgraded.take()
+    }
+
     fn do_read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
-        let (Some(flow), Some(connection), timings) =
-            (&mut self.flow, &mut self.connection, &mut self.timings)
-        else {
+        let trailer_limit = self.is_chunked.then_some(self.max_trailer_size);
+
+        let (Some(flow), Some(connection), timings, trailers, progress) = (
+            &mut self.flow,
+            &mut self.connection,
+            &mut self.timings,
+            &mut self.trailers,
+            &mut self.progress,
+        ) else {
             return Ok(0);
         };
 
@@ -556,9 +921,14 @@ impl BodyHandler {
             // First try to use input already buffered
             if has_buffered_input {
                 let input = connection.buffers().input();
-                let (input_used, output_used) = flow.read(input, buf)?;
+                let (input_used, output_used) =
+                    read_input(flow, input, buf, trailers, trailer_limit)?;
                 connection.consume_input(input_used);
 
+                if let Some(p) = progress {
+                    p.record(input_used, output_used);
+                }
+
                 if output_used > 0 {
                     return Ok(output_used);
                 }
@@ -594,9 +964,13 @@ impl BodyHandler {
             let input = connection.buffers().input();
             let input_ended = input.is_empty();
 
-            let (input_used, output_used) = flow.read(input, buf)?;
+            let (input_used, output_used) = read_input(flow, input, buf, trailers, trailer_limit)?;
             connection.consume_input(input_used);
 
+            if let Some(p) = progress {
+                p.record(input_used, output_used);
+            }
+
             if output_used > 0 {
                 return Ok(output_used);
             } else if input_ended {
@@ -617,6 +991,11 @@ impl BodyHandler {
     fn ended(&mut self) -> Result<(), Error> {
         self.timings.record_time(Timeout::RecvBody);
 
+        if self.is_chunked && self.trailers.is_none() {
+            // The chunked body ended without trailers.
+            self.trailers = Some(HeaderMap::new());
+        }
+
         let flow = self.flow.take().expect("ended() called with body");
 
         if !flow.can_proceed() {
@@ -654,6 +1033,106 @@ impl BodyHandler {
     }
 }
 
+/// Read body data from the input, capturing any trailers after the last chunk.
+///
+/// `trailer_limit` is set for chunked bodies, which might have trailers.
+fn read_input(
+    flow: &mut Flow<RecvBody>,
+    input: &[u8],
+    buf: &mut [u8],
+    trailers: &mut Option<HeaderMap>,
+    trailer_limit: Option<usize>,
+) -> Result<(usize, usize), Error> {
+    if let Some(limit) = trailer_limit {
+        if trailers.is_none() && flow.is_on_chunk_boundary() {
+            match parse_trailers(input, limit)? {
+                Trailers::NotLastChunk => {}
+                // Wait for the entire trailer section before letting the flow consume it.
+                Trailers::Incomplete => return Ok((0, 0)),
+                Trailers::Complete(v) => *trailers = Some(v),
+            }
+        }
+    }
+
+    Ok(flow.read(input, buf)?)
+}
+
+enum Trailers {
+    NotLastChunk,
+    Incomplete,
+    Complete(HeaderMap),
+}
+
+/// Parse the trailer section, if the input is at the last chunk.
+///
+/// ```text
+/// 0\r\n
+/// grpc-status: 0\r\n
+/// \r\n
+/// ```
+fn parse_trailers(input: &[u8], max_size: usize) -> Result<Trailers, Error> {
+    let Some(i) = find(input, b"\r\n") else {
+        return Ok(Trailers::Incomplete);
+    };
+
+    // Chunk size, optionally followed by chunk extensions.
+    let size = input[..i].split(|c| *c == b';').next().unwrap_or_default();
+    let is_last = std::str::from_utf8(size)
+        .ok()
+        .and_then(|s| usize::from_str_radix(s.trim(), 16).ok())
+        == Some(0);
+
+    if !is_last {
+        // Malformed sizes are left for the flow to report.
+        return Ok(Trailers::NotLastChunk);
+    }
+
+    let section = &input[i + 2..];
+
+    if section.starts_with(b"\r\n") {
+        return Ok(Trailers::Complete(HeaderMap::new()));
+    }
+
+    let Some(end) = find(section, b"\r\n\r\n") else {
+        if section.len() > max_size {
+            return Err(Error::LargeResponseHeader(section.len(), max_size));
+        }
+        return Ok(Trailers::Incomplete);
+    };
+
+    if end > max_size {
+        return Err(Error::LargeResponseHeader(end, max_size));
+    }
+
+    let mut map = HeaderMap::new();
+
+    for header in parse_headers(&section[..end]) {
+        match header {
+            Ok((name, value)) => {
+                map.append(name, value);
+            }
+            Err(line) => debug!(
+                "Ignore malformed trailer: {:?}",
+                String::from_utf8_lossy(line)
+            ),
+        }
+    }
+
+    Ok(Trailers::Complete(map))
+}
+
+/// Whether the request asks for a protocol upgrade.
+#[cfg(feature = "http2")]
+fn is_upgrade(headers: &HeaderMap) -> bool {
+    headers.contains_key(header::UPGRADE)
+        || headers
+            .get_all(header::CONNECTION)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|v| v.trim().eq_ignore_ascii_case("upgrade"))
+}
+
 impl io::Read for BodyHandler {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         self.do_read(buf).map_err(|e| e.into_io())
@@ -3,7 +3,10 @@ use std::io::{self, Read, Stdin};
 use std::net::TcpStream;
 
 use crate::body::{Body, BodyReader};
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use crate::config::BodyCompression;
 use crate::http;
+use crate::multipart::Multipart;
 use crate::util::private::Private;
 
 /// Request body for sending data via POST, PUT and PATCH.
@@ -62,6 +65,9 @@ impl<'a> SendBody<'a> {
             BodyInner::Reader(v) => v.read(buf),
             BodyInner::OwnedReader(v) => v.read(buf),
             BodyInner::Body(v) => v.read(buf),
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            BodyInner::Compressed(v, _) => v.read(buf),
+            BodyInner::Multipart(v) => v.read(buf),
         }?;
 
         if n == 0 {
@@ -74,6 +80,42 @@ impl<'a> SendBody<'a> {
     pub(crate) fn body_mode(&self) -> BodyMode {
         self.inner.body_mode()
     }
+
+    /// Compress the body while it is read.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    pub(crate) fn into_compressed(self, compression: BodyCompression) -> io::Result<SendBody<'a>> {
+        let replay = match self.inner {
+            BodyInner::ByteSlice(v) => Some((v, compression)),
+            _ => None,
+        };
+
+        let reader = SendBodyReader(self);
+
+        let encoder: Box<dyn Read + 'a> = match compression {
+            #[cfg(feature = "gzip")]
+            BodyCompression::Gzip => Box::new(flate2::read::GzEncoder::new(
+                reader,
+                flate2::Compression::default(),
+            )),
+            #[cfg(feature = "zstd")]
+            BodyCompression::Zstd => Box::new(zstd::stream::read::Encoder::new(reader, 0)?),
+        };
+
+        Ok(BodyInner::Compressed(encoder, replay).into())
+    }
+
+    /// Whether the body can be sent again using [`AsSendBody::as_body`].
+    ///
+    /// Readers are consumed when sent, while byte slices are reborrowed, and
+    /// compressed again if needed.
+    pub(crate) fn is_replayable(&self) -> bool {
+        match self.inner {
+            BodyInner::None | BodyInner::ByteSlice(_) => true,
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            BodyInner::Compressed(_, replay) => replay.is_some(),
+            _ => false,
+        }
+    }
 }
 
 use http::Response;
@@ -101,6 +143,7 @@ use ureq_proto::BodyMode;
 /// * `TcpStream`
 /// * `UnixStream` (not on windows)
 /// * `&[u8; N]`
+/// * [`Multipart`]
 /// * `()`
 ///
 /// # Example
@@ -143,6 +186,19 @@ impl<'a> AsSendBody for SendBody<'a> {
                 BodyInner::Reader(v) => BodyInner::Reader(v),
                 BodyInner::Body(v) => BodyInner::Reader(v),
                 BodyInner::OwnedReader(v) => BodyInner::Reader(v),
+                #[cfg(any(feature = "gzip", feature = "zstd"))]
+                BodyInner::Compressed(v, replay) => match replay {
+                    // Compress the byte slice again, to send it from the start.
+                    Some((data, compression)) => {
+                        let body: SendBody = BodyInner::ByteSlice(data).into();
+                        match body.into_compressed(*compression) {
+                            Ok(v) => v.inner,
+                            Err(_) => BodyInner::Reader(v),
+                        }
+                    }
+                    None => BodyInner::Reader(v),
+                },
+                BodyInner::Multipart(v) => BodyInner::Multipart(v),
             },
             ended: self.ended,
         }
@@ -155,6 +211,21 @@ pub(crate) enum BodyInner<'a> {
     Body(BodyReader<'a>),
     Reader(&'a mut dyn Read),
     OwnedReader(Box<dyn Read>),
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    /// The encoder, and the byte slice it compresses, for sending it again.
+    Compressed(Box<dyn Read + 'a>, Option<(&'a [u8], BodyCompression)>),
+    Multipart(&'a mut Multipart),
+}
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+/// Reads a [`SendBody`] for a compressing encoder.
+struct SendBodyReader<'a>(SendBody<'a>);
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+impl<'a> Read for SendBodyReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
 }
 
 impl<'a> BodyInner<'a> {
@@ -165,6 +236,9 @@ impl<'a> BodyInner<'a> {
             BodyInner::Body(v) => v.body_mode(),
             BodyInner::Reader(_) => BodyMode::Chunked,
             BodyInner::OwnedReader(_) => BodyMode::Chunked,
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            BodyInner::Compressed(..) => BodyMode::Chunked,
+            BodyInner::Multipart(v) => v.body_mode(),
         }
     }
 }
@@ -243,9 +317,145 @@ impl<const N: usize> AsSendBody for &[u8; N] {
     }
 }
 
+impl Private for Multipart {}
+impl AsSendBody for Multipart {
+    fn as_body(&mut self) -> SendBody<'_> {
+        BodyInner::Multipart(self).into()
+    }
+}
+
 impl Private for () {}
 impl AsSendBody for () {
     fn as_body(&mut self) -> SendBody {
         BodyInner::None.into()
     }
 }
+
+#[cfg(all(test, feature = "_test", any(feature = "gzip", feature = "zstd")))]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use crate::transport::set_handler_fn;
+    use crate::Agent;
+
+    /// Agent compressing request bodies, with a handler asserting the
+    /// request decodes to the expected data.
+    fn expect_compressed(
+        pattern: &'static str,
+        compression: BodyCompression,
+        data: Vec<u8>,
+    ) -> Agent {
+        set_handler_fn(pattern, move |_uri, req, w| {
+            let headers = req.headers();
+            assert_eq!(headers["content-encoding"], compression.as_str());
+            assert_eq!(headers["transfer-encoding"], "chunked");
+            assert!(!headers.contains_key("content-length"));
+            assert_eq!(decompress(compression, req.body()), data);
+            write!(w, "HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+        });
+
+        Config::builder()
+            .body_compression(Some(compression))
+            .build()
+            .into()
+    }
+
+    fn decompress(compression: BodyCompression, body: &[u8]) -> Vec<u8> {
+        let mut decoded = vec![];
+        match compression {
+            #[cfg(feature = "gzip")]
+            BodyCompression::Gzip => {
+                flate2::read::GzDecoder::new(body)
+                    .read_to_end(&mut decoded)
+                    .unwrap();
+            }
+            #[cfg(feature = "zstd")]
+            BodyCompression::Zstd => decoded = zstd::stream::decode_all(body).unwrap(),
+        }
+        decoded
+    }
+
+    fn read_all(mut body: SendBody) -> Vec<u8> {
+        let mut out = vec![];
+        let mut buf = [0; 1024];
+        loop {
+            let n = body.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+        out
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn gzip_request_body() {
+        let data = b"log line\n".repeat(10_000);
+        let agent = expect_compressed("/gzip", BodyCompression::Gzip, data.clone());
+        agent.post("https://my.test/gzip").send(&data[..]).unwrap();
+    }
+
+    #[test]
+    #[cfg(all(feature = "gzip", feature = "json"))]
+    fn gzip_json_request_body() {
+        let value = serde_json::json!({ "lines": vec!["log line"; 1_000] });
+        let data = serde_json::to_vec_pretty(&value).unwrap();
+        let agent = expect_compressed("/json", BodyCompression::Gzip, data);
+        agent
+            .post("https://my.test/json")
+            .send_json(&value)
+            .unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn compressed_byte_slice_is_replayable() {
+        let data = b"log line\n".repeat(1_000);
+        let mut slice = &data[..];
+        let mut body = slice
+            .as_body()
+            .into_compressed(BodyCompression::Gzip)
+            .unwrap();
+        assert!(body.is_replayable());
+
+        // Each replay sends the whole body, compressed from the start.
+        for _ in 0..2 {
+            let compressed = read_all(body.as_body());
+            assert_eq!(decompress(BodyCompression::Gzip, &compressed), data);
+        }
+
+        let mut reader = &data[..];
+        let body = SendBody::from_reader(&mut reader)
+            .into_compressed(BodyCompression::Gzip)
+            .unwrap();
+        assert!(!body.is_replayable());
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn empty_request_body_not_compressed() {
+        set_handler_fn("/empty", |_uri, req, w| {
+            assert!(!req.headers().contains_key("content-encoding"));
+            assert_eq!(req.headers()["content-length"], "0");
+            write!(w, "HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+        });
+
+        let agent: Agent = Config::builder()
+            .body_compression(Some(BodyCompression::Gzip))
+            .build()
+            .into();
+
+        agent.post("https://my.test/empty").send("").unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn zstd_request_body() {
+        let data = b"log line\n".repeat(10_000);
+        let agent = expect_compressed("/zstd", BodyCompression::Zstd, data.clone());
+        let mut slice = &data[..];
+        let reader = SendBody::from_reader(&mut slice);
+        agent.post("https://my.test/zstd").send(reader).unwrap();
+    }
+}
@@ -0,0 +1,664 @@
+//! Server-Sent Events.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{BufRead, BufReader};
+use std::thread;
+use std::time::Duration;
+
+use http::header::{ACCEPT, CACHE_CONTROL};
+use http::{HeaderMap, HeaderName, HeaderValue, Response, StatusCode, Uri};
+
+use crate::body::BodyReader;
+use crate::http;
+use crate::{Agent, Body, Error};
+
+/// Reconnection time until the server sets one with the `retry` field.
+const DEFAULT_RETRY: Duration = Duration::from_secs(3);
+
+const LAST_EVENT_ID: &str = "last-event-id";
+
+/// An event of a `text/event-stream` body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// The event type, from the `event` field. Defaults to `message`.
+    pub event: String,
+
+    /// The `data` fields, joined by line breaks.
+    pub data: String,
+
+    /// The last event ID, from the most recent `id` field of the stream.
+    pub id: Option<String>,
+
+    /// The reconnection time, if this event had a `retry` field.
+    pub retry: Option<Duration>,
+}
+
+/// Iterator of the events of a `text/event-stream` body.
+///
+/// Obtained via [`Body::into_event_stream()`]. Events are parsed as the body is
+/// read, and the iterator ends with the body. An event that is not complete when
+/// the body ends is dropped.
+///
+/// To reconnect when the stream ends, use [`Agent::event_source()`].
+pub struct EventStream {
+    reader: BufReader<BodyReader<'static>>,
+    max_size: u64,
+    last_event_id: Option<String>,
+    /// Whether the stream had an `id` field, which might have reset the ID.
+    has_id: bool,
+    retry: Option<Duration>,
+    /// The previous line ended with `\r`, which may be followed by `\n`.
+    skip_lf: bool,
+    first_line: bool,
+    done: bool,
+}
+
+impl EventStream {
+    pub(crate) fn new(reader: BodyReader<'static>, max_size: u64) -> Self {
+        EventStream {
+            reader: BufReader::new(reader),
+            max_size,
+            last_event_id: None,
+            has_id: false,
+            retry: None,
+            skip_lf: false,
+            first_line: true,
+            done: false,
+        }
+    }
+
+    /// The last event ID of the stream.
+    ///
+    /// This is what to send in the `Last-Event-ID` header to resume the stream.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// The reconnection time most recently set by the server.
+    pub fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    /// Read one line, without the line ending. Returns `false` at the end of the body.
+    fn read_line(&mut self, line: &mut Vec<u8>) -> Result<bool, Error> {
+        loop {
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(false);
+            }
+
+            if self.skip_lf {
+                self.skip_lf = false;
+                if buf[0] == b'\n' {
+                    self.reader.consume(1);
+                    continue;
+                }
+            }
+
+            let (used, found) = match buf.iter().position(|c| *c == b'\n' || *c == b'\r') {
+                Some(pos) => {
+                    line.extend_from_slice(&buf[..pos]);
+                    self.skip_lf = buf[pos] == b'\r';
+                    (pos + 1, true)
+                }
+                None => {
+                    line.extend_from_slice(buf);
+                    (buf.len(), false)
+                }
+            };
+            self.reader.consume(used);
+
+            if line.len() as u64 > self.max_size {
+                return Err(Error::BodyExceedsLimit(self.max_size));
+            }
+
+            if found {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn next_event(&mut self) -> Result<Option<Event>, Error> {
+        let mut event = String::new();
+        let mut data = String::new();
+        let mut retry = None;
+
+        let mut line = Vec::new();
+
+        loop {
+            line.clear();
+            if !self.read_line(&mut line)? {
+                return Ok(None);
+            }
+
+            if self.first_line {
+                self.first_line = false;
+                if line.starts_with(b"\xef\xbb\xbf") {
+                    line.drain(..3);
+                }
+            }
+
+            if line.is_empty() {
+                if data.is_empty() {
+                    // Nothing to dispatch.
+                    event.clear();
+                    retry = None;
+                    continue;
+                }
+
+                data.pop();
+
+                if event.is_empty() {
+                    event.push_str("message");
+                }
+
+                return Ok(Some(Event {
+                    event,
+                    data,
+                    id: self.last_event_id.clone(),
+                    retry,
+                }));
+            }
+
+            if line[0] == b':' {
+                // Comment, often used as keep-alive.
+                continue;
+            }
+
+            let line = String::from_utf8_lossy(&line);
+
+            let (field, value) = match line.split_once(':') {
+                Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+                None => (&*line, ""),
+            };
+
+            match field {
+                "event" => {
+                    event.clear();
+                    event.push_str(value);
+                }
+                "data" => {
+                    data.push_str(value);
+                    data.push('\n');
+
+                    if data.len() as u64 > self.max_size {
+                        return Err(Error::BodyExceedsLimit(self.max_size));
+                    }
+                }
+                "id" if !value.contains('\0') => {
+                    self.last_event_id = Some(value.to_string()).filter(|v| !v.is_empty());
+                    self.has_id = true;
+                }
+                "retry" if !value.is_empty() && value.bytes().all(|c| c.is_ascii_digit()) => {
+                    if let Ok(millis) = value.parse() {
+                        retry = Some(Duration::from_millis(millis));
+                        self.retry = retry;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Iterator for EventStream {
+    type Item = Result<Event, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.next_event();
+        if !matches!(result, Ok(Some(_))) {
+            self.done = true;
+        }
+
+        result.transpose()
+    }
+}
+
+/// Builder for an [`EventSource`].
+///
+/// Created via [`Agent::event_source()`].
+pub struct EventSourceBuilder {
+    agent: Agent,
+    builder: http::request::Builder,
+    last_event_id: Option<String>,
+    retry: Duration,
+    max_reconnects: Option<u32>,
+}
+
+impl EventSourceBuilder {
+    pub(crate) fn new<T>(agent: Agent, uri: T) -> Self
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        EventSourceBuilder {
+            agent,
+            builder: http::Request::get(uri),
+            last_event_id: None,
+            retry: DEFAULT_RETRY,
+            max_reconnects: None,
+        }
+    }
+
+    /// Appends a header to every request of the event source.
+    pub fn header<K, V>(mut self, key: K, value: V) -> Self
+    where
+        HeaderName: TryFrom<K>,
+        <HeaderName as TryFrom<K>>::Error: Into<http::Error>,
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<http::Error>,
+    {
+        self.builder = self.builder.header(key, value);
+        self
+    }
+
+    /// Resume a stream after this event ID, by sending it in `Last-Event-ID`.
+    pub fn last_event_id(mut self, id: impl Into<String>) -> Self {
+        self.last_event_id = Some(id.into());
+        self
+    }
+
+    /// Time to wait before reconnecting, until the server sets it with `retry`.
+    ///
+    /// Defaults to 3 seconds
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Max number of reconnects without receiving an event in between.
+    ///
+    /// `None` means to reconnect forever.
+    ///
+    /// Defaults to `None`
+    pub fn max_reconnects(mut self, max: Option<u32>) -> Self {
+        self.max_reconnects = max;
+        self
+    }
+
+    /// Make the first request.
+    ///
+    /// Failing to connect is returned here, without reconnecting.
+    pub fn connect(self) -> Result<EventSource, Error> {
+        let request = self.builder.body(())?;
+        let (parts, _) = request.into_parts();
+
+        let mut source = EventSource {
+            agent: self.agent,
+            uri: parts.uri,
+            headers: parts.headers,
+            stream: None,
+            last_event_id: self.last_event_id,
+            retry: self.retry,
+            max_reconnects: self.max_reconnects,
+            reconnects: 0,
+            done: false,
+        };
+
+        source.stream = source.open()?;
+        source.done = source.stream.is_none();
+
+        Ok(source)
+    }
+}
+
+/// A `text/event-stream` that reconnects.
+///
+/// Created via [`Agent::event_source()`]. When the stream ends, or the connection fails,
+/// the event source waits for the reconnection time and requests the stream again. The
+/// last event ID received is sent in the `Last-Event-ID` header, which lets the server
+/// resume where the stream left off.
+///
+/// The iteration ends when the server answers `204 No Content`, and fails on any other
+/// response than a `200 OK` with content-type `text/event-stream`. Server errors (5xx)
+/// and I/O errors are retried, up to
+/// [`max_reconnects`](EventSourceBuilder::max_reconnects).
+///
+/// A timeout configured for the agent, such as
+/// [`timeout_global`](crate::config::ConfigBuilder::timeout_global), ends the stream,
+/// which is then reconnected.
+pub struct EventSource {
+    agent: Agent,
+    uri: Uri,
+    headers: HeaderMap,
+    stream: Option<EventStream>,
+    last_event_id: Option<String>,
+    retry: Duration,
+    max_reconnects: Option<u32>,
+    reconnects: u32,
+    done: bool,
+}
+
+impl EventSource {
+    /// The last event ID received.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// Request the stream. `None` means the server doesn't want a reconnect.
+    fn open(&mut self) -> Result<Option<EventStream>, Error> {
+        let mut request = http::Request::get(self.uri.clone()).body(())?;
+
+        let headers = request.headers_mut();
+        *headers = self.headers.clone();
+        if !headers.contains_key(ACCEPT) {
+            headers.insert(ACCEPT, HeaderValue::from_static("text/event-stream"));
+        }
+        if !headers.contains_key(CACHE_CONTROL) {
+            headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        }
+        if let Some(id) = &self.last_event_id {
+            let value = HeaderValue::from_str(id).map_err(http::Error::from)?;
+            headers.insert(LAST_EVENT_ID, value);
+        }
+
+        let response: Response<Body> = self.agent.run(request)?;
+
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::NO_CONTENT => {
+                debug!("Event source closed by server");
+                return Ok(None);
+            }
+            _ => return Err(self.agent.config().status_error(response)),
+        }
+
+        let is_event_stream = response
+            .body()
+            .mime_type()
+            .map(|m| m.trim().eq_ignore_ascii_case("text/event-stream"))
+            .unwrap_or(false);
+
+        if !is_event_stream {
+            return Err(Error::EventStream("response is not text/event-stream"));
+        }
+
+        Ok(Some(response.into_body().into_event_stream()))
+    }
+
+    /// Whether to reconnect after `error`, or the end of the stream.
+    fn reconnect(&mut self, error: Option<&Error>) -> bool {
+        if error.map(|e| !e.is_retryable()).unwrap_or(false) {
+            return false;
+        }
+        if let Some(max) = self.max_reconnects {
+            if self.reconnects >= max {
+                return false;
+            }
+        }
+
+        self.reconnects += 1;
+        debug!("Reconnect event source in {:?}", self.retry);
+        thread::sleep(self.retry);
+
+        true
+    }
+}
+
+impl Iterator for EventSource {
+    type Item = Result<Event, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.done {
+                return None;
+            }
+
+            let Some(stream) = &mut self.stream else {
+                match self.open() {
+                    Ok(stream) => {
+                        self.done = stream.is_none();
+                        self.stream = stream;
+                    }
+                    Err(e) => {
+                        if !self.reconnect(Some(&e)) {
+                            self.done = true;
+                            return Some(Err(e));
+                        }
+                    }
+                }
+                continue;
+            };
+
+            let next = stream.next();
+
+            // The stream might have seen id or retry without a dispatched event.
+            if stream.has_id {
+                self.last_event_id = stream.last_event_id.clone();
+            }
+            if let Some(retry) = stream.retry() {
+                self.retry = retry;
+            }
+
+            match next {
+                Some(Ok(event)) => {
+                    self.reconnects = 0;
+                    return Some(Ok(event));
+                }
+                Some(Err(e)) => {
+                    self.stream = None;
+                    if !self.reconnect(Some(&e)) {
+                        self.done = true;
+                        return Some(Err(e));
+                    }
+                }
+                None => {
+                    self.stream = None;
+                    if !self.reconnect(None) {
+                        self.done = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for EventStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventStream")
+            .field("last_event_id", &self.last_event_id)
+            .field("retry", &self.retry)
+            .finish()
+    }
+}
+
+impl fmt::Debug for EventSourceBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventSourceBuilder")
+            .field("last_event_id", &self.last_event_id)
+            .field("retry", &self.retry)
+            .field("max_reconnects", &self.max_reconnects)
+            .finish()
+    }
+}
+
+impl fmt::Debug for EventSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventSource")
+            .field("uri", &self.uri)
+            .field("last_event_id", &self.last_event_id)
+            .field("retry", &self.retry)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn events(data: &str) -> Vec<Event> {
+        Body::builder()
+            .mime_type("text/event-stream")
+            .data(data)
+            .into_event_stream()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn event(event: &str, data: &str, id: Option<&str>, retry: Option<u64>) -> Event {
+        Event {
+            event: event.into(),
+            data: data.into(),
+            id: id.map(|v| v.into()),
+            retry: retry.map(Duration::from_millis),
+        }
+    }
+
+    #[test]
+    fn parse_events() {
+        let events = events(
+            "\u{feff}: keep-alive\n\
+            data: first\n\
+            data:  second\n\
+            data\n\
+            \n\
+            event: update\r\n\
+            id: 7\r\n\
+            retry: 500\r\n\
+            data:{\"a\":1}\r\n\
+            \r\n\
+            retry: soon\r\
+            data: x\r\
+            \r\
+            id\n\
+            \n\
+            data: y\n\
+            \n\
+            data: incomplete\n",
+        );
+
+        assert_eq!(
+            events,
+            [
+                event("message", "first\n second\n", None, None),
+                event("update", "{\"a\":1}", Some("7"), Some(500)),
+                event("message", "x", Some("7"), None),
+                event("message", "y", None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn event_size_limit() {
+        let body = Body::builder().data(format!("data: {}\n\n", "x".repeat(100)));
+        let mut stream = EventStream::new(body.into_reader(), 50);
+
+        assert!(matches!(
+            stream.next(),
+            Some(Err(Error::BodyExceedsLimit(50)))
+        ));
+        assert!(stream.next().is_none());
+    }
+
+    #[cfg(feature = "_test")]
+    #[test]
+    fn event_source_reconnect() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use crate::transport::set_handler_fn;
+
+        let calls = AtomicUsize::new(0);
+
+        set_handler_fn("/events", move |_uri, req, w| {
+            let last_event_id = req
+                .headers()
+                .get("last-event-id")
+                .map(|v| v.to_str().unwrap().to_string());
+            assert_eq!(req.headers()["accept"], "text/event-stream");
+
+            let body = match calls.fetch_add(1, Ordering::SeqCst) {
+                0 => {
+                    assert_eq!(last_event_id, None);
+                    "retry: 0\n\nid: 1\ndata: a\n\n"
+                }
+                1 => {
+                    assert_eq!(last_event_id.as_deref(), Some("1"));
+                    "id: 2\ndata: b\n\n"
+                }
+                _ => {
+                    assert_eq!(last_event_id.as_deref(), Some("2"));
+                    return write!(w, "HTTP/1.1 204 No Content\r\n\r\n");
+                }
+            };
+
+            write!(
+                w,
+                "HTTP/1.1 200 OK\r\n\
+                content-type: text/event-stream\r\n\
+                content-length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        });
+
+        let data: Vec<_> = Agent::new_with_defaults()
+            .event_source("https://my.test/events")
+            .connect()
+            .unwrap()
+            .map(|e| e.unwrap().data)
+            .collect();
+
+        assert_eq!(data, ["a", "b"]);
+    }
+
+    #[cfg(feature = "_test")]
+    #[test]
+    fn event_source_id_reset() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use crate::transport::set_handler_fn;
+
+        let calls = AtomicUsize::new(0);
+
+        set_handler_fn("/events-reset", move |_uri, req, w| {
+            assert_eq!(req.headers()["cache-control"], "max-age=0");
+
+            if calls.fetch_add(1, Ordering::SeqCst) > 0 {
+                assert!(req.headers().get("last-event-id").is_none());
+                return write!(w, "HTTP/1.1 204 No Content\r\n\r\n");
+            }
+
+            // An empty id resets the last event ID, also without an event.
+            let body = "retry: 0\n\nid: 1\ndata: a\n\nid\n\n";
+            write!(
+                w,
+                "HTTP/1.1 200 OK\r\n\
+                content-type: text/event-stream\r\n\
+                content-length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        });
+
+        let mut source = Agent::new_with_defaults()
+            .event_source("https://my.test/events-reset")
+            .header("cache-control", "max-age=0")
+            .max_reconnects(Some(1))
+            .connect()
+            .unwrap();
+
+        assert_eq!(source.next().unwrap().unwrap().id.as_deref(), Some("1"));
+        assert!(source.next().is_none());
+        assert_eq!(source.last_event_id(), None);
+    }
+
+    #[cfg(feature = "_test")]
+    #[test]
+    fn event_source_not_event_stream() {
+        use crate::transport::set_handler;
+
+        set_handler("/not-events", 200, &[("content-type", "text/plain")], b"hi");
+
+        let err = Agent::new_with_defaults()
+            .event_source("https://my.test/not-events")
+            .connect()
+            .unwrap_err();
+
+        assert!(matches!(err, Error::EventStream(_)));
+    }
+}
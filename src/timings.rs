@@ -1,5 +1,7 @@
-use std::fmt;
 use std::sync::Arc;
+use std::{fmt, time};
+
+use once_cell::sync::OnceCell;
 
 use crate::config::Timeouts;
 use crate::transport::time::{Duration, Instant};
@@ -87,6 +89,9 @@ pub(crate) struct CallTimings {
     timeouts: Timeouts,
     current_time: CurrentTime,
     times: ArrayVec<(Timeout, Instant), 8>,
+
+    /// Set once the body is received, shared with the [`HopTimings`] of this call.
+    body_download: Option<Arc<OnceCell<time::Duration>>>,
 }
 
 impl Default for CallTimings {
@@ -95,6 +100,7 @@ impl Default for CallTimings {
             timeouts: Default::default(),
             current_time: Default::default(),
             times: empty_times(),
+            body_download: None,
         }
     }
 }
@@ -115,6 +121,7 @@ impl CallTimings {
             timeouts,
             current_time,
             times,
+            body_download: None,
         }
     }
 
@@ -126,9 +133,17 @@ impl CallTimings {
             timeouts: self.timeouts,
             current_time: self.current_time,
             times: self.times,
+            body_download: None,
         }
     }
 
+    /// Forget the times recorded on a connection, to run the same call on a new one.
+    ///
+    /// Global and PerCall are kept, since the retry is part of the same call.
+    pub(crate) fn reset_connection(&mut self) {
+        self.times.truncate(2);
+    }
+
     pub(crate) fn now(&self) -> Instant {
         self.current_time.now()
     }
@@ -152,12 +167,65 @@ impl CallTimings {
 
         // Record the time
         self.times.push((timeout, self.current_time.now()));
+
+        if timeout == Timeout::RecvBody {
+            if let (Some(cell), Some(v)) = (
+                &self.body_download,
+                self.between(Timeout::RecvResponse, Timeout::RecvBody),
+            ) {
+                let _ = cell.set(v);
+            }
+        }
     }
 
     fn time_of(&self, timeout: Timeout) -> Option<Instant> {
         self.times.iter().find(|x| x.0 == timeout).map(|x| x.1)
     }
 
+    fn between(&self, from: Timeout, to: Timeout) -> Option<time::Duration> {
+        let from = self.time_of(from)?;
+        let to = self.time_of(to)?;
+
+        match to.duration_since(from) {
+            Duration::Exact(v) => Some(v),
+            Duration::NotHappening => None,
+        }
+    }
+
+    /// Timings of this call, once the response headers are received.
+    ///
+    /// The body download time is filled in when the body has been read.
+    pub(crate) fn hop_timings(
+        &mut self,
+        tls_handshake: Option<time::Duration>,
+        connection_reused: bool,
+    ) -> HopTimings {
+        // The last step before receiving the response.
+        let sent = [Timeout::SendBody, Timeout::Await100, Timeout::SendRequest]
+            .iter()
+            .copied()
+            .find(|t| self.time_of(*t).is_some())
+            .unwrap_or(Timeout::SendRequest);
+
+        // TLS transports do the handshake lazily when sending the request.
+        let send = self
+            .between(Timeout::Connect, sent)
+            .map(|v| v.saturating_sub(tls_handshake.unwrap_or_default()));
+
+        let body_download = Arc::new(OnceCell::new());
+        self.body_download = Some(body_download.clone());
+
+        HopTimings {
+            dns: self.between(Timeout::PerCall, Timeout::Resolve),
+            connect: self.between(Timeout::Resolve, Timeout::Connect),
+            tls_handshake,
+            send,
+            time_to_first_byte: self.between(sent, Timeout::RecvResponse),
+            body_download,
+            connection_reused,
+        }
+    }
+
     pub(crate) fn next_timeout(&self, timeout: Timeout) -> NextTimeout {
         let (reason, at) = timeout
             .timeouts_to_check()
@@ -176,6 +244,100 @@ impl CallTimings {
     }
 }
 
+/// Time spent in each phase of a request.
+///
+/// This is found in the extensions of every [`http::Response`](crate::http::Response)
+/// returned by ureq. There is one [`HopTimings`] per call made, which means more than one
+/// when following redirects.
+///
+/// ```
+/// use ureq::ResponseTimings;
+///
+/// let mut res = ureq::get("http://httpbin.org/get").call()?;
+/// res.body_mut().read_to_string()?;
+///
+/// let timings = res.extensions().get::<ResponseTimings>().unwrap();
+/// let last = timings.hops().last().unwrap();
+///
+/// println!("dns: {:?}", last.dns());
+/// println!("connect: {:?}", last.connect());
+/// println!("time to first byte: {:?}", last.time_to_first_byte());
+/// println!("body: {:?}", last.body_download());
+/// # Ok::<_, ureq::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ResponseTimings {
+    hops: Vec<HopTimings>,
+}
+
+impl ResponseTimings {
+    pub(crate) fn push(&mut self, hop: HopTimings) {
+        self.hops.push(hop);
+    }
+
+    /// Timings of each call, in order. The last is the call that produced the response.
+    pub fn hops(&self) -> &[HopTimings] {
+        &self.hops
+    }
+}
+
+/// Timings of a single call. See [`ResponseTimings`].
+///
+/// A phase that didn't happen, or didn't complete, has no duration.
+#[derive(Debug, Clone)]
+pub struct HopTimings {
+    dns: Option<time::Duration>,
+    connect: Option<time::Duration>,
+    tls_handshake: Option<time::Duration>,
+    send: Option<time::Duration>,
+    time_to_first_byte: Option<time::Duration>,
+    body_download: Arc<OnceCell<time::Duration>>,
+    connection_reused: bool,
+}
+
+impl HopTimings {
+    /// Time spent resolving the host name.
+    pub fn dns(&self) -> Option<time::Duration> {
+        self.dns
+    }
+
+    /// Time spent opening the connection, including any proxy handshake, but not TLS.
+    ///
+    /// This is close to zero for a connection reused from the pool.
+    pub fn connect(&self) -> Option<time::Duration> {
+        self.connect
+    }
+
+    /// Time spent on the TLS handshake.
+    ///
+    /// `None` for plain text connections and connections reused from the pool.
+    pub fn tls_handshake(&self) -> Option<time::Duration> {
+        self.tls_handshake
+    }
+
+    /// Time spent sending the request headers and body.
+    pub fn send(&self) -> Option<time::Duration> {
+        self.send
+    }
+
+    /// Time from the request being sent to the response headers being received.
+    pub fn time_to_first_byte(&self) -> Option<time::Duration> {
+        self.time_to_first_byte
+    }
+
+    /// Time spent receiving the response body.
+    ///
+    /// For the last call, this is only available once the body has been read to the end.
+    pub fn body_download(&self) -> Option<time::Duration> {
+        self.body_download.get().copied()
+    }
+
+    /// Whether the call used a connection from the pool.
+    pub fn connection_reused(&self) -> bool {
+        self.connection_reused
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct CurrentTime(Arc<dyn Fn() -> Instant + Send + Sync + 'static>);
 
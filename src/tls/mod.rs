@@ -1,7 +1,8 @@
 //! TLS for handling `https`.
 
+use std::collections::VecDeque;
 use std::fmt;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 mod cert;
 pub use cert::{parse_pem, Certificate, PemItem, PrivateKey};
@@ -106,6 +107,31 @@ impl TlsConfig {
             config: TlsConfig::default(),
         }
     }
+
+    /// Tells whether two configs would produce the same TLS client setup.
+    ///
+    /// Certificates are compared by identity (the `Arc` they are held in), not
+    /// by content. Cloning a config therefore keeps it "the same".
+    pub(crate) fn is_same_config(&self, other: &TlsConfig) -> bool {
+        let same_client_cert = match (&self.client_cert, &other.client_cert) {
+            (None, None) => true,
+            (Some(a), Some(b)) => Arc::ptr_eq(&a.0, &b.0),
+            _ => false,
+        };
+
+        let same_root_certs = match (&self.root_certs, &other.root_certs) {
+            (RootCerts::Specific(a), RootCerts::Specific(b)) => Arc::ptr_eq(a, b),
+            (RootCerts::PlatformVerifier, RootCerts::PlatformVerifier) => true,
+            (RootCerts::WebPki, RootCerts::WebPki) => true,
+            _ => false,
+        };
+
+        self.provider == other.provider
+            && self.use_sni == other.use_sni
+            && self.disable_verification == other.disable_verification
+            && same_client_cert
+            && same_root_certs
+    }
 }
 
 /// Builder of [`TlsConfig`]
@@ -211,6 +237,67 @@ impl<I: IntoIterator<Item = Certificate<'static>>> From<I> for RootCerts {
     }
 }
 
+/// Max number of distinct [`TlsConfig`] to keep a built client config for.
+const MAX_CACHED_CONFIGS: usize = 8;
+
+/// LRU cache of TLS client configs, one per distinct [`TlsConfig`].
+///
+/// Building a TLS client config is expensive (root certs are parsed etc.), so
+/// the connectors keep the built result around. Since [`TlsConfig`] can be set
+/// per request, we can't assume it's the same for every connection.
+pub(crate) struct ConfigCache<T> {
+    // The cached TlsConfig holds on to the Arc of the certificates, which
+    // means the pointer comparison in is_same_config() can't be fooled by
+    // a reused allocation.
+    entries: Mutex<VecDeque<(TlsConfig, T)>>,
+}
+
+impl<T: Clone> ConfigCache<T> {
+    pub fn get_or_try_init<E>(
+        &self,
+        tls_config: &TlsConfig,
+        build: impl FnOnce(&TlsConfig) -> Result<T, E>,
+    ) -> Result<T, E> {
+        {
+            let mut entries = self.entries.lock().unwrap();
+
+            if let Some(i) = entries.iter().position(|e| e.0.is_same_config(tls_config)) {
+                // Move to the back to mark as most recently used.
+                let entry = entries.remove(i).unwrap(); // unwrap ok since we just got position
+                let value = entry.1.clone();
+                entries.push_back(entry);
+                return Ok(value);
+            }
+        }
+
+        // Build outside the lock. If two threads race to build the same
+        // config, we end up with a duplicate entry, which is harmless.
+        let value = build(tls_config)?;
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.push_back((tls_config.clone(), value.clone()));
+
+        while entries.len() > MAX_CACHED_CONFIGS {
+            entries.pop_front();
+        }
+
+        Ok(value)
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+}
+
+impl<T> Default for ConfigCache<T> {
+    fn default() -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+}
+
 impl Default for TlsConfig {
     fn default() -> Self {
         let provider = TlsProvider::default();
@@ -252,4 +339,60 @@ mod test {
         let c = TlsConfig::default();
         assert_no_alloc(|| c.clone());
     }
+
+    #[test]
+    fn tls_config_same_config() {
+        let c1 = TlsConfig::default();
+        assert!(c1.is_same_config(&c1.clone()));
+        assert!(c1.is_same_config(&TlsConfig::default()));
+
+        let c2 = TlsConfig::builder().use_sni(false).build();
+        assert!(!c1.is_same_config(&c2));
+
+        let c3 = TlsConfig::builder().root_certs(RootCerts::from([])).build();
+        let c4 = TlsConfig::builder().root_certs(RootCerts::from([])).build();
+        assert!(c3.is_same_config(&c3.clone()));
+        // Specific root certs are compared by identity.
+        assert!(!c3.is_same_config(&c4));
+    }
+
+    #[test]
+    fn config_cache_per_tls_config() {
+        let cache = ConfigCache::default();
+        let build = |c: &TlsConfig| Ok::<_, ()>(c.use_sni);
+
+        let c1 = TlsConfig::default();
+        let c2 = TlsConfig::builder().use_sni(false).build();
+
+        assert_eq!(cache.get_or_try_init(&c1, build), Ok(true));
+        assert_eq!(cache.get_or_try_init(&c2, build), Ok(false));
+        assert_eq!(cache.len(), 2);
+
+        // Cached, the builder is not called again.
+        let r = cache.get_or_try_init(&c1.clone(), |_| Err(()));
+        assert_eq!(r, Ok(true));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn config_cache_evicts_least_recently_used() {
+        let cache = ConfigCache::default();
+        let configs: Vec<_> = (0..=MAX_CACHED_CONFIGS)
+            .map(|_| TlsConfig::builder().root_certs(RootCerts::from([])).build())
+            .collect();
+
+        for (i, c) in configs.iter().enumerate() {
+            cache.get_or_try_init(c, |_| Ok::<_, ()>(i)).unwrap();
+        }
+
+        assert_eq!(cache.len(), MAX_CACHED_CONFIGS);
+
+        // The first one is evicted and must be rebuilt.
+        let r = cache.get_or_try_init(&configs[0], |_| Err(()));
+        assert_eq!(r, Err(()));
+
+        // The last one is still there.
+        let r = cache.get_or_try_init(&configs[MAX_CACHED_CONFIGS], |_| Err(()));
+        assert_eq!(r, Ok(MAX_CACHED_CONFIGS));
+    }
 }
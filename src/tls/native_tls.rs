@@ -2,14 +2,14 @@ use std::convert::TryFrom;
 use std::fmt;
 use std::io::{Read, Write};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::tls::{RootCerts, TlsProvider};
+use crate::tls::{ConfigCache, RootCerts, TlsProvider};
 use crate::{transport::*, Error};
 use der::pem::LineEnding;
 use der::Document;
 use native_tls::{Certificate, HandshakeError, Identity, TlsConnector};
 use native_tls::{TlsConnectorBuilder, TlsStream};
-use once_cell::sync::OnceCell;
 
 use super::TlsConfig;
 
@@ -18,7 +18,9 @@ use super::TlsConfig;
 /// Requires feature flag **native-tls**.
 #[derive(Default)]
 pub struct NativeTlsConnector {
-    connector: OnceCell<Arc<TlsConnector>>,
+    connectors: ConfigCache<Arc<TlsConnector>>,
+    #[cfg(feature = "http2")]
+    http2_connectors: ConfigCache<Arc<TlsConnector>>,
 }
 
 impl Connector for NativeTlsConnector {
@@ -45,20 +47,7 @@ impl Connector for NativeTlsConnector {
 
         trace!("Try wrap TLS");
 
-        let tls_config = &details.config.tls_config;
-
-        // Initialize the connector on first run.
-        let connector_ref = match self.connector.get() {
-            Some(v) => v,
-            None => {
-                // This is unlikely to be racy, but if it is, doesn't matter much.
-                let c = build_connector(tls_config)?;
-                // Maybe someone else set it first. Weird, but ok.
-                let _ = self.connector.set(c);
-                self.connector.get().unwrap()
-            }
-        };
-        let connector = connector_ref.clone(); // cheap clone due to Arc
+        let connector = self.connector(details)?;
 
         let domain = details
             .uri
@@ -67,7 +56,15 @@ impl Connector for NativeTlsConnector {
             .host()
             .to_string();
 
-        let adapter = TransportAdapter::new(transport);
+        #[allow(unused_mut)]
+        let mut adapter = TransportAdapter::new(transport);
+
+        // The handshake happens right away, see below.
+        #[cfg(feature = "http2")]
+        if details.offers_http2() {
+            adapter.set_timeout(details.timeout);
+        }
+
         let stream = LazyStream::Unstarted(Some((connector, domain, adapter)));
 
         let buffers = LazyBuffers::new(
@@ -75,7 +72,14 @@ impl Connector for NativeTlsConnector {
             details.config.output_buffer_size,
         );
 
-        let transport = Box::new(NativeTlsTransport { buffers, stream });
+        #[allow(unused_mut)]
+        let mut transport = Box::new(NativeTlsTransport { buffers, stream });
+
+        // The negotiated protocol is needed before the first request.
+        #[cfg(feature = "http2")]
+        if details.offers_http2() {
+            transport.stream.handshaken()?;
+        }
 
         debug!("Wrapped TLS");
 
@@ -83,7 +87,25 @@ impl Connector for NativeTlsConnector {
     }
 }
 
-fn build_connector(tls_config: &TlsConfig) -> Result<Arc<TlsConnector>, Error> {
+impl NativeTlsConnector {
+    fn connector(&self, details: &ConnectionDetails) -> Result<Arc<TlsConnector>, Error> {
+        let tls_config = &details.config.tls_config;
+
+        #[cfg(feature = "http2")]
+        if details.offers_http2() {
+            return self
+                .http2_connectors
+                .get_or_try_init(tls_config, |c| build_connector(c, &["h2", "http/1.1"]));
+        }
+
+        // Reuse the connector built for an identical TlsConfig, or build a new one.
+        // The TlsConfig can differ per request.
+        self.connectors
+            .get_or_try_init(tls_config, |c| build_connector(c, &[]))
+    }
+}
+
+fn build_connector(tls_config: &TlsConfig, alpn: &[&str]) -> Result<Arc<TlsConnector>, Error> {
     let mut builder = TlsConnector::builder();
 
     if tls_config.disable_verification {
@@ -129,6 +151,13 @@ fn build_connector(tls_config: &TlsConfig) -> Result<Arc<TlsConnector>, Error> {
 
     builder.use_sni(tls_config.use_sni);
 
+    #[cfg(feature = "http2")]
+    if !alpn.is_empty() {
+        builder.request_alpns(alpn);
+    }
+    #[cfg(not(feature = "http2"))]
+    let _ = alpn;
+
     if !tls_config.use_sni {
         debug!("Disable SNI");
     }
@@ -212,13 +241,30 @@ impl Transport for NativeTlsTransport {
     fn is_tls(&self) -> bool {
         true
     }
+
+    fn tls_handshake_duration(&self) -> Option<Duration> {
+        match &self.stream {
+            LazyStream::Unstarted(_) => None,
+            LazyStream::Started(_, v) => Some(*v),
+        }
+    }
+
+    #[cfg(feature = "http2")]
+    fn is_http2(&self) -> bool {
+        match &self.stream {
+            LazyStream::Unstarted(_) => false,
+            LazyStream::Started(v, _) => {
+                v.negotiated_alpn().ok().flatten().as_deref() == Some(b"h2")
+            }
+        }
+    }
 }
 
 /// Helper to delay the handshake until we are starting IO.
 /// This normalizes native-tls to behave like rustls.
 enum LazyStream {
     Unstarted(Option<(Arc<TlsConnector>, String, TransportAdapter)>),
-    Started(TlsStream<TransportAdapter>),
+    Started(TlsStream<TransportAdapter>, Duration),
 }
 
 impl LazyStream {
@@ -226,15 +272,16 @@ impl LazyStream {
         match self {
             LazyStream::Unstarted(v) => {
                 let (conn, domain, adapter) = v.take().unwrap();
+                let started = Instant::now();
                 let stream = conn.connect(&domain, adapter).map_err(|e| match e {
                     HandshakeError::Failure(e) => e,
                     HandshakeError::WouldBlock(_) => unreachable!(),
                 })?;
-                *self = LazyStream::Started(stream);
+                *self = LazyStream::Started(stream, started.elapsed());
                 // Next time we hit the other match arm
                 return self.handshaken();
             }
-            LazyStream::Started(v) => Ok(v),
+            LazyStream::Started(v, _) => Ok(v),
         }
     }
 }
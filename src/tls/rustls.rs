@@ -2,15 +2,15 @@ use std::convert::TryInto;
 use std::fmt;
 use std::io::{Read, Write};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use once_cell::sync::OnceCell;
 use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
 use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned, ALL_VERSIONS};
 use rustls_pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs1KeyDer, PrivatePkcs8KeyDer};
 use rustls_pki_types::{PrivateSec1KeyDer, ServerName};
 
 use crate::tls::cert::KeyKind;
-use crate::tls::{RootCerts, TlsProvider};
+use crate::tls::{ConfigCache, RootCerts, TlsProvider};
 use crate::transport::{Buffers, ConnectionDetails, Connector, LazyBuffers};
 use crate::transport::{NextTimeout, Transport, TransportAdapter};
 use crate::Error;
@@ -22,7 +22,9 @@ use super::TlsConfig;
 /// Requires feature flag **rustls**.
 #[derive(Default)]
 pub struct RustlsConnector {
-    config: OnceCell<Arc<ClientConfig>>,
+    configs: ConfigCache<Arc<ClientConfig>>,
+    #[cfg(feature = "http2")]
+    http2_configs: ConfigCache<Arc<ClientConfig>>,
 }
 
 impl Connector for RustlsConnector {
@@ -49,11 +51,7 @@ impl Connector for RustlsConnector {
 
         trace!("Try wrap in TLS");
 
-        let tls_config = &details.config.tls_config;
-
-        // Initialize the config on first run.
-        let config_ref = self.config.get_or_init(|| build_config(tls_config));
-        let config = config_ref.clone(); // cheap clone due to Arc
+        let config = self.config(details)?;
 
         let name_borrowed: ServerName<'_> = details
             .uri
@@ -79,7 +77,18 @@ impl Connector for RustlsConnector {
             details.config.output_buffer_size,
         );
 
-        let transport = Box::new(RustlsTransport { buffers, stream });
+        #[allow(unused_mut)]
+        let mut transport = Box::new(RustlsTransport {
+            buffers,
+            stream,
+            handshake: HandshakeTimer::default(),
+        });
+
+        // The negotiated protocol is needed before the first request.
+        #[cfg(feature = "http2")]
+        if details.offers_http2() {
+            transport.complete_handshake(details.timeout)?;
+        }
 
         debug!("Wrapped TLS");
 
@@ -87,6 +96,26 @@ impl Connector for RustlsConnector {
     }
 }
 
+impl RustlsConnector {
+    fn config(&self, details: &ConnectionDetails) -> Result<Arc<ClientConfig>, Error> {
+        let tls_config = &details.config.tls_config;
+
+        #[cfg(feature = "http2")]
+        if details.offers_http2() {
+            return self.http2_configs.get_or_try_init(tls_config, |c| {
+                let mut config = (*build_config(c)).clone();
+                config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+                Ok(Arc::new(config))
+            });
+        }
+
+        // Reuse the config built for an identical TlsConfig, or build a new one.
+        // The TlsConfig can differ per request.
+        self.configs
+            .get_or_try_init(tls_config, |c| Ok(build_config(c)))
+    }
+}
+
 fn build_config(tls_config: &TlsConfig) -> Arc<ClientConfig> {
     // Improve chances of ureq working out-of-the-box by not requiring the user
     // to select a default crypto provider.
@@ -167,6 +196,42 @@ fn build_config(tls_config: &TlsConfig) -> Arc<ClientConfig> {
 struct RustlsTransport {
     buffers: LazyBuffers,
     stream: StreamOwned<ClientConnection, TransportAdapter>,
+    handshake: HandshakeTimer,
+}
+
+/// Measures the handshake, which rustls does as part of the first IO.
+#[derive(Default)]
+struct HandshakeTimer {
+    started: Option<Instant>,
+    duration: Option<Duration>,
+}
+
+impl HandshakeTimer {
+    fn before_io(&mut self, conn: &ClientConnection) {
+        if conn.is_handshaking() && self.started.is_none() {
+            self.started = Some(Instant::now());
+        }
+    }
+
+    fn after_io(&mut self, conn: &ClientConnection) {
+        if self.duration.is_none() && !conn.is_handshaking() {
+            self.duration = self.started.map(|s| s.elapsed());
+        }
+    }
+}
+
+impl RustlsTransport {
+    #[cfg(feature = "http2")]
+    fn complete_handshake(&mut self, timeout: NextTimeout) -> Result<(), Error> {
+        self.stream.get_mut().set_timeout(timeout);
+
+        self.handshake.before_io(&self.stream.conn);
+        let result = self.stream.conn.complete_io(&mut self.stream.sock);
+        self.handshake.after_io(&self.stream.conn);
+        result?;
+
+        Ok(())
+    }
 }
 
 impl Transport for RustlsTransport {
@@ -177,8 +242,11 @@ impl Transport for RustlsTransport {
     fn transmit_output(&mut self, amount: usize, timeout: NextTimeout) -> Result<(), Error> {
         self.stream.get_mut().set_timeout(timeout);
 
+        self.handshake.before_io(&self.stream.conn);
         let output = &self.buffers.output()[..amount];
-        self.stream.write_all(output)?;
+        let result = self.stream.write_all(output);
+        self.handshake.after_io(&self.stream.conn);
+        result?;
 
         Ok(())
     }
@@ -190,8 +258,11 @@ impl Transport for RustlsTransport {
 
         self.stream.get_mut().set_timeout(timeout);
 
+        self.handshake.before_io(&self.stream.conn);
         let input = self.buffers.input_append_buf();
-        let amount = self.stream.read(input)?;
+        let result = self.stream.read(input);
+        self.handshake.after_io(&self.stream.conn);
+        let amount = result?;
         self.buffers.input_appended(amount);
 
         Ok(amount > 0)
@@ -204,6 +275,15 @@ impl Transport for RustlsTransport {
     fn is_tls(&self) -> bool {
         true
     }
+
+    fn tls_handshake_duration(&self) -> Option<Duration> {
+        self.handshake.duration
+    }
+
+    #[cfg(feature = "http2")]
+    fn is_http2(&self) -> bool {
+        self.stream.conn.alpn_protocol() == Some(b"h2")
+    }
 }
 
 #[derive(Debug)]
@@ -50,6 +50,8 @@ pub use chain::ChainedConnector;
 mod test;
 #[cfg(feature = "_test")]
 pub use test::set_handler;
+#[cfg(all(test, feature = "_test"))]
+pub(crate) use test::set_handler_fn;
 
 #[cfg(feature = "socks-proxy")]
 mod socks;
@@ -145,6 +147,16 @@ impl<'a> ConnectionDetails<'a> {
 
         self.uri.scheme() == Some(&Scheme::HTTPS)
     }
+
+    /// Whether TLS should offer HTTP/2 using ALPN.
+    ///
+    /// Not through a CONNECT proxy, since the TLS must wait for the tunnel.
+    #[cfg(all(feature = "http2", feature = "_tls"))]
+    pub(crate) fn offers_http2(&self) -> bool {
+        self.config.http2
+            && self.config.connect_proxy_uri().is_none()
+            && self.uri.scheme() == Some(&Scheme::HTTPS)
+    }
 }
 
 /// Transport of HTTP/1.1 as created by a [`Connector`].
@@ -201,6 +213,23 @@ pub trait Transport: Debug + Send + Sync {
     fn is_tls(&self) -> bool {
         false
     }
+
+    /// Time spent on the TLS handshake, once it has completed.
+    ///
+    /// This is reported in [`HopTimings::tls_handshake()`](crate::HopTimings::tls_handshake).
+    ///
+    /// Defaults to `None`, override in TLS transports.
+    fn tls_handshake_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+
+    /// Whether the transport negotiated HTTP/2, which ureq then speaks on it.
+    ///
+    /// Defaults to `false`, override in TLS transports doing ALPN. Transports wrapping
+    /// another transport must forward this.
+    fn is_http2(&self) -> bool {
+        false
+    }
 }
 
 /// Default connector providing TCP sockets, TLS and SOCKS proxy.
@@ -236,7 +265,7 @@ impl Default for DefaultConnector {
             // When enabled, all tests are connected to a dummy server and will not
             // make requests to the internet.
             #[cfg(feature = "_test")]
-            test::TestConnector.boxed(),
+            test::TestConnector::default().boxed(),
             //
             // If we are using socks-proxy, that takes precedence over TcpConnector.
             #[cfg(feature = "socks-proxy")]
@@ -2,7 +2,7 @@
 
 use std::cell::RefCell;
 use std::io::Write;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Read};
 use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
 use std::sync::{Arc, Mutex};
 use std::{fmt, io, thread};
@@ -15,8 +15,18 @@ use crate::Error;
 
 use super::{Buffers, ConnectionDetails, Connector, LazyBuffers, NextTimeout, Transport};
 
-#[derive(Default)]
-pub(crate) struct TestConnector;
+pub(crate) struct TestConnector {
+    /// Handlers of the thread creating the connector, for connections made on other threads.
+    inherited: Vec<TestHandler>,
+}
+
+impl Default for TestConnector {
+    fn default() -> Self {
+        TestConnector {
+            inherited: HANDLERS.with(|h| (*h).borrow().clone()),
+        }
+    }
+}
 
 thread_local!(static HANDLERS: RefCell<Vec<TestHandler>> = const { RefCell::new(Vec::new()) });
 
@@ -41,6 +51,7 @@ impl Connector for TestConnector {
         let (tx2, rx2) = mpsc::sync_channel(10);
 
         let mut handlers = HANDLERS.with(|h| (*h).borrow().clone());
+        handlers.extend(self.inherited.iter().cloned());
         setup_default_handlers(&mut handlers);
 
         thread::spawn(|| test_run(uri, rx1, tx2, handlers));
@@ -59,7 +70,10 @@ impl Connector for TestConnector {
 impl TestHandler {
     fn new(
         pattern: &'static str,
-        handler: impl Fn(Uri, Request<()>, &mut dyn Write) -> io::Result<()> + Send + Sync + 'static,
+        handler: impl Fn(Uri, Request<Vec<u8>>, &mut dyn Write) -> io::Result<()>
+            + Send
+            + Sync
+            + 'static,
     ) -> Self {
         TestHandler {
             pattern,
@@ -68,6 +82,18 @@ impl TestHandler {
     }
 }
 
+/// Helper for tests where the response depends on the request.
+///
+/// The request holds the body the client sent, with any chunked encoding removed.
+#[cfg(test)]
+pub(crate) fn set_handler_fn(
+    pattern: &'static str,
+    handler: impl Fn(Uri, Request<Vec<u8>>, &mut dyn Write) -> io::Result<()> + Send + Sync + 'static,
+) {
+    let handler = TestHandler::new(pattern, handler);
+    HANDLERS.with(|h| (*h).borrow_mut().push(handler));
+}
+
 /// Helper for **_test** feature tests.
 pub fn set_handler(pattern: &'static str, status: u16, headers: &[(&str, &str)], body: &[u8]) {
     // Convert headers to a big string
@@ -96,7 +122,7 @@ pub fn set_handler(pattern: &'static str, status: u16, headers: &[(&str, &str)],
 #[derive(Clone)]
 struct TestHandler {
     pattern: &'static str,
-    handler: Arc<dyn Fn(Uri, Request<()>, &mut dyn Write) -> io::Result<()> + Sync + Send>,
+    handler: Arc<dyn Fn(Uri, Request<Vec<u8>>, &mut dyn Write) -> io::Result<()> + Sync + Send>,
 }
 
 fn test_run(
@@ -105,22 +131,35 @@ fn test_run(
     tx: mpsc::SyncSender<Vec<u8>>,
     handlers: Vec<TestHandler>,
 ) {
-    let mut reader = BufReader::new(RxRead(rx));
+    let mut reader = BufReader::new(RxRead(rx, vec![]));
     let mut writer = TxWrite(tx);
     let uri_s = uri.to_string();
 
+    // The request might arrive in several writes.
+    let mut input = vec![];
+
     let req = loop {
-        let input = reader.fill_buf().expect("test fill_buf");
         let maybe =
-            ureq_proto::parser::try_parse_request::<100>(input).expect("test parse request");
-        if let Some((amount, req)) = maybe {
-            reader.consume(amount);
+            ureq_proto::parser::try_parse_request::<100>(&input).expect("test parse request");
+        if let Some((used, req)) = maybe {
+            input.drain(..used);
             break req;
-        } else {
-            continue;
         }
+
+        let more = reader.fill_buf().expect("test fill_buf");
+        let amount = more.len();
+        if amount == 0 {
+            // Client went away before sending a full request.
+            return;
+        }
+        input.extend_from_slice(more);
+        reader.consume(amount);
     };
 
+    // Whatever was read past the request head is the start of the body.
+    let body = read_request_body(req.headers(), &mut (&input[..]).chain(&mut reader));
+    let req = req.map(|_| body);
+
     for handler in handlers {
         if uri_s.contains(handler.pattern) {
             (handler.handler)(uri, req, &mut writer).expect("test handler to not fail");
@@ -131,6 +170,59 @@ fn test_run(
     panic!("test server unhandled url: {}", uri);
 }
 
+/// Reads the request body, framed by content-length or chunked transfer encoding.
+///
+/// Stops early if the client goes away.
+fn read_request_body(headers: &http::HeaderMap, reader: &mut impl BufRead) -> Vec<u8> {
+    let mut body = vec![];
+
+    let chunked = headers
+        .get("transfer-encoding")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.eq_ignore_ascii_case("chunked"))
+        .unwrap_or(false);
+
+    if chunked {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                break;
+            }
+            let size = line.trim_end().split(';').next().unwrap_or("");
+            let Ok(size) = usize::from_str_radix(size, 16) else {
+                break;
+            };
+            if size == 0 {
+                // Skip trailers until the final empty line.
+                loop {
+                    line.clear();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line.trim_end().is_empty() {
+                        break;
+                    }
+                }
+                break;
+            }
+            let start = body.len();
+            body.resize(start + size, 0);
+            if reader.read_exact(&mut body[start..]).is_err() {
+                body.truncate(start);
+                break;
+            }
+            line.clear();
+            let _ = reader.read_line(&mut line);
+        }
+    } else if let Some(len) = headers
+        .get("content-length")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+    {
+        let _ = reader.take(len).read_to_end(&mut body);
+    }
+
+    body
+}
+
 fn setup_default_handlers(handlers: &mut Vec<TestHandler>) {
     fn maybe_add(handler: TestHandler, handlers: &mut Vec<TestHandler>) {
         let already_declared = handlers.iter().any(|h| h.pattern == handler.pattern);
@@ -396,17 +488,20 @@ const HTTPBIN_JSON: &str = r#"
   }
 }"#;
 
-struct RxRead(Receiver<Vec<u8>>);
+/// Reads what the client sent, keeping what doesn't fit in the buffer for the next read.
+struct RxRead(Receiver<Vec<u8>>, Vec<u8>);
 
 impl io::Read for RxRead {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let v = match self.0.recv() {
-            Ok(v) => v,
-            Err(_) => return Ok(0), // remote side is gone
-        };
-        assert!(buf.len() > v.len());
-        let max = buf.len().min(v.len());
-        buf[..max].copy_from_slice(&v[..]);
+        while self.1.is_empty() {
+            self.1 = match self.0.recv() {
+                Ok(v) => v,
+                Err(_) => return Ok(0), // remote side is gone
+            };
+        }
+        let max = buf.len().min(self.1.len());
+        buf[..max].copy_from_slice(&self.1[..max]);
+        self.1.drain(..max);
         Ok(max)
     }
 }
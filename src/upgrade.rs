@@ -0,0 +1,108 @@
+use std::fmt;
+use std::io;
+
+use crate::pool::Connection;
+use crate::transport::time::Duration;
+use crate::transport::NextTimeout;
+use crate::{Error, Timeout};
+
+/// The raw connection after a `101 Switching Protocols` response, or a proxy tunnel.
+///
+/// Obtained via [`Body::into_upgraded()`](crate::Body::into_upgraded). This is the same
+/// connection the request was sent on, including any TLS and proxy layers. Bytes the
+/// server sent directly after the response header are read first.
+///
+/// Also returned by [`Agent::tunnel()`](crate::Agent::tunnel), then connected to the
+/// target through the proxy.
+///
+/// No timeouts apply to reading or writing, since upgraded protocols are typically
+/// long lived.
+///
+/// ```no_run
+/// use std::io::{Read, Write};
+///
+/// let res = ureq::get("http://my-server.test/chat")
+///     .header("connection", "upgrade")
+///     .header("upgrade", "my-protocol")
+///     .call()?;
+///
+/// assert_eq!(res.status(), 101);
+///
+/// let mut stream = res.into_body().into_upgraded()?;
+///
+/// stream.write_all(b"hello")?;
+///
+/// let mut buf = [0; 1024];
+/// let n = stream.read(&mut buf)?;
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct Upgraded {
+    connection: Connection,
+}
+
+impl Upgraded {
+    pub(crate) fn new(connection: Connection) -> Self {
+        Upgraded { connection }
+    }
+}
+
+impl io::Read for Upgraded {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.connection.buffers().input().is_empty() {
+            let timeout = NextTimeout {
+                after: Duration::NotHappening,
+                reason: Timeout::RecvBody,
+            };
+
+            match self.connection.await_input(timeout) {
+                Ok(_) => {}
+                // Remote closed the connection.
+                Err(Error::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(0),
+                Err(e) => return Err(e.into_io()),
+            }
+        }
+
+        let input = self.connection.buffers().input();
+        let amount = input.len().min(buf.len());
+        buf[..amount].copy_from_slice(&input[..amount]);
+        self.connection.consume_input(amount);
+
+        Ok(amount)
+    }
+}
+
+impl io::Write for Upgraded {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let output = self.connection.buffers().output();
+        let amount = output.len().min(buf.len());
+        output[..amount].copy_from_slice(&buf[..amount]);
+
+        let timeout = NextTimeout {
+            after: Duration::NotHappening,
+            reason: Timeout::SendBody,
+        };
+
+        self.connection
+            .transmit_output(amount, timeout)
+            .map_err(Error::into_io)?;
+
+        Ok(amount)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // transmit_output() doesn't buffer.
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Upgraded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Upgraded")
+            .field("connection", &self.connection)
+            .finish()
+    }
+}
@@ -1,5 +1,7 @@
+use std::collections::hash_map::RandomState;
 use std::convert::TryFrom;
 use std::fmt;
+use std::hash::{BuildHasher, Hasher};
 use std::io::{self, ErrorKind};
 
 use http::header::{ACCEPT, ACCEPT_CHARSET, ACCEPT_ENCODING};
@@ -362,3 +364,45 @@ impl HeaderMapExt for HeaderMap {
         self.contains_key("content-type")
     }
 }
+
+/// A random number, without depending on a random number generator.
+///
+/// RandomState is seeded with random keys per thread, and every instance
+/// differs. SipHash keeps the output unpredictable.
+pub(crate) fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Position of `needle` in `haystack`.
+pub(crate) fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Trim optional whitespace around a header value.
+pub(crate) fn trim_ows(mut v: &[u8]) -> &[u8] {
+    while let [b' ' | b'\t', rest @ ..] = v {
+        v = rest;
+    }
+    while let [rest @ .., b' ' | b'\t'] = v {
+        v = rest;
+    }
+    v
+}
+
+/// Parse `name: value` lines, as found in trailers and multipart parts.
+///
+/// The block excludes the empty line ending it. Malformed lines are errors, for the
+/// caller to either reject or skip.
+pub(crate) fn parse_headers(
+    block: &[u8],
+) -> impl Iterator<Item = Result<(HeaderName, HeaderValue), &[u8]>> {
+    block.split(|c| *c == b'\n').map(|line| {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        let colon = line.iter().position(|c| *c == b':').ok_or(line)?;
+        let name = HeaderName::from_bytes(&line[..colon]).map_err(|_| line)?;
+        let value = HeaderValue::from_bytes(trim_ows(&line[colon + 1..])).map_err(|_| line)?;
+
+        Ok((name, value))
+    })
+}
@@ -0,0 +1,151 @@
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress};
+
+use crate::Error;
+
+/// Each compressed message ends with an empty stored block, which is not transmitted.
+const TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// The permessage-deflate extension (RFC 7692).
+pub(crate) struct Deflate {
+    compress: Compress,
+    decompress: Decompress,
+    client_no_context_takeover: bool,
+    server_no_context_takeover: bool,
+}
+
+impl Deflate {
+    /// Set up the extension from the parameters in the server response.
+    ///
+    /// Returns an error for parameters we did not offer.
+    pub(crate) fn negotiate(params: &[&str]) -> Result<Self, Error> {
+        let mut client_no_context_takeover = false;
+        let mut server_no_context_takeover = false;
+
+        for param in params {
+            let name = param.split('=').next().unwrap_or("").trim();
+
+            match name {
+                "client_no_context_takeover" => client_no_context_takeover = true,
+                "server_no_context_takeover" => server_no_context_takeover = true,
+                // A smaller window in the server compressor doesn't affect us.
+                "server_max_window_bits" => {}
+                _ => return Err(Error::WebSocket("unexpected permessage-deflate parameter")),
+            }
+        }
+
+        Ok(Deflate {
+            compress: Compress::new(Compression::default(), false),
+            decompress: Decompress::new(false),
+            client_no_context_takeover,
+            server_no_context_takeover,
+        })
+    }
+
+    pub(crate) fn compress(&mut self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::with_capacity(data.len() / 2 + 64);
+        let mut pos = 0;
+
+        loop {
+            if out.capacity() - out.len() < 64 {
+                out.reserve(data.len().max(1024));
+            }
+
+            let before = self.compress.total_in();
+            self.compress
+                .compress_vec(&data[pos..], &mut out, FlushCompress::Sync)
+                .map_err(|_| Error::WebSocket("permessage-deflate compression failed"))?;
+            pos += (self.compress.total_in() - before) as usize;
+
+            // The flush is done when there is output space left over.
+            if pos == data.len() && out.len() < out.capacity() {
+                break;
+            }
+        }
+
+        if out.ends_with(&TAIL) {
+            out.truncate(out.len() - TAIL.len());
+        }
+
+        if self.client_no_context_takeover {
+            self.compress.reset();
+        }
+
+        Ok(out)
+    }
+
+    pub(crate) fn decompress(&mut self, data: &[u8], max_size: usize) -> Result<Vec<u8>, Error> {
+        let mut input = Vec::with_capacity(data.len() + TAIL.len());
+        input.extend_from_slice(data);
+        input.extend_from_slice(&TAIL);
+
+        let mut out = Vec::with_capacity((data.len() * 2).min(max_size) + 64);
+        let mut pos = 0;
+
+        loop {
+            if out.capacity() - out.len() < 64 {
+                out.reserve(input.len().max(1024));
+            }
+
+            let before_in = self.decompress.total_in();
+            let before_out = self.decompress.total_out();
+            self.decompress
+                .decompress_vec(&input[pos..], &mut out, FlushDecompress::Sync)
+                .map_err(|_| Error::WebSocket("permessage-deflate decompression failed"))?;
+            pos += (self.decompress.total_in() - before_in) as usize;
+
+            if out.len() > max_size {
+                return Err(Error::WebSocket("message exceeds max size"));
+            }
+
+            let made_progress =
+                self.decompress.total_in() > before_in || self.decompress.total_out() > before_out;
+
+            if (pos == input.len() && out.len() < out.capacity()) || !made_progress {
+                break;
+            }
+        }
+
+        if self.server_no_context_takeover {
+            self.decompress.reset(false);
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roundtrip_with_context_takeover() {
+        let mut client = Deflate::negotiate(&[]).unwrap();
+        let mut server = Deflate::negotiate(&[]).unwrap();
+
+        for _ in 0..3 {
+            let data = "hello hello hello hello".repeat(100);
+            let compressed = client.compress(data.as_bytes()).unwrap();
+            assert!(compressed.len() < data.len());
+
+            // The server decompresses with the client's context.
+            let plain = server.decompress(&compressed, 1024 * 1024).unwrap();
+            assert_eq!(plain, data.as_bytes());
+        }
+    }
+
+    #[test]
+    fn decompress_max_size() {
+        let mut client = Deflate::negotiate(&[]).unwrap();
+        let mut server = Deflate::negotiate(&[]).unwrap();
+
+        let compressed = client.compress(&[0; 100_000]).unwrap();
+        let err = server.decompress(&compressed, 10_000).unwrap_err();
+        assert!(matches!(err, Error::WebSocket(_)));
+    }
+
+    #[test]
+    fn negotiate_unknown_param() {
+        assert!(Deflate::negotiate(&["client_max_window_bits=10"]).is_err());
+        assert!(Deflate::negotiate(&["server_no_context_takeover"]).is_ok());
+    }
+}
@@ -0,0 +1,846 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
+use http::uri::Scheme;
+use http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode, Uri};
+use sha1::{Digest, Sha1};
+
+use crate::http;
+use crate::upgrade::Upgraded;
+use crate::{Agent, Error, SendBody};
+
+use self::deflate::Deflate;
+
+mod deflate;
+
+/// Appended to the `Sec-WebSocket-Key` to form the `Sec-WebSocket-Accept`.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+/// Control frames can't be fragmented and are limited to 125 bytes.
+const MAX_CONTROL_PAYLOAD: usize = 125;
+
+/// Builder for a [`WebSocket`].
+///
+/// Created via [`Agent::websocket()`]. The handshake is a regular request made by
+/// the agent, which means the agent config, such as proxy, TLS and cookies, apply.
+pub struct WebSocketBuilder {
+    agent: Agent,
+    builder: http::request::Builder,
+    protocols: Vec<String>,
+    permessage_deflate: bool,
+    max_message_size: usize,
+    max_frame_size: usize,
+}
+
+impl WebSocketBuilder {
+    pub(crate) fn new<T>(agent: Agent, uri: T) -> Self
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        WebSocketBuilder {
+            agent,
+            builder: http::Request::get(uri),
+            protocols: vec![],
+            permessage_deflate: false,
+            max_message_size: 64 * 1024 * 1024,
+            max_frame_size: 16 * 1024 * 1024,
+        }
+    }
+
+    /// Appends a header to the handshake request.
+    pub fn header<K, V>(mut self, key: K, value: V) -> Self
+    where
+        HeaderName: TryFrom<K>,
+        <HeaderName as TryFrom<K>>::Error: Into<http::Error>,
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<http::Error>,
+    {
+        self.builder = self.builder.header(key, value);
+        self
+    }
+
+    /// Offer a subprotocol in the `Sec-WebSocket-Protocol` header.
+    ///
+    /// Can be called multiple times to offer several, in order of preference. The
+    /// one picked by the server is found in [`WebSocket::protocol()`].
+    pub fn protocol(mut self, protocol: impl Into<String>) -> Self {
+        self.protocols.push(protocol.into());
+        self
+    }
+
+    /// Offer the permessage-deflate extension (RFC 7692).
+    ///
+    /// Messages are compressed if the server accepts it.
+    ///
+    /// Defaults to `false`
+    pub fn permessage_deflate(mut self, enabled: bool) -> Self {
+        self.permessage_deflate = enabled;
+        self
+    }
+
+    /// Max size of a received message, after reassembly and decompression.
+    ///
+    /// Defaults to 64MB
+    pub fn max_message_size(mut self, size: usize) -> Self {
+        self.max_message_size = size;
+        self
+    }
+
+    /// Max size of sent frames. Larger messages are sent fragmented.
+    ///
+    /// Defaults to 16MB
+    pub fn max_frame_size(mut self, size: usize) -> Self {
+        self.max_frame_size = size.max(1);
+        self
+    }
+
+    /// Do the handshake and open the connection.
+    pub fn connect(self) -> Result<WebSocket, Error> {
+        let mut request = self.builder.body(())?;
+
+        // ws:// and wss:// are the same as http:// and https:// to the agent.
+        let scheme = match request.uri().scheme_str() {
+            Some("ws") => Some(Scheme::HTTP),
+            Some("wss") => Some(Scheme::HTTPS),
+            _ => None,
+        };
+        if let Some(scheme) = scheme {
+            let mut parts = request.uri().clone().into_parts();
+            parts.scheme = Some(scheme);
+            *request.uri_mut() = Uri::from_parts(parts).map_err(http::Error::from)?;
+        }
+
+        let mut nonce = [0; 16];
+        getrandom::getrandom(&mut nonce).map_err(io::Error::from)?;
+        let key = BASE64_STANDARD.encode(nonce);
+
+        let headers = request.headers_mut();
+        headers.insert(header::CONNECTION, HeaderValue::from_static("upgrade"));
+        headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+        headers.insert(
+            header::SEC_WEBSOCKET_VERSION,
+            HeaderValue::from_static("13"),
+        );
+        headers.insert(header::SEC_WEBSOCKET_KEY, to_header_value(&key)?);
+
+        if !self.protocols.is_empty() {
+            let value = to_header_value(&self.protocols.join(", "))?;
+            headers.insert(header::SEC_WEBSOCKET_PROTOCOL, value);
+        }
+
+        if self.permessage_deflate {
+            headers.insert(
+                header::SEC_WEBSOCKET_EXTENSIONS,
+                HeaderValue::from_static("permessage-deflate"),
+            );
+        }
+
+        // The upgrade needs HTTP/1.1.
+        #[cfg(feature = "http2")]
+        let request = self
+            .agent
+            .configure_request(request)
+            .http2(false)
+            .http2_prior_knowledge(false)
+            .build();
+
+        let response = self.agent.run_via_middleware(request, SendBody::none())?;
+
+        if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+            return Err(Error::WebSocket("server did not switch protocols"));
+        }
+
+        let headers = response.headers();
+
+        let is_websocket = headers
+            .get(header::UPGRADE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.eq_ignore_ascii_case("websocket"))
+            .unwrap_or(false);
+
+        if !is_websocket || !has_token(headers, header::CONNECTION, "upgrade") {
+            return Err(Error::WebSocket("missing upgrade headers in response"));
+        }
+
+        let accept = headers.get(header::SEC_WEBSOCKET_ACCEPT);
+        if accept.map(|v| v.as_bytes()) != Some(accept_key(&key).as_bytes()) {
+            return Err(Error::WebSocket("invalid sec-websocket-accept"));
+        }
+
+        let protocol = match headers.get(header::SEC_WEBSOCKET_PROTOCOL) {
+            Some(v) => {
+                let v = v.to_str().unwrap_or_default();
+                if !self.protocols.iter().any(|p| p == v) {
+                    return Err(Error::WebSocket("unexpected sec-websocket-protocol"));
+                }
+                Some(v.to_string())
+            }
+            None => None,
+        };
+
+        let mut deflate = None;
+
+        for value in headers.get_all(header::SEC_WEBSOCKET_EXTENSIONS) {
+            let value = value.to_str().unwrap_or_default();
+
+            for extension in value.split(',') {
+                let mut params = extension.split(';').map(str::trim);
+                let name = params.next().unwrap_or_default();
+
+                if name.is_empty() {
+                    continue;
+                }
+
+                // Only accept extensions we offered.
+                if name != "permessage-deflate" || !self.permessage_deflate || deflate.is_some() {
+                    return Err(Error::WebSocket("unexpected sec-websocket-extensions"));
+                }
+
+                let params: Vec<&str> = params.collect();
+                deflate = Some(Deflate::negotiate(&params)?);
+            }
+        }
+
+        let stream = response.into_body().into_upgraded()?;
+
+        debug!(
+            "WebSocket open, protocol: {:?}, deflate: {}",
+            protocol,
+            deflate.is_some()
+        );
+
+        Ok(WebSocket {
+            stream,
+            protocol,
+            deflate,
+            max_message_size: self.max_message_size,
+            max_frame_size: self.max_frame_size,
+            partial: None,
+            close_sent: false,
+            close_received: false,
+        })
+    }
+}
+
+fn to_header_value(v: &str) -> Result<HeaderValue, Error> {
+    HeaderValue::from_str(v).map_err(|e| http::Error::from(e).into())
+}
+
+fn has_token(headers: &HeaderMap, name: HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|t| t.trim().eq_ignore_ascii_case(token))
+}
+
+fn accept_key(key: &str) -> String {
+    let hash = Sha1::new().chain_update(key).chain_update(GUID).finalize();
+    BASE64_STANDARD.encode(hash)
+}
+
+/// A message sent or received on a [`WebSocket`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// A UTF-8 text message.
+    Text(String),
+
+    /// A binary message.
+    Binary(Vec<u8>),
+
+    /// A ping.
+    ///
+    /// Received pings are answered automatically.
+    Ping(Vec<u8>),
+
+    /// A pong, the answer to a ping.
+    Pong(Vec<u8>),
+
+    /// Start or answer the closing handshake.
+    Close(Option<CloseFrame>),
+}
+
+/// Status code and reason of a close message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    /// Status code, such as 1000 for a normal closure.
+    pub code: u16,
+
+    /// Reason for closing. Max 123 bytes.
+    pub reason: String,
+}
+
+impl CloseFrame {
+    fn parse(payload: &[u8]) -> Result<Option<CloseFrame>, Error> {
+        if payload.is_empty() {
+            return Ok(None);
+        }
+
+        if payload.len() < 2 {
+            return Err(Error::WebSocket("invalid close frame"));
+        }
+
+        let code = u16::from_be_bytes([payload[0], payload[1]]);
+
+        // 1004-1006 and 1015 are reserved, and must not be sent.
+        let valid = matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999);
+
+        let Ok(reason) = String::from_utf8(payload[2..].to_vec()) else {
+            return Err(Error::WebSocket("invalid close frame"));
+        };
+
+        if !valid {
+            return Err(Error::WebSocket("invalid close code"));
+        }
+
+        Ok(Some(CloseFrame { code, reason }))
+    }
+
+    fn to_payload(&self) -> Vec<u8> {
+        let mut payload = self.code.to_be_bytes().to_vec();
+        payload.extend_from_slice(self.reason.as_bytes());
+        payload
+    }
+}
+
+/// A blocking WebSocket (RFC 6455).
+///
+/// Created via [`Agent::websocket()`].
+///
+/// ```no_run
+/// use ureq::{Agent, Message};
+///
+/// let agent = Agent::new_with_defaults();
+///
+/// let mut ws = agent.websocket("wss://echo.websocket.org/")
+///     .permessage_deflate(true)
+///     .connect()?;
+///
+/// ws.send(Message::Text("hello".into()))?;
+///
+/// match ws.read()? {
+///     Message::Text(v) => println!("{}", v),
+///     _ => {}
+/// }
+///
+/// ws.close(1000, "bye")?;
+/// # Ok::<_, ureq::Error>(())
+/// ```
+pub struct WebSocket {
+    stream: Upgraded,
+    protocol: Option<String>,
+    deflate: Option<Deflate>,
+    max_message_size: usize,
+    max_frame_size: usize,
+    partial: Option<Partial>,
+    close_sent: bool,
+    close_received: bool,
+}
+
+struct Frame {
+    fin: bool,
+    rsv1: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// A fragmented message being received.
+struct Partial {
+    opcode: u8,
+    compressed: bool,
+    data: Vec<u8>,
+}
+
+impl WebSocket {
+    /// The subprotocol picked by the server.
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// Send a message.
+    ///
+    /// Sending [`Message::Close`] starts the closing handshake, after which nothing
+    /// more can be sent. See also [`WebSocket::close()`].
+    pub fn send(&mut self, message: Message) -> Result<(), Error> {
+        if self.close_sent {
+            return Err(Error::WebSocket("connection is closed"));
+        }
+
+        match message {
+            Message::Text(v) => self.send_data(OP_TEXT, v.as_bytes()),
+            Message::Binary(v) => self.send_data(OP_BINARY, &v),
+            Message::Ping(v) => self.send_control(OP_PING, &v),
+            Message::Pong(v) => self.send_control(OP_PONG, &v),
+            Message::Close(v) => {
+                let payload = v.map(|f| f.to_payload()).unwrap_or_default();
+                self.send_control(OP_CLOSE, &payload)?;
+                self.close_sent = true;
+                Ok(())
+            }
+        }
+    }
+
+    /// Read the next message.
+    ///
+    /// Blocks until a message arrives. Fragmented messages are returned once complete.
+    /// A received [`Message::Close`] is answered, unless we started the closing
+    /// handshake, after which reading fails.
+    pub fn read(&mut self) -> Result<Message, Error> {
+        if self.close_received {
+            return Err(Error::WebSocket("connection is closed"));
+        }
+
+        loop {
+            let frame = self.read_frame()?;
+
+            match frame.opcode {
+                OP_TEXT | OP_BINARY => {
+                    if self.partial.is_some() {
+                        return Err(Error::WebSocket("expected continuation frame"));
+                    }
+                    if frame.rsv1 && self.deflate.is_none() {
+                        return Err(Error::WebSocket("reserved bits set"));
+                    }
+
+                    let partial = Partial {
+                        opcode: frame.opcode,
+                        compressed: frame.rsv1,
+                        data: frame.payload,
+                    };
+
+                    if frame.fin {
+                        return self.finish(partial);
+                    }
+                    self.partial = Some(partial);
+                }
+                OP_CONTINUATION => {
+                    if frame.rsv1 {
+                        return Err(Error::WebSocket("reserved bits set"));
+                    }
+                    let Some(mut partial) = self.partial.take() else {
+                        return Err(Error::WebSocket("unexpected continuation frame"));
+                    };
+
+                    if partial.data.len() + frame.payload.len() > self.max_message_size {
+                        return Err(Error::WebSocket("message exceeds max size"));
+                    }
+                    partial.data.extend_from_slice(&frame.payload);
+
+                    if frame.fin {
+                        return self.finish(partial);
+                    }
+                    self.partial = Some(partial);
+                }
+                OP_CLOSE | OP_PING | OP_PONG => {
+                    if !frame.fin || frame.rsv1 || frame.payload.len() > MAX_CONTROL_PAYLOAD {
+                        return Err(Error::WebSocket("invalid control frame"));
+                    }
+                    return self.control(frame.opcode, frame.payload);
+                }
+                _ => return Err(Error::WebSocket("unknown opcode")),
+            }
+        }
+    }
+
+    /// Close the connection.
+    ///
+    /// Sends a close message, unless already sent, and reads until the server answers.
+    /// Any messages received meanwhile are discarded.
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<(), Error> {
+        if !self.close_sent {
+            let frame = CloseFrame {
+                code,
+                reason: reason.to_string(),
+            };
+            self.send(Message::Close(Some(frame)))?;
+        }
+
+        while !self.close_received {
+            self.read()?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, partial: Partial) -> Result<Message, Error> {
+        let data = match (&mut self.deflate, partial.compressed) {
+            (Some(deflate), true) => deflate.decompress(&partial.data, self.max_message_size)?,
+            _ => partial.data,
+        };
+
+        if partial.opcode == OP_TEXT {
+            String::from_utf8(data)
+                .map(Message::Text)
+                .map_err(|_| Error::WebSocket("invalid utf-8 in text message"))
+        } else {
+            Ok(Message::Binary(data))
+        }
+    }
+
+    fn control(&mut self, opcode: u8, payload: Vec<u8>) -> Result<Message, Error> {
+        match opcode {
+            OP_PING => {
+                if !self.close_sent {
+                    self.write_frame(true, false, OP_PONG, &payload)?;
+                }
+                Ok(Message::Ping(payload))
+            }
+            OP_PONG => Ok(Message::Pong(payload)),
+            _ => {
+                let frame = CloseFrame::parse(&payload)?;
+                self.close_received = true;
+
+                if !self.close_sent {
+                    // Echo the status code to complete the closing handshake.
+                    let reply = frame
+                        .as_ref()
+                        .map(|f| f.code.to_be_bytes().to_vec())
+                        .unwrap_or_default();
+                    self.write_frame(true, false, OP_CLOSE, &reply)?;
+                    self.close_sent = true;
+                }
+
+                Ok(Message::Close(frame))
+            }
+        }
+    }
+
+    fn send_data(&mut self, opcode: u8, data: &[u8]) -> Result<(), Error> {
+        let (data, compressed) = match &mut self.deflate {
+            Some(deflate) => (Cow::Owned(deflate.compress(data)?), true),
+            None => (Cow::Borrowed(data), false),
+        };
+
+        if data.is_empty() {
+            return self.write_frame(true, compressed, opcode, &[]);
+        }
+
+        let count = (data.len() + self.max_frame_size - 1) / self.max_frame_size;
+
+        for (i, chunk) in data.chunks(self.max_frame_size).enumerate() {
+            let first = i == 0;
+            let fin = i == count - 1;
+
+            // Only the first frame has the opcode and the compressed bit.
+            let (opcode, rsv1) = if first {
+                (opcode, compressed)
+            } else {
+                (OP_CONTINUATION, false)
+            };
+
+            self.write_frame(fin, rsv1, opcode, chunk)?;
+        }
+
+        Ok(())
+    }
+
+    fn send_control(&mut self, opcode: u8, payload: &[u8]) -> Result<(), Error> {
+        if payload.len() > MAX_CONTROL_PAYLOAD {
+            return Err(Error::WebSocket("control frame payload too large"));
+        }
+        self.write_frame(true, false, opcode, payload)
+    }
+
+    fn write_frame(
+        &mut self,
+        fin: bool,
+        rsv1: bool,
+        opcode: u8,
+        payload: &[u8],
+    ) -> Result<(), Error> {
+        let mut frame = Vec::with_capacity(payload.len() + 14);
+
+        frame.push((fin as u8) << 7 | (rsv1 as u8) << 6 | opcode);
+
+        // Frames from the client are always masked.
+        let len = payload.len();
+        if len < 126 {
+            frame.push(0x80 | len as u8);
+        } else if len <= u16::MAX as usize {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            frame.push(0x80 | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+
+        let mut mask = [0; 4];
+        getrandom::getrandom(&mut mask).map_err(io::Error::from)?;
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+
+        self.stream.write_all(&frame)?;
+
+        Ok(())
+    }
+
+    fn read_frame(&mut self) -> Result<Frame, Error> {
+        let mut head = [0; 2];
+        self.stream.read_exact(&mut head)?;
+
+        if head[0] & 0x30 != 0 {
+            return Err(Error::WebSocket("reserved bits set"));
+        }
+
+        // Frames from the server are never masked.
+        if head[1] & 0x80 != 0 {
+            return Err(Error::WebSocket("masked frame from server"));
+        }
+
+        let len = match head[1] & 0x7f {
+            126 => {
+                let mut buf = [0; 2];
+                self.stream.read_exact(&mut buf)?;
+                u16::from_be_bytes(buf) as u64
+            }
+            127 => {
+                let mut buf = [0; 8];
+                self.stream.read_exact(&mut buf)?;
+                u64::from_be_bytes(buf)
+            }
+            n => n as u64,
+        };
+
+        if len > self.max_message_size as u64 {
+            return Err(Error::WebSocket("message exceeds max size"));
+        }
+
+        let mut payload = vec![0; len as usize];
+        self.stream.read_exact(&mut payload)?;
+
+        Ok(Frame {
+            fin: head[0] & 0x80 != 0,
+            rsv1: head[0] & 0x40 != 0,
+            opcode: head[0] & 0x0f,
+            payload,
+        })
+    }
+}
+
+impl fmt::Debug for WebSocketBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocketBuilder")
+            .field("protocols", &self.protocols)
+            .field("permessage_deflate", &self.permessage_deflate)
+            .field("max_message_size", &self.max_message_size)
+            .field("max_frame_size", &self.max_frame_size)
+            .finish()
+    }
+}
+
+impl fmt::Debug for WebSocket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocket")
+            .field("stream", &self.stream)
+            .field("protocol", &self.protocol)
+            .field("deflate", &self.deflate.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpStream;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::test::local;
+
+    /// Echo server answering every frame with the same frame, unmasked.
+    ///
+    /// Since frames are echoed as is, fragmentation and compression come back unchanged.
+    fn echo_server(extensions: Option<&'static str>) -> u16 {
+        let (port, _) = local::server(move |stream| echo(stream, extensions));
+        port
+    }
+
+    fn echo(stream: TcpStream, extensions: Option<&str>) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+
+        let mut key = String::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("sec-websocket-key") {
+                    key = value.trim().to_string();
+                }
+            }
+        }
+
+        write!(
+            writer,
+            "HTTP/1.1 101 Switching Protocols\r\n\
+                upgrade: websocket\r\n\
+                connection: Upgrade\r\n\
+                sec-websocket-accept: {}\r\n",
+            accept_key(&key)
+        )
+        .unwrap();
+        if let Some(v) = extensions {
+            write!(writer, "sec-websocket-extensions: {}\r\n", v).unwrap();
+        }
+        writer.write_all(b"\r\n").unwrap();
+
+        loop {
+            let mut head = [0; 2];
+            if reader.read_exact(&mut head).is_err() {
+                return;
+            }
+            assert!(head[1] & 0x80 != 0, "client frames must be masked");
+
+            let len = match head[1] & 0x7f {
+                126 => {
+                    let mut buf = [0; 2];
+                    reader.read_exact(&mut buf).unwrap();
+                    u16::from_be_bytes(buf) as usize
+                }
+                127 => {
+                    let mut buf = [0; 8];
+                    reader.read_exact(&mut buf).unwrap();
+                    u64::from_be_bytes(buf) as usize
+                }
+                n => n as usize,
+            };
+
+            let mut mask = [0; 4];
+            reader.read_exact(&mut mask).unwrap();
+            let mut payload = vec![0; len];
+            reader.read_exact(&mut payload).unwrap();
+            for (i, b) in payload.iter_mut().enumerate() {
+                *b ^= mask[i % 4];
+            }
+
+            let opcode = head[0] & 0x0f;
+            let reply = if opcode == OP_PING { OP_PONG } else { opcode };
+
+            let mut frame = vec![(head[0] & 0xf0) | reply];
+            if len < 126 {
+                frame.push(len as u8);
+            } else if len <= u16::MAX as usize {
+                frame.push(126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            } else {
+                frame.push(127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+            frame.extend_from_slice(&payload);
+            writer.write_all(&frame).unwrap();
+
+            if opcode == OP_CLOSE {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn accept_key_rfc_example() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn echo_messages() {
+        let port = echo_server(None);
+        let mut ws = local::agent(Config::default())
+            .websocket(format!("ws://127.0.0.1:{}/echo", port))
+            .connect()
+            .unwrap();
+
+        ws.send(Message::Text("hello".into())).unwrap();
+        assert_eq!(ws.read().unwrap(), Message::Text("hello".into()));
+
+        let big = vec![42; 100_000];
+        ws.send(Message::Binary(big.clone())).unwrap();
+        assert_eq!(ws.read().unwrap(), Message::Binary(big));
+
+        ws.send(Message::Ping(b"ping".to_vec())).unwrap();
+        assert_eq!(ws.read().unwrap(), Message::Pong(b"ping".to_vec()));
+
+        ws.close(1000, "bye").unwrap();
+        assert!(ws.send(Message::Text("more".into())).is_err());
+        assert!(ws.read().is_err());
+    }
+
+    #[test]
+    fn echo_fragmented() {
+        let port = echo_server(None);
+        let mut ws = local::agent(Config::default())
+            .websocket(format!("ws://127.0.0.1:{}/echo", port))
+            .max_frame_size(3)
+            .connect()
+            .unwrap();
+
+        ws.send(Message::Text("hello fragmented world".into()))
+            .unwrap();
+        assert_eq!(
+            ws.read().unwrap(),
+            Message::Text("hello fragmented world".into())
+        );
+    }
+
+    #[test]
+    fn echo_permessage_deflate() {
+        let port = echo_server(Some("permessage-deflate"));
+        let mut ws = local::agent(Config::default())
+            .websocket(format!("ws://127.0.0.1:{}/echo", port))
+            .permessage_deflate(true)
+            .max_frame_size(10)
+            .connect()
+            .unwrap();
+
+        for _ in 0..3 {
+            let text = "compress me ".repeat(50);
+            ws.send(Message::Text(text.clone())).unwrap();
+            assert_eq!(ws.read().unwrap(), Message::Text(text));
+        }
+    }
+
+    #[test]
+    fn unoffered_extension() {
+        let port = echo_server(Some("permessage-deflate"));
+        let err = local::agent(Config::default())
+            .websocket(format!("ws://127.0.0.1:{}/echo", port))
+            .connect()
+            .unwrap_err();
+        assert!(matches!(err, Error::WebSocket(_)));
+    }
+
+    #[test]
+    fn parse_close_frame() {
+        assert_eq!(CloseFrame::parse(&[]).unwrap(), None);
+        assert!(CloseFrame::parse(&[3]).is_err());
+        assert!(CloseFrame::parse(&1005_u16.to_be_bytes()).is_err());
+
+        let mut payload = 1001_u16.to_be_bytes().to_vec();
+        payload.extend_from_slice(b"away");
+        assert_eq!(
+            CloseFrame::parse(&payload).unwrap(),
+            Some(CloseFrame {
+                code: 1001,
+                reason: "away".into()
+            })
+        );
+    }
+}